@@ -1,6 +1,6 @@
 mod other;
 
-env_inventory::register!("HELLO");
+env_inventory::register!(HELLO);
 
 fn main() {
     println!("ENV VARS\n{:?}", env_inventory::list_all_vars());
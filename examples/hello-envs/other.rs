@@ -1,2 +1,2 @@
 use env_inventory::register;
-register!("WORLD");
+register!(WORLD);
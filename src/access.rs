@@ -0,0 +1,91 @@
+//! Counting of variable reads.
+//!
+//! Every call to [`RequiredVar::get`](crate::RequiredVar::get), or to a
+//! getter such as [`get_list`](crate::get_list) or
+//! [`get_enum`](crate::get_enum), bumps a per-variable atomic counter, so
+//! teams can find hot config reads worth caching and cold ones worth
+//! removing.
+
+use crate::{resolve_one, RequiredVar, VarSource};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+static COUNTS: RwLock<Option<HashMap<&'static str, AtomicU64>>> = RwLock::new(None);
+
+/// Records one read of `name`.
+pub(crate) fn record(name: &'static str) {
+    {
+        let counts = COUNTS.read().unwrap_or_else(|e| e.into_inner());
+        if let Some(count) = counts.as_ref().and_then(|counts| counts.get(name)) {
+            count.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    }
+
+    // First read of this variable: take the write lock to add its counter
+    let mut counts = COUNTS.write().unwrap_or_else(|e| e.into_inner());
+    counts
+        .get_or_insert_with(HashMap::new)
+        .entry(name)
+        .or_insert_with(|| AtomicU64::new(0))
+        .fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records one read of `name` if it is registered.
+pub(crate) fn record_registered(name: &str) {
+    if let Some(var) = inventory::iter::<RequiredVar>().find(|var| var.name == name) {
+        record(var.name);
+    }
+}
+
+/// Resolves `name` as [`resolve_one`] does, recording the read; the getters
+/// read their values through this.
+pub(crate) fn read(name: &str) -> Option<(String, VarSource)> {
+    record_registered(name);
+    resolve_one(name)
+}
+
+/// Returns how many times each variable has been read through
+/// [`RequiredVar::get`] or a getter.
+///
+/// Variables that have never been read are absent from the map.
+pub fn access_counts() -> HashMap<String, u64> {
+    let counts = COUNTS.read().unwrap_or_else(|e| e.into_inner());
+    counts
+        .iter()
+        .flatten()
+        .map(|(name, count)| (name.to_string(), count.load(Ordering::Relaxed)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequiredVar;
+
+    crate::register!(COUNTED_VAR = "value");
+
+    #[test]
+    fn test_access_counts() {
+        let var = inventory::iter::<RequiredVar>()
+            .find(|var| var.name() == "COUNTED_VAR")
+            .unwrap();
+        assert_eq!(access_counts().get("COUNTED_VAR"), None);
+
+        for _ in 0..3 {
+            var.get();
+        }
+        assert_eq!(access_counts().get("COUNTED_VAR"), Some(&3));
+
+        // Bulk resolution isn't counted as reads
+        crate::map();
+        assert_eq!(access_counts().get("COUNTED_VAR"), Some(&3));
+
+        // The getters are
+        crate::get_list("COUNTED_VAR");
+        crate::get_enum::<String>("COUNTED_VAR").unwrap();
+        crate::get_first_set(&["COUNTED_VAR"]).unwrap();
+        assert_eq!(access_counts().get("COUNTED_VAR"), Some(&6));
+    }
+}
@@ -0,0 +1,659 @@
+//! Audits of the registry itself, catching developer mistakes in the
+//! registrations before they turn into confusing runtime behavior.
+
+use crate::{EnvInventoryError, ExpectedType, RequiredVar};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// Audits every registered variable for contradictory constraints.
+///
+/// A variable registered with the `required` option must be provided
+/// explicitly, so a default on it can never take effect, nor can the
+/// `optional` option; such a registration is reported as contradictory, as
+/// is a default outside the values allowed by the `allowed` option.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, validate_registry};
+/// register!(API_TOKEN; required);
+/// register!(LOG_LEVEL = "info");
+///
+/// validate_registry().unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ContradictoryConstraints`: For the first contradictory registration,
+///   by variable name.
+pub fn validate_registry() -> Result<(), EnvInventoryError> {
+    audit_vars(inventory::iter::<RequiredVar>())
+}
+
+pub(crate) fn audit_vars<'a, I>(vars: I) -> Result<(), EnvInventoryError>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let mut vars: Vec<&RequiredVar> = vars.into_iter().collect();
+    vars.sort_by_key(|var| var.name);
+
+    for var in vars {
+        if var.required && (var.default.is_some() || var.condition.is_some()) {
+            return Err(EnvInventoryError::ContradictoryConstraints {
+                name: var.name.to_string(),
+                reason: format!(
+                    "registered as required in {}, but also given a default that can never apply",
+                    var.source
+                ),
+            });
+        }
+        if var.required && var.optional {
+            return Err(EnvInventoryError::ContradictoryConstraints {
+                name: var.name.to_string(),
+                reason: format!("registered as both required and optional in {}", var.source),
+            });
+        }
+        if let Some(default) = var.default {
+            if !var.allowed.is_empty() && !var.allowed.contains(&default) {
+                return Err(EnvInventoryError::ContradictoryConstraints {
+                    name: var.name.to_string(),
+                    reason: format!(
+                        "registered in {} with the default {:?}, which isn't one of its allowed values {:?}",
+                        var.source, default, var.allowed
+                    ),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that at least `min` distinct variables are registered.
+///
+/// Registrations are collected at link time, and a build that drops them,
+/// for instance through `--gc-sections` or a library linked without any of
+/// its symbols used, silently ends up with an empty registry and nothing to
+/// validate. Call this at startup with the number of variables the binary
+/// is known to register.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{assert_registry_populated, register};
+/// register!(LISTEN_ADDR = "0.0.0.0:8080");
+///
+/// assert_registry_populated(1).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `EmptyRegistry`: If fewer than `min` variables are registered.
+pub fn assert_registry_populated(min: usize) -> Result<(), EnvInventoryError> {
+    check_populated(inventory::iter::<RequiredVar>(), min)
+}
+
+pub(crate) fn check_populated<'a, I>(vars: I, min: usize) -> Result<(), EnvInventoryError>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let found = vars
+        .into_iter()
+        .map(|var| var.name)
+        .collect::<BTreeSet<_>>()
+        .len();
+    if found < min {
+        return Err(EnvInventoryError::EmptyRegistry { found, min });
+    }
+    Ok(())
+}
+
+/// Checks that the registered variables are exactly those listed in the
+/// schema file at `schema`, keeping the code in sync with an external
+/// contract.
+///
+/// The schema lists one variable per line, alone or as `NAME=value` with the
+/// value ignored, so an `.env.example` such as the one written by
+/// [`export_env_template`](crate::export_env_template) can serve as the
+/// schema. Blank lines and comments are skipped, as is an `export ` prefix,
+/// except for comments of the form `# NAME=`, which is how the template
+/// lists the variables without a default.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, validate_completeness};
+/// # use std::io::Write;
+/// register!(LISTEN_ADDR = "0.0.0.0:8080");
+///
+/// let mut schema = tempfile::NamedTempFile::new().unwrap();
+/// writeln!(schema, "# Address to listen on\nLISTEN_ADDR=0.0.0.0:8080").unwrap();
+/// validate_completeness(schema.path()).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ReadFileError`: If the schema can't be read.
+/// * `IncompleteRegistry`: If a variable in the schema isn't registered, or
+///   a registered variable isn't in the schema.
+pub fn validate_completeness(schema: &Path) -> Result<(), EnvInventoryError> {
+    let content = fs::read_to_string(schema)
+        .map_err(|_| EnvInventoryError::ReadFileError(schema.display().to_string()))?;
+    check_completeness(inventory::iter::<RequiredVar>(), &content)
+}
+
+pub(crate) fn check_completeness<'a, I>(vars: I, schema: &str) -> Result<(), EnvInventoryError>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let registered: BTreeSet<&str> = vars.into_iter().map(|var| var.name).collect();
+    let listed: BTreeSet<&str> = schema.lines().filter_map(listed_name).collect();
+
+    let unregistered: Vec<String> = listed
+        .difference(&registered)
+        .map(|name| name.to_string())
+        .collect();
+    let undeclared: Vec<String> = registered
+        .difference(&listed)
+        .map(|name| name.to_string())
+        .collect();
+    if unregistered.is_empty() && undeclared.is_empty() {
+        Ok(())
+    } else {
+        Err(EnvInventoryError::IncompleteRegistry {
+            unregistered,
+            undeclared,
+        })
+    }
+}
+
+/// Gets the name of the variable listed on `line` of a schema, if any.
+fn listed_name(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() {
+        return None;
+    }
+    if let Some(comment) = line.strip_prefix('#') {
+        let name = comment.trim().strip_suffix('=')?;
+        let is_name =
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        return if is_name { Some(name) } else { None };
+    }
+    let line = line.strip_prefix("export ").unwrap_or(line);
+    Some(line.split('=').next().unwrap_or(line).trim())
+}
+
+/// Reports pairs of distinct registered names within `threshold` edits of
+/// each other, which are usually typos of the same variable.
+///
+/// Distance is the Levenshtein edit distance. Each pair is ordered by name,
+/// and the pairs are sorted.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{find_similar_names, register};
+/// register!(DATABASE_URL = "postgres://localhost/app");
+/// register!(DATABSE_URL = "postgres://localhost/app");
+///
+/// assert_eq!(
+///     find_similar_names(1),
+///     vec![("DATABASE_URL".to_string(), "DATABSE_URL".to_string())]
+/// );
+/// ```
+pub fn find_similar_names(threshold: usize) -> Vec<(String, String)> {
+    similar_names(inventory::iter::<RequiredVar>(), threshold)
+}
+
+pub(crate) fn similar_names<'a, I>(vars: I, threshold: usize) -> Vec<(String, String)>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let names: Vec<&str> = vars
+        .into_iter()
+        .map(|var| var.name)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let mut pairs = Vec::new();
+    for (index, first) in names.iter().enumerate() {
+        for second in &names[index + 1..] {
+            if is_similar(first, second, threshold) {
+                pairs.push((first.to_string(), second.to_string()));
+            }
+        }
+    }
+    pairs
+}
+
+/// Checks if `a` and `b` are at most `threshold` single-character edits
+/// apart.
+pub(crate) fn is_similar(a: &str, b: &str, threshold: usize) -> bool {
+    a.len().abs_diff(b.len()) <= threshold && edit_distance(a, b) <= threshold
+}
+
+/// Groups the registered names that differ only by case, such as `PATH` and
+/// `Path`.
+///
+/// Such names are distinct variables on Unix, but the same one on Windows,
+/// where environment variables are case-insensitive. Each group is sorted,
+/// as are the groups.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{find_case_collisions, register};
+/// register!(DATABASE_URL = "postgres://localhost/app");
+/// register!(Database_Url = "postgres://localhost/app");
+///
+/// assert_eq!(
+///     find_case_collisions(),
+///     vec![vec!["DATABASE_URL".to_string(), "Database_Url".to_string()]]
+/// );
+/// ```
+pub fn find_case_collisions() -> Vec<Vec<String>> {
+    case_collisions(inventory::iter::<RequiredVar>())
+}
+
+pub(crate) fn case_collisions<'a, I>(vars: I) -> Vec<Vec<String>>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let mut groups: BTreeMap<String, BTreeSet<&str>> = BTreeMap::new();
+    for var in vars {
+        groups
+            .entry(var.name.to_ascii_uppercase())
+            .or_default()
+            .insert(var.name);
+    }
+
+    let mut collisions: Vec<Vec<String>> = groups
+        .into_values()
+        .filter(|names| names.len() > 1)
+        .map(|names| names.into_iter().map(str::to_string).collect())
+        .collect();
+    collisions.sort();
+    collisions
+}
+
+/// A variable registered more than once with differing defaults or types.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Conflict {
+    /// The name of the variable.
+    pub name: String,
+    /// Every registration of the variable, sorted by source.
+    pub registrations: Vec<Registration>,
+}
+
+/// One registration of a variable reported in a [`Conflict`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Registration {
+    /// Where the variable was registered.
+    pub source: String,
+    /// The registered default, if any.
+    pub default: Option<String>,
+    /// The registered type, if any.
+    pub expected_type: Option<ExpectedType>,
+    /// The Rust integer type, such as `u16`, whose range the value must fit,
+    /// if registered with one.
+    pub type_name: Option<String>,
+}
+
+/// Reports the variables registered more than once with differing defaults
+/// or types, including integer types of different ranges such as `u8` and
+/// `u16`.
+///
+/// Which default applies to such a variable depends on priorities and link
+/// order, so the value can come as a surprise in a large dependency tree.
+/// Identical duplicate registrations aren't conflicts. The conflicts are
+/// sorted by name.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{conflicts, register};
+/// register!(CACHE_SIZE = "1024");
+/// register!(CACHE_SIZE = "2048");
+/// register!(CACHE_TTL = "60");
+/// register!(CACHE_TTL = "60");
+///
+/// let conflicts = conflicts();
+/// assert_eq!(conflicts.len(), 1);
+/// assert_eq!(conflicts[0].name, "CACHE_SIZE");
+/// assert_eq!(conflicts[0].registrations.len(), 2);
+/// ```
+pub fn conflicts() -> Vec<Conflict> {
+    registration_conflicts(inventory::iter::<RequiredVar>())
+}
+
+pub(crate) fn registration_conflicts<'a, I>(vars: I) -> Vec<Conflict>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let mut by_name: BTreeMap<&str, Vec<&RequiredVar>> = BTreeMap::new();
+    for var in vars {
+        by_name.entry(var.name).or_default().push(var);
+    }
+
+    by_name
+        .into_iter()
+        .filter(|(_, vars)| {
+            vars.iter()
+                .map(|var| (var.default, var.expected_type, var.int_range))
+                .collect::<BTreeSet<_>>()
+                .len()
+                > 1
+        })
+        .map(|(name, vars)| {
+            let mut registrations: Vec<Registration> = vars
+                .into_iter()
+                .map(|var| Registration {
+                    source: var.source.to_string(),
+                    default: var.default.map(str::to_string),
+                    expected_type: var.expected_type,
+                    type_name: var.int_range.map(|range| range.type_name.to_string()),
+                })
+                .collect();
+            registrations.sort_by(|a, b| a.source.cmp(&b.source));
+            Conflict {
+                name: name.to_string(),
+                registrations,
+            }
+        })
+        .collect()
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, ca) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_with_default() {
+        let vars = [
+            RequiredVar::new("AUDIT_PLAIN_VAR").with_default("plain"),
+            RequiredVar::new("AUDIT_CONTRADICTORY_VAR")
+                .with_default("unused")
+                .with_required(),
+        ];
+
+        match audit_vars(&vars) {
+            Err(EnvInventoryError::ContradictoryConstraints { name, reason }) => {
+                assert_eq!(name, "AUDIT_CONTRADICTORY_VAR");
+                assert!(reason.contains("required"));
+            }
+            other => panic!("expected a contradiction, got {:?}", other),
+        }
+        assert!(audit_vars(&vars[..1]).is_ok());
+    }
+
+    #[test]
+    fn test_required_and_optional() {
+        let vars = [RequiredVar::new("AUDIT_OPTIONAL_VAR")
+            .with_required()
+            .with_optional()];
+
+        match audit_vars(&vars) {
+            Err(EnvInventoryError::ContradictoryConstraints { name, reason }) => {
+                assert_eq!(name, "AUDIT_OPTIONAL_VAR");
+                assert!(reason.contains("optional"));
+            }
+            other => panic!("expected a contradiction, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_default_not_allowed() {
+        let vars = [
+            RequiredVar::new("AUDIT_ALLOWED_VAR")
+                .with_default("fast")
+                .with_allowed(&["fast", "slow"]),
+            RequiredVar::new("AUDIT_DISALLOWED_VAR")
+                .with_default("turbo")
+                .with_allowed(&["fast", "slow"]),
+        ];
+
+        assert!(audit_vars(&vars[..1]).is_ok());
+        match audit_vars(&vars) {
+            Err(EnvInventoryError::ContradictoryConstraints { name, reason }) => {
+                assert_eq!(name, "AUDIT_DISALLOWED_VAR");
+                assert!(reason.contains("\"turbo\""));
+            }
+            other => panic!("expected a contradiction, got {:?}", other),
+        }
+    }
+
+    crate::register!(AUDIT_DATABASE_URL = "postgres://localhost/app");
+    crate::register!(AUDIT_DATABSE_URL = "postgres://localhost/app");
+
+    #[test]
+    fn test_find_similar_names() {
+        let pair = (
+            "AUDIT_DATABASE_URL".to_string(),
+            "AUDIT_DATABSE_URL".to_string(),
+        );
+        assert!(find_similar_names(1).contains(&pair));
+        assert!(!find_similar_names(0).contains(&pair));
+    }
+
+    #[test]
+    fn test_similar_names() {
+        let vars = [
+            RequiredVar::new("CACHE_TTL"),
+            RequiredVar::new("CACHE_TTL"),
+            RequiredVar::new("CAHCE_TTL"),
+            RequiredVar::new("LOG_LEVEL"),
+        ];
+
+        assert_eq!(similar_names(&vars, 1), vec![]);
+        assert_eq!(
+            similar_names(&vars, 2),
+            vec![("CACHE_TTL".to_string(), "CAHCE_TTL".to_string())]
+        );
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[allow(non_upper_case_globals)]
+    mod case_collision {
+        crate::register!(AUDIT_LOG_DIR = "/var/log");
+        crate::register!(Audit_Log_Dir = "/var/log");
+    }
+
+    #[test]
+    fn test_find_case_collisions() {
+        let group = vec!["AUDIT_LOG_DIR".to_string(), "Audit_Log_Dir".to_string()];
+        assert!(find_case_collisions().contains(&group));
+    }
+
+    #[test]
+    fn test_case_collisions() {
+        let vars = [
+            RequiredVar::new("PATH"),
+            RequiredVar::new("Path"),
+            RequiredVar::new("PATH"),
+            RequiredVar::new("HOME"),
+            RequiredVar::new("database_url"),
+            RequiredVar::new("DATABASE_URL"),
+            RequiredVar::new("Database_Url"),
+        ];
+
+        assert_eq!(
+            case_collisions(&vars),
+            vec![
+                vec![
+                    "DATABASE_URL".to_string(),
+                    "Database_Url".to_string(),
+                    "database_url".to_string(),
+                ],
+                vec!["PATH".to_string(), "Path".to_string()],
+            ]
+        );
+        assert!(case_collisions(&vars[3..4]).is_empty());
+    }
+
+    #[test]
+    fn test_registration_conflicts() {
+        let vars = [
+            RequiredVar::new("AUDIT_CACHE_SIZE")
+                .with_default("1024")
+                .with_source("lib_b"),
+            RequiredVar::new("AUDIT_CACHE_SIZE")
+                .with_default("2048")
+                .with_source("lib_a"),
+            RequiredVar::new("AUDIT_CACHE_TTL")
+                .with_default("60")
+                .with_source("lib_a"),
+            RequiredVar::new("AUDIT_CACHE_TTL")
+                .with_default("60")
+                .with_source("lib_b"),
+            RequiredVar::new("AUDIT_CACHE_PORT").with_default("80"),
+            RequiredVar::new("AUDIT_CACHE_PORT")
+                .with_default("80")
+                .with_expected_type(ExpectedType::UInt),
+        ];
+
+        let conflicts = registration_conflicts(&vars);
+        let names: Vec<&str> = conflicts.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["AUDIT_CACHE_PORT", "AUDIT_CACHE_SIZE"]);
+        assert_eq!(
+            conflicts[1].registrations,
+            vec![
+                Registration {
+                    source: "lib_a".to_string(),
+                    default: Some("2048".to_string()),
+                    expected_type: None,
+                    type_name: None,
+                },
+                Registration {
+                    source: "lib_b".to_string(),
+                    default: Some("1024".to_string()),
+                    expected_type: None,
+                    type_name: None,
+                },
+            ]
+        );
+
+        // Integer types of the same kind conflict if their ranges differ
+        let vars = [
+            crate::__register_type!(RequiredVar::new("AUDIT_PORT").with_default("80"), u8),
+            crate::__register_type!(RequiredVar::new("AUDIT_PORT").with_default("80"), u16),
+            crate::__register_type!(RequiredVar::new("AUDIT_RETRIES").with_default("3"), u8),
+            crate::__register_type!(RequiredVar::new("AUDIT_RETRIES").with_default("3"), u8),
+        ];
+        let conflicts = registration_conflicts(&vars);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].name, "AUDIT_PORT");
+        let type_names: Vec<Option<&str>> = conflicts[0]
+            .registrations
+            .iter()
+            .map(|registration| registration.type_name.as_deref())
+            .collect();
+        assert_eq!(type_names, vec![Some("u8"), Some("u16")]);
+    }
+
+    #[test]
+    fn test_check_completeness() {
+        let vars = [
+            RequiredVar::new("AUDIT_SCHEMA_HOST").with_default("localhost"),
+            RequiredVar::new("AUDIT_SCHEMA_PORT").with_default("80"),
+            RequiredVar::new("AUDIT_SCHEMA_PORT").with_default("80"),
+        ];
+
+        let schema = "# Contract\n\nAUDIT_SCHEMA_HOST=localhost\nexport AUDIT_SCHEMA_PORT\n";
+        assert!(check_completeness(&vars, schema).is_ok());
+
+        // Variables without a default are commented out, other comments
+        // are skipped
+        let schema = "# e.g. a=b\n# AUDIT_SCHEMA_HOST=\nAUDIT_SCHEMA_PORT=80\n# Note:\n";
+        assert!(check_completeness(&vars, schema).is_ok());
+
+        let schema = "AUDIT_SCHEMA_HOST\nAUDIT_SCHEMA_USER\n";
+        match check_completeness(&vars, schema) {
+            Err(EnvInventoryError::IncompleteRegistry {
+                unregistered,
+                undeclared,
+            }) => {
+                assert_eq!(unregistered, vec!["AUDIT_SCHEMA_USER"]);
+                assert_eq!(undeclared, vec!["AUDIT_SCHEMA_PORT"]);
+            }
+            other => panic!("expected an incomplete registry, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_validate_completeness() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("schema.env");
+        fs::write(&path, "AUDIT_SCHEMA_UNREGISTERED=1\n").unwrap();
+        match validate_completeness(&path) {
+            Err(EnvInventoryError::IncompleteRegistry { unregistered, .. }) => {
+                assert_eq!(unregistered, vec!["AUDIT_SCHEMA_UNREGISTERED"]);
+            }
+            other => panic!("expected an incomplete registry, got {:?}", other),
+        }
+
+        // Listing every registered variable passes
+        let names: BTreeSet<&str> = inventory::iter::<RequiredVar>()
+            .map(|var| var.name)
+            .collect();
+        let schema: Vec<&str> = names.into_iter().collect();
+        fs::write(&path, schema.join("\n")).unwrap();
+        assert!(validate_completeness(&path).is_ok());
+
+        // So does the template, which comments out variables without a
+        // default
+        let template = crate::export_env_template();
+        assert!(template.contains("# TEST_ENV_VAR=\n"));
+        fs::write(&path, template).unwrap();
+        assert!(validate_completeness(&path).is_ok());
+
+        assert!(matches!(
+            validate_completeness(&dir.path().join("missing.env")),
+            Err(EnvInventoryError::ReadFileError(_))
+        ));
+    }
+
+    #[test]
+    fn test_assert_registry_populated() {
+        match check_populated(&[], 1) {
+            Err(EnvInventoryError::EmptyRegistry { found, min }) => {
+                assert_eq!((found, min), (0, 1));
+            }
+            other => panic!("expected an empty registry, got {:?}", other),
+        }
+        assert!(check_populated(&[], 0).is_ok());
+
+        // Registrations of the same variable count once
+        let vars = [
+            RequiredVar::new("AUDIT_POPULATED_VAR"),
+            RequiredVar::new("AUDIT_POPULATED_VAR"),
+        ];
+        assert!(check_populated(&vars, 1).is_ok());
+        assert!(check_populated(&vars, 2).is_err());
+
+        assert!(assert_registry_populated(2).is_ok());
+        assert!(assert_registry_populated(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_registry_is_consistent() {
+        validate_registry().unwrap();
+    }
+}
@@ -0,0 +1,127 @@
+//! Case-insensitive matching of variable names against the environment, for
+//! platforms and CI systems that mangle their casing.
+
+use crate::EnvInventoryError;
+use std::env;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static CASE_INSENSITIVE: AtomicBool = AtomicBool::new(false);
+
+/// Makes lookups of registered variables fall back to environment variables
+/// whose names differ only in case, such as `Database_Url` for
+/// `DATABASE_URL`.
+///
+/// An exact match always wins. If several variables match ignoring case but
+/// none exactly, the variable has no value, even if it has a default, and
+/// loading or validation fails with `EnvInventoryError::AmbiguousVar` listing
+/// them. Off by default.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, resolve_one, set_case_insensitive};
+/// register!(SERVICE_URL);
+///
+/// std::env::set_var("Service_Url", "http://localhost");
+/// set_case_insensitive(true);
+/// assert_eq!(resolve_one("SERVICE_URL").unwrap().0, "http://localhost");
+/// ```
+pub fn set_case_insensitive(enabled: bool) {
+    CASE_INSENSITIVE.store(enabled, Ordering::SeqCst);
+}
+
+/// Checks if lookups fall back to case-insensitive matches; see
+/// [`set_case_insensitive`].
+pub fn is_case_insensitive() -> bool {
+    CASE_INSENSITIVE.load(Ordering::SeqCst)
+}
+
+/// Gets the value of `name` from the environment, falling back to a
+/// case-insensitive match if enabled.
+///
+/// # Errors
+///
+/// * `AmbiguousVar`: If there is no exact match and several variables match
+///   ignoring case.
+pub(crate) fn lookup(name: &str) -> Result<Option<String>, EnvInventoryError> {
+    if let Ok(value) = env::var(name) {
+        return Ok(Some(value));
+    }
+    if !is_case_insensitive() {
+        return Ok(None);
+    }
+
+    let mut matches: Vec<(String, String)> = env::vars_os()
+        .filter_map(|(key, value)| Some((key.into_string().ok()?, value.into_string().ok()?)))
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .collect();
+    match matches.len() {
+        0 => Ok(None),
+        1 => Ok(matches.pop().map(|(_, value)| value)),
+        _ => {
+            let mut candidates: Vec<String> = matches.into_iter().map(|(key, _)| key).collect();
+            candidates.sort();
+            Err(EnvInventoryError::AmbiguousVar {
+                name: name.to_string(),
+                candidates,
+            })
+        }
+    }
+}
+
+/// Gets the value of `name` like [`lookup`], treating ambiguity as unset.
+pub(crate) fn var(name: &str) -> Option<String> {
+    lookup(name).ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env_lock, resolve_one, validate_env_vars};
+
+    crate::register!(CASE_SERVICE_URL = "http://default");
+
+    #[test]
+    fn test_case_insensitive() {
+        let _guard = env_lock();
+        crate::satisfy_required_vars();
+        env::remove_var("CASE_SERVICE_URL");
+        env::set_var("Case_Service_Url", "http://mangled");
+
+        // Exact matches only by default
+        assert_eq!(resolve_one("CASE_SERVICE_URL").unwrap().0, "http://default");
+
+        set_case_insensitive(true);
+        assert_eq!(resolve_one("CASE_SERVICE_URL").unwrap().0, "http://mangled");
+        let var = inventory::iter::<crate::RequiredVar>()
+            .find(|var| var.name == "CASE_SERVICE_URL")
+            .unwrap();
+        assert_eq!(var.get().unwrap(), "http://mangled");
+
+        // The exact match wins
+        env::set_var("CASE_SERVICE_URL", "http://exact");
+        env::set_var("case_service_url", "http://lower");
+        assert_eq!(lookup("CASE_SERVICE_URL").unwrap().unwrap(), "http://exact");
+
+        env::remove_var("CASE_SERVICE_URL");
+        match validate_env_vars() {
+            Err(EnvInventoryError::AmbiguousVar { name, candidates }) => {
+                assert_eq!(name, "CASE_SERVICE_URL");
+                assert_eq!(candidates, vec!["Case_Service_Url", "case_service_url"]);
+            }
+            other => panic!("expected an ambiguous variable, got {:?}", other),
+        }
+        // Ambiguity doesn't fall back to the default
+        assert_eq!(var.get(), None);
+        assert_eq!(resolve_one("CASE_SERVICE_URL"), None);
+        assert!(matches!(
+            crate::expanded_map(),
+            Err(EnvInventoryError::AmbiguousVar { .. })
+        ));
+
+        set_case_insensitive(false);
+        env::remove_var("Case_Service_Url");
+        env::remove_var("case_service_url");
+        assert!(validate_env_vars().is_ok());
+    }
+}
@@ -0,0 +1,109 @@
+//! Dry-run detection of environment variables a load would overwrite.
+//!
+//! The environment always wins over config files and defaults, so a load
+//! never replaces a value it can read. It does replace values it can't read,
+//! and it rewrites values containing `$VAR` or `~` with their expansion.
+
+use crate::expand::expand;
+use crate::{
+    merge_settings, positional_file_kinds, registered_default, EnvInventoryError, LoaderConfig,
+    Priority, RequiredVar, Sections,
+};
+use std::collections::BTreeSet;
+use std::env;
+use std::path::Path;
+
+/// Lists the registered variables that are set in the environment but whose
+/// values [`load_and_validate_env_vars`](crate::load_and_validate_env_vars)
+/// would replace, without changing anything.
+///
+/// A set variable is replaced if its value isn't valid Unicode and the config
+/// files, platform source or a default provide one, or if shell expansion
+/// changes it. The result is sorted by name.
+///
+/// # Errors
+///
+/// * `ReadFileError`: If the first config file cannot be read.
+/// * `ParseFileError`: If the first config file cannot be parsed as TOML.
+pub fn would_clobber<P: AsRef<Path>, S: Sections + ?Sized>(
+    config_paths: &[P],
+    section: &S,
+) -> Result<Vec<String>, EnvInventoryError> {
+    let (settings, _) = merge_settings(
+        &positional_file_kinds(config_paths),
+        &section.names(),
+        &LoaderConfig::new(),
+    )?;
+    let lookup = |name: &str| env::var(name).ok().or_else(|| settings.get(name).cloned());
+
+    let mut clobbered = BTreeSet::new();
+    for var in inventory::iter::<RequiredVar>() {
+        if env::var_os(var.name).is_none() {
+            continue;
+        }
+        let replaced = match env::var(var.name) {
+            Ok(value) => expand(&value).map_or(false, |expanded| expanded != value),
+            // The loader treats unreadable values as unset
+            Err(_) => {
+                settings.contains_key(var.name)
+                    || var.platform_value().is_some()
+                    || registered_default(var.name, Priority::Binary, lookup).is_some()
+                    || registered_default(var.name, Priority::Library, lookup).is_some()
+            }
+        };
+        if replaced {
+            clobbered.insert(var.name.to_string());
+        }
+    }
+    Ok(clobbered.into_iter().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+    use std::fs;
+    use tempfile::tempdir;
+
+    crate::register!(CLOBBER_PLAIN = "default");
+    crate::register!(CLOBBER_EXPANDED = "default");
+    crate::register!(CLOBBER_UNREADABLE = "default");
+
+    #[test]
+    fn test_would_clobber() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        fs::write(
+            &path,
+            "[env]\nCLOBBER_PLAIN = \"file\"\nCLOBBER_EXPANDED = \"file\"\n",
+        )
+        .unwrap();
+        env::set_var("CLOBBER_PLAIN", "from env");
+        env::set_var("CLOBBER_EXPANDED", "$CLOBBER_PLAIN/data");
+        env::remove_var("CLOBBER_UNREADABLE");
+
+        assert_eq!(
+            would_clobber(&[&path], "env").unwrap(),
+            vec!["CLOBBER_EXPANDED".to_string()]
+        );
+        // Nothing was changed
+        assert_eq!(env::var("CLOBBER_EXPANDED").unwrap(), "$CLOBBER_PLAIN/data");
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            env::set_var("CLOBBER_UNREADABLE", OsStr::from_bytes(b"\xff"));
+            assert_eq!(
+                would_clobber(&[&path], "env").unwrap(),
+                vec!["CLOBBER_EXPANDED".to_string(), "CLOBBER_UNREADABLE".to_string()]
+            );
+        }
+
+        for name in ["CLOBBER_PLAIN", "CLOBBER_EXPANDED", "CLOBBER_UNREADABLE"] {
+            env::remove_var(name);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Advisories about questionable but permitted config, reported without
+//! failing the load.
+
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// An advisory reported by the loaders through the hook installed with
+/// [`set_diagnostic_hook`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Diagnostic {
+    /// A variable registered with the `secret` option took its value from a
+    /// plaintext config file. Enable
+    /// [`LoaderConfig::forbid_secrets_in_files`](crate::LoaderConfig::forbid_secrets_in_files)
+    /// to fail instead.
+    SecretInConfigFile {
+        /// The name of the secret variable.
+        name: String,
+        /// The config file setting it.
+        path: String,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::SecretInConfigFile { name, path } => write!(
+                f,
+                "Secret {} is set in plaintext in {}; set it in the environment or a vault instead",
+                name, path
+            ),
+        }
+    }
+}
+
+type Hook = Arc<dyn Fn(&Diagnostic) + Send + Sync>;
+
+static HOOK: RwLock<Option<Hook>> = RwLock::new(None);
+
+/// Installs a function receiving every [`Diagnostic`] the loaders report,
+/// replacing any previous hook.
+///
+/// Without a hook, diagnostics are printed to stderr as warnings, or logged
+/// with `tracing::warn!` when the `tracing` feature is enabled. The hook may
+/// itself install another hook.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::set_diagnostic_hook;
+/// set_diagnostic_hook(|diagnostic| eprintln!("config: {}", diagnostic));
+/// ```
+pub fn set_diagnostic_hook<F>(hook: F)
+where
+    F: Fn(&Diagnostic) + Send + Sync + 'static,
+{
+    let mut current = HOOK.write().unwrap_or_else(|e| e.into_inner());
+    *current = Some(Arc::new(hook));
+}
+
+/// Reports `diagnostic` to the installed hook, or else to stderr or
+/// `tracing`.
+pub(crate) fn emit(diagnostic: Diagnostic) {
+    // Called outside the lock, so the hook may replace itself
+    let hook = HOOK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match hook {
+        Some(hook) => hook(&diagnostic),
+        #[cfg(feature = "tracing")]
+        None => tracing::warn!("{}", diagnostic),
+        #[cfg(not(feature = "tracing"))]
+        None => eprintln!("Warning: {}", diagnostic),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_hook_may_replace_itself() {
+        let _guard = env_lock();
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reported);
+        set_diagnostic_hook(move |_| {
+            let sink = Arc::clone(&sink);
+            set_diagnostic_hook(move |diagnostic| sink.lock().unwrap().push(diagnostic.clone()));
+        });
+        let diagnostic = Diagnostic::SecretInConfigFile {
+            name: "API_KEY".to_string(),
+            path: "app.toml".to_string(),
+        };
+
+        emit(diagnostic.clone());
+        assert!(reported.lock().unwrap().is_empty());
+        emit(diagnostic.clone());
+        assert_eq!(*reported.lock().unwrap(), vec![diagnostic]);
+    }
+}
@@ -0,0 +1,255 @@
+//! `.env` files, as read by dotenv and docker compose.
+
+use crate::EnvInventoryError;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Sets the variables assigned in the `.env` file at `path` that aren't
+/// already set in the environment.
+///
+/// Each line is `KEY=value`, optionally prefixed with `export `. Blank lines
+/// and lines starting with `#` are ignored. Values may be wrapped in double
+/// quotes, which support the escapes `\n`, `\t`, `\"` and `\\`, or single
+/// quotes, which are taken literally; unquoted values end at a ` #` comment.
+/// Everything after the first `=` is the value, so values may contain `=`.
+///
+/// Unlike the loaders, this sets every variable in the file, registered or
+/// not, and validates nothing. To merge a `.env` file with config files
+/// instead, pass it to [`load_and_validate_env_vars`](crate::load_and_validate_env_vars)
+/// along with them.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::load_dotenv;
+/// load_dotenv(".env").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ReadFileError`: If the file can't be read.
+/// * `ParseFileError`: If a line isn't a valid assignment.
+pub fn load_dotenv<P: AsRef<Path>>(path: P) -> Result<(), EnvInventoryError> {
+    let settings = load_dotenv_settings(path.as_ref())?;
+    let _lock = crate::sync::lock_env();
+    for (name, value) in settings {
+        if env::var_os(&name).is_none() {
+            crate::sync::set_var(&name, value);
+        }
+    }
+    Ok(())
+}
+
+/// Checks if the file at `path` is a `.env` file, judging by its name: `.env`,
+/// `.env.local` and the like, or anything with the `env` extension.
+pub(crate) fn is_dotenv(path: &Path) -> bool {
+    let name = match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name,
+        None => return false,
+    };
+    name == ".env"
+        || name.starts_with(".env.")
+        || path.extension().map_or(false, |ext| ext == "env")
+}
+
+/// Reads the assignments of the `.env` file at `path`.
+pub(crate) fn load_dotenv_settings(
+    path: &Path,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let content = fs::read_to_string(path)
+        .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+    parse_dotenv_settings(&content, &path.display().to_string())
+}
+
+/// Reads the assignments of the `.env` file at `path` as config, checking
+/// its `SCHEMA_VERSION`.
+pub(crate) fn load_dotenv_config(
+    path: &Path,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let content = fs::read_to_string(path)
+        .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+    parse_dotenv_config(&content, &path.display().to_string())
+}
+
+/// Parses the assignments of `.env` `content` read from `source` as config,
+/// checking its `SCHEMA_VERSION`.
+pub(crate) fn parse_dotenv_config(
+    content: &str,
+    source: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let mut settings = parse_dotenv_settings(content, source)?;
+    crate::take_schema_version(&mut settings)?;
+    Ok(settings)
+}
+
+/// Parses the assignments of `.env` `content` read from `source`.
+pub(crate) fn parse_dotenv_settings(
+    content: &str,
+    source: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    parse_dotenv(content)
+        .map(|settings| settings.into_iter().collect())
+        .map_err(|_| EnvInventoryError::ParseFileError(source.to_string()))
+}
+
+/// Parses the assignments in `content`, in order, failing on the number of
+/// the first invalid line.
+fn parse_dotenv(content: &str) -> Result<Vec<(String, String)>, usize> {
+    let mut settings = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (name, value) = line.split_once('=').ok_or(index + 1)?;
+        let name = name.trim();
+        if !is_name(name) {
+            return Err(index + 1);
+        }
+        let value = parse_value(value.trim()).ok_or(index + 1)?;
+        settings.push((name.to_string(), value));
+    }
+    Ok(settings)
+}
+
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+/// Parses a value, unquoting it. Returns `None` for an unterminated quote or
+/// anything but a comment after the closing quote.
+fn parse_value(value: &str) -> Option<String> {
+    let (parsed, rest) = if let Some(quoted) = value.strip_prefix('"') {
+        let mut parsed = String::new();
+        let mut chars = quoted.char_indices();
+        loop {
+            match chars.next()? {
+                (end, '"') => break (parsed, &quoted[end + 1..]),
+                (_, '\\') => match chars.next()? {
+                    (_, 'n') => parsed.push('\n'),
+                    (_, 't') => parsed.push('\t'),
+                    (_, 'r') => parsed.push('\r'),
+                    (_, c @ ('"' | '\\' | '$')) => parsed.push(c),
+                    (_, c) => {
+                        parsed.push('\\');
+                        parsed.push(c);
+                    }
+                },
+                (_, c) => parsed.push(c),
+            }
+        }
+    } else if let Some(quoted) = value.strip_prefix('\'') {
+        let end = quoted.find('\'')?;
+        (quoted[..end].to_string(), &quoted[end + 1..])
+    } else {
+        let end = value
+            .char_indices()
+            .find(|&(i, c)| c == '#' && value[..i].ends_with(char::is_whitespace))
+            .map_or(value.len(), |(i, _)| i);
+        return Some(value[..end].trim_end().to_string());
+    };
+
+    let rest = rest.trim_start();
+    if rest.is_empty() || rest.starts_with('#') {
+        Some(parsed)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn parse(content: &str) -> Vec<(String, String)> {
+        parse_dotenv(content).unwrap()
+    }
+
+    fn pair(name: &str, value: &str) -> (String, String) {
+        (name.to_string(), value.to_string())
+    }
+
+    #[test]
+    fn test_parse_dotenv() {
+        let content = "# comment\n\nPLAIN=value\nexport EXPORTED=yes\nSPACED = padded   \n";
+        assert_eq!(
+            parse(content),
+            vec![
+                pair("PLAIN", "value"),
+                pair("EXPORTED", "yes"),
+                pair("SPACED", "padded"),
+            ]
+        );
+
+        // Values containing `=`, and CRLF line endings
+        assert_eq!(
+            parse("URL=postgres://db/app?sslmode=require\r\nEMPTY=\r\n"),
+            vec![
+                pair("URL", "postgres://db/app?sslmode=require"),
+                pair("EMPTY", ""),
+            ]
+        );
+
+        // Quotes and comments
+        assert_eq!(
+            parse(concat!(
+                "DOUBLE=\"a \\\"quoted\\\" # value\\n\" # comment\n",
+                "SINGLE='$literal \\n'\n",
+                "INLINE=value # comment\n",
+                "HASH=a#b\n",
+            )),
+            vec![
+                pair("DOUBLE", "a \"quoted\" # value\n"),
+                pair("SINGLE", "$literal \\n"),
+                pair("INLINE", "value"),
+                pair("HASH", "a#b"),
+            ]
+        );
+
+        assert_eq!(parse_dotenv("VALID=1\nnot an assignment\n"), Err(2));
+        assert_eq!(parse_dotenv("1BAD=1\n"), Err(1));
+        assert_eq!(parse_dotenv("OPEN=\"unterminated\n"), Err(1));
+        assert_eq!(parse_dotenv("TRAILING=\"quoted\" junk\n"), Err(1));
+    }
+
+    #[test]
+    fn test_is_dotenv() {
+        assert!(is_dotenv(Path::new("/app/.env")));
+        assert!(is_dotenv(Path::new(".env.local")));
+        assert!(is_dotenv(Path::new("prod.env")));
+        assert!(!is_dotenv(Path::new("app.toml")));
+        assert!(!is_dotenv(Path::new(".envrc")));
+    }
+
+    #[test]
+    fn test_load_dotenv() {
+        let _guard = crate::env_lock();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join(".env");
+        fs::write(&path, "DOTENV_NEW=from file\r\nDOTENV_SET=from file\r\n").unwrap();
+        env::remove_var("DOTENV_NEW");
+        env::set_var("DOTENV_SET", "from env");
+
+        load_dotenv(&path).unwrap();
+        assert_eq!(env::var("DOTENV_NEW").unwrap(), "from file");
+        assert_eq!(env::var("DOTENV_SET").unwrap(), "from env");
+        env::remove_var("DOTENV_NEW");
+        env::remove_var("DOTENV_SET");
+
+        fs::write(&path, "not an assignment\n").unwrap();
+        assert!(matches!(
+            load_dotenv(&path),
+            Err(EnvInventoryError::ParseFileError(_))
+        ));
+        assert!(matches!(
+            load_dotenv(dir.path().join("missing.env")),
+            Err(EnvInventoryError::ReadFileError(_))
+        ));
+    }
+}
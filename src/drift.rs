@@ -0,0 +1,71 @@
+//! Detection of config changes made after startup.
+//!
+//! [`init_config`](crate::init_config) snapshots the resolved values once
+//! loading succeeds; [`drift_since_init`] compares the current values against
+//! that snapshot, revealing variables mutated later by the app or by external
+//! tooling.
+
+use crate::{map, RequiredVar, MASK};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::RwLock;
+
+static SNAPSHOT: RwLock<Option<HashMap<String, String>>> = RwLock::new(None);
+
+/// A variable whose resolved value differs from the snapshot taken at init.
+///
+/// The values of variables at or above the
+/// [`redaction_level`](crate::redaction_level) are replaced by `****`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConfigChange {
+    /// The name of the variable.
+    pub name: String,
+    /// The value at init, or `None` if it had none.
+    pub before: Option<String>,
+    /// The current value, or `None` if it has none.
+    pub after: Option<String>,
+}
+
+/// Replaces the snapshot with the current resolved values.
+pub(crate) fn capture() {
+    let values = resolved();
+    *SNAPSHOT.write().unwrap_or_else(|e| e.into_inner()) = Some(values);
+}
+
+/// Lists the variables whose resolved value changed since
+/// [`init_config`](crate::init_config) last succeeded, sorted by name.
+///
+/// Returns an empty list if `init_config` hasn't run.
+pub fn drift_since_init() -> Vec<ConfigChange> {
+    let snapshot = SNAPSHOT.read().unwrap_or_else(|e| e.into_inner());
+    let before = match snapshot.as_ref() {
+        Some(before) => before,
+        None => return Vec::new(),
+    };
+    let after = resolved();
+
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+    names
+        .into_iter()
+        .filter(|name| before.get(*name) != after.get(*name))
+        .map(|name| {
+            let secret = inventory::iter::<RequiredVar>()
+                .any(|var| var.name == name.as_str() && var.is_redacted());
+            let shown = |value: Option<&String>| match value {
+                Some(_) if secret => Some(MASK.to_string()),
+                value => value.cloned(),
+            };
+            ConfigChange {
+                name: name.clone(),
+                before: shown(before.get(name)),
+                after: shown(after.get(name)),
+            }
+        })
+        .collect()
+}
+
+fn resolved() -> HashMap<String, String> {
+    map()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
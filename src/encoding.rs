@@ -0,0 +1,78 @@
+//! Decoding of encoded values.
+
+use crate::{access, EnvInventoryError, RequiredVar, MASK};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+/// Gets the value of a registered variable decoded from base64.
+///
+/// Secrets such as TLS keys are often injected base64-encoded. The value uses
+/// the standard alphabet with padding; whitespace, such as the line breaks of
+/// a wrapped value, is ignored. Register the variable with the `base64`
+/// option to have validation reject values that don't decode.
+///
+/// Returns `Ok(None)` if `name` isn't registered or has no value.
+///
+/// # Errors
+///
+/// * `ParseValueError`: If the value isn't valid base64. The value is masked
+///   if the variable is a secret.
+pub fn get_base64(name: &str) -> Result<Option<Vec<u8>>, EnvInventoryError> {
+    match access::read(name) {
+        Some((value, _)) => match decode_base64(&value) {
+            Ok(decoded) => Ok(Some(decoded)),
+            Err(_) => {
+                let secret = inventory::iter::<RequiredVar>()
+                    .any(|var| var.name == name && var.is_redacted());
+                Err(EnvInventoryError::ParseValueError {
+                    name: name.to_string(),
+                    value: if secret { MASK.to_string() } else { value },
+                    type_name: "base64",
+                })
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn decode_base64(value: &str) -> Result<Vec<u8>, String> {
+    let compact: String = value.chars().filter(|c| !c.is_ascii_whitespace()).collect();
+    STANDARD.decode(compact).map_err(|e| format!("invalid base64: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    crate::register!(BASE64_TLS_KEY = "aGVsbG8="; base64);
+    crate::register!(BASE64_SECRET_KEY = "aGk="; base64; secret);
+
+    #[test]
+    fn test_get_base64() {
+        let _guard = crate::env_lock();
+        env::remove_var("BASE64_TLS_KEY");
+        assert_eq!(get_base64("BASE64_TLS_KEY").unwrap(), Some(b"hello".to_vec()));
+        assert_eq!(get_base64("NOT_REGISTERED_BASE64").unwrap(), None);
+
+        env::set_var("BASE64_TLS_KEY", "d29y\nbGQ=");
+        assert_eq!(get_base64("BASE64_TLS_KEY").unwrap(), Some(b"world".to_vec()));
+
+        env::set_var("BASE64_TLS_KEY", "not base64!");
+        assert!(matches!(
+            get_base64("BASE64_TLS_KEY"),
+            Err(EnvInventoryError::ParseValueError {
+                type_name: "base64",
+                ..
+            })
+        ));
+        env::remove_var("BASE64_TLS_KEY");
+
+        env::set_var("BASE64_SECRET_KEY", "not base64!");
+        match get_base64("BASE64_SECRET_KEY") {
+            Err(EnvInventoryError::ParseValueError { value, .. }) => assert_eq!(value, MASK),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        env::remove_var("BASE64_SECRET_KEY");
+    }
+}
@@ -0,0 +1,252 @@
+//! Expansion of `~` and variable references in values.
+//!
+//! Values may refer to other variables, Unix-style as `$VAR` or `${VAR}`, or
+//! Windows-style as `%VAR%`. Which syntax is recognized is selected with
+//! [`set_expansion_style`].
+
+use crate::EnvInventoryError;
+use std::env::{self, VarError};
+use std::sync::RwLock;
+
+/// The syntax of variable references recognized when expanding values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExpansionStyle {
+    /// `~`, `$VAR`, `${VAR}` and `${VAR:-default}`, as in POSIX shells.
+    Unix,
+    /// `%VAR%`, as in `cmd.exe`, with `%%` for a literal `%`. As there, a
+    /// reference to a variable that isn't set is kept as is.
+    Windows,
+    /// Unix-style and Windows-style references, expanded in a single pass.
+    Both,
+}
+
+impl Default for ExpansionStyle {
+    /// [`ExpansionStyle::Both`] on Windows, [`ExpansionStyle::Unix`]
+    /// elsewhere.
+    fn default() -> Self {
+        if cfg!(windows) {
+            ExpansionStyle::Both
+        } else {
+            ExpansionStyle::Unix
+        }
+    }
+}
+
+static STYLE: RwLock<Option<ExpansionStyle>> = RwLock::new(None);
+
+/// Selects the syntax of variable references expanded by the loaders,
+/// [`expanded_map`](crate::expanded_map) and
+/// [`get_raw_and_expanded`](crate::get_raw_and_expanded).
+///
+/// Without a call, the [default](ExpansionStyle::default) for the target
+/// platform is used.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{set_expansion_style, ExpansionStyle};
+/// // Accept `%APPDATA%\app` in config files written for Windows
+/// set_expansion_style(ExpansionStyle::Both);
+/// ```
+pub fn set_expansion_style(style: ExpansionStyle) {
+    *STYLE.write().unwrap_or_else(|e| e.into_inner()) = Some(style);
+}
+
+/// The syntax of variable references currently expanded.
+pub fn expansion_style() -> ExpansionStyle {
+    STYLE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .unwrap_or_default()
+}
+
+/// Expands `value`, looking references up in the environment.
+pub(crate) fn expand(value: &str) -> Result<String, EnvInventoryError> {
+    expand_with(value, |name| env::var(name).map(Some))
+}
+
+/// Expands `value`, looking references up with `context`. A reference for
+/// which `context` returns `Ok(None)` is left as is.
+pub(crate) fn expand_with<C>(value: &str, context: C) -> Result<String, EnvInventoryError>
+where
+    C: Fn(&str) -> Result<Option<String>, VarError>,
+{
+    expand_as(expansion_style(), value, context)
+}
+
+fn expand_as<C>(style: ExpansionStyle, value: &str, context: C) -> Result<String, EnvInventoryError>
+where
+    C: Fn(&str) -> Result<Option<String>, VarError>,
+{
+    match style {
+        ExpansionStyle::Unix => expand_unix(value, true, &context),
+        ExpansionStyle::Windows => expand_percent(value, &context, |text, _| Ok(text.to_string())),
+        ExpansionStyle::Both => expand_percent(value, &context, |text, at_start| {
+            expand_unix(text, at_start, &context)
+        }),
+    }
+}
+
+/// Expands `~`, if `at_start` of the value, and `$VAR` references in `value`.
+fn expand_unix<C>(value: &str, at_start: bool, context: C) -> Result<String, EnvInventoryError>
+where
+    C: Fn(&str) -> Result<Option<String>, VarError>,
+{
+    let expanded = if at_start {
+        let home_dir = || Some(shellexpand::tilde("~").into_owned());
+        shellexpand::full_with_context(value, home_dir, &context)
+    } else {
+        shellexpand::env_with_context(value, &context)
+    };
+    expanded
+        .map(|expanded| expanded.into_owned())
+        .map_err(|e| EnvInventoryError::MissingEnvVar(e.to_string()))
+}
+
+/// Expands `%VAR%` references in `value`, and `%%` to `%`, passing the text
+/// around them through `text` with whether it starts the value. A `%` that
+/// doesn't start a reference, as in `100%`, is kept, as is a reference to a
+/// variable that isn't set.
+///
+/// Substituted values aren't scanned again, so a `%` in them is kept.
+fn expand_percent<C, T>(value: &str, context: C, text: T) -> Result<String, EnvInventoryError>
+where
+    C: Fn(&str) -> Result<Option<String>, VarError>,
+    T: Fn(&str, bool) -> Result<String, EnvInventoryError>,
+{
+    let mut out = String::with_capacity(value.len());
+    // The start of the text not expanded yet, and where to look for a `%`
+    let mut text_start = 0;
+    let mut pos = 0;
+    while let Some(offset) = value[pos..].find('%') {
+        let start = pos + offset;
+        let after = &value[start + 1..];
+        if after.starts_with('%') {
+            out.push_str(&text(&value[text_start..start], text_start == 0)?);
+            out.push('%');
+            pos = start + 2;
+            text_start = pos;
+            continue;
+        }
+
+        let name = after.find('%').map(|end| &after[..end]);
+        match name {
+            Some(name) if is_reference(name) => {
+                out.push_str(&text(&value[text_start..start], text_start == 0)?);
+                match context(name) {
+                    Ok(Some(found)) => out.push_str(&found),
+                    Ok(None) | Err(VarError::NotPresent) => {
+                        out.push('%');
+                        out.push_str(name);
+                        out.push('%');
+                    }
+                    Err(e) => {
+                        return Err(EnvInventoryError::MissingEnvVar(format!(
+                            "error looking key '{}' up: {}",
+                            name, e
+                        )))
+                    }
+                }
+                pos = start + name.len() + 2;
+                text_start = pos;
+            }
+            _ => pos = start + 1,
+        }
+    }
+    out.push_str(&text(&value[text_start..], text_start == 0)?);
+    Ok(out)
+}
+
+/// Checks if `name` can be the name in a `%name%` reference.
+fn is_reference(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '(' | ')'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(name: &str) -> Result<Option<String>, VarError> {
+        match name {
+            "APPDATA" => Ok(Some("C:\\Users\\me\\AppData".to_string())),
+            "HOST" => Ok(Some("db.internal".to_string())),
+            "UNKNOWN" => Ok(None),
+            _ => Err(VarError::NotPresent),
+        }
+    }
+
+    #[test]
+    fn test_windows_style() {
+        let expand = |value| expand_as(ExpansionStyle::Windows, value, context).unwrap();
+
+        assert_eq!(expand("%APPDATA%\\app"), "C:\\Users\\me\\AppData\\app");
+        assert_eq!(expand("%HOST%:%HOST%"), "db.internal:db.internal");
+        assert_eq!(expand("%UNKNOWN%"), "%UNKNOWN%");
+
+        // Literal percents
+        assert_eq!(expand("100%%"), "100%");
+        assert_eq!(expand("100%"), "100%");
+        assert_eq!(expand("50% of 100% done"), "50% of 100% done");
+        assert_eq!(expand("%%HOST%%"), "%HOST%");
+
+        // Unix-style references are left alone
+        assert_eq!(expand("$HOST"), "$HOST");
+
+        // As in `cmd.exe`, references to unset variables are kept, so
+        // percent-encoded values expand to themselves
+        assert_eq!(expand("%MISSING%"), "%MISSING%");
+        assert_eq!(expand("hello%20world%20x"), "hello%20world%20x");
+    }
+
+    #[test]
+    fn test_styles() {
+        let value = "${HOST}/%HOST%";
+        assert_eq!(
+            expand_as(ExpansionStyle::Unix, value, context).unwrap(),
+            "db.internal/%HOST%"
+        );
+        assert_eq!(
+            expand_as(ExpansionStyle::Both, value, context).unwrap(),
+            "db.internal/db.internal"
+        );
+        assert_eq!(
+            expand_as(ExpansionStyle::Both, "~/%HOST%/${HOST}", context).unwrap(),
+            format!("{}/db.internal/db.internal", shellexpand::tilde("~"))
+        );
+    }
+
+    #[test]
+    fn test_both_styles_in_one_pass() {
+        let context = |name: &str| match name {
+            "PW" => Ok(Some("ab%%cd".to_string())),
+            "HOST" => Ok(Some("db.internal".to_string())),
+            _ => Err(VarError::NotPresent),
+        };
+        let expand = |value| expand_as(ExpansionStyle::Both, value, context).unwrap();
+
+        // Substituted values aren't expanded again
+        assert_eq!(expand("${PW}"), "ab%%cd");
+        assert_eq!(expand("$PW-%HOST%"), "ab%%cd-db.internal");
+        assert_eq!(
+            expand("https://%HOST%/hello%20world%20x"),
+            "https://db.internal/hello%20world%20x"
+        );
+    }
+
+    #[test]
+    fn test_set_expansion_style() {
+        let _guard = crate::env_lock();
+        env::set_var("EXPAND_STYLE_VAR", "value");
+
+        set_expansion_style(ExpansionStyle::Windows);
+        assert_eq!(expansion_style(), ExpansionStyle::Windows);
+        assert_eq!(expand("%EXPAND_STYLE_VAR%").unwrap(), "value");
+
+        set_expansion_style(ExpansionStyle::default());
+        assert_eq!(expand("$EXPAND_STYLE_VAR").unwrap(), "value");
+        env::remove_var("EXPAND_STYLE_VAR");
+    }
+}
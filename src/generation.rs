@@ -0,0 +1,54 @@
+//! A counter of config changes, for invalidating state derived from it.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The values applied by the last successful load that changed them.
+static LAST_VALUES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// The generation of the loaded config: 0 before any load, then incremented
+/// by each successful load that applies different values to the environment
+/// from the previous one.
+///
+/// Loads that only return the values, such as [`Loader::load`](crate::Loader::load)
+/// or loads with [`mutate_env`](crate::LoaderConfig::mutate_env) off, don't
+/// change the generation.
+///
+/// Compare it with the generation state was computed at to know whether to
+/// recompute it after a reload.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{generation, register, LoaderConfig};
+/// # use std::path::Path;
+/// register!(WORKER_COUNT = "4");
+///
+/// let loader = LoaderConfig::new();
+/// loader.load_files::<&Path, _>(&[], "env").unwrap();
+/// let seen = generation();
+///
+/// // Nothing changed
+/// loader.load_files::<&Path, _>(&[], "env").unwrap();
+/// assert_eq!(generation(), seen);
+///
+/// std::env::set_var("WORKER_COUNT", "8");
+/// loader.load_files::<&Path, _>(&[], "env").unwrap();
+/// assert!(generation() > seen);
+/// ```
+pub fn generation() -> u64 {
+    GENERATION.load(Ordering::SeqCst)
+}
+
+/// Records the values applied by a successful load, starting a new
+/// generation if they changed.
+pub(crate) fn record(values: HashMap<String, String>) {
+    let mut last = LAST_VALUES.lock().unwrap_or_else(|e| e.into_inner());
+    if last.as_ref() != Some(&values) {
+        *last = Some(values);
+        GENERATION.fetch_add(1, Ordering::SeqCst);
+    }
+}
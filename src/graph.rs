@@ -0,0 +1,236 @@
+//! Dependencies between variables through `$VAR` and `${VAR}` references.
+
+use crate::{EnvInventoryError, RequiredVar};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::env;
+
+/// Lists each registered variable with the variables it references, both
+/// sorted by name.
+///
+/// References are the `$NAME` and `${NAME}` expansions (including
+/// `${NAME:-fallback}`) in any of the variable's registered defaults and in
+/// its current environment value, plus the inputs of a variable registered
+/// with [`register_derived!`](crate::register_derived). Referenced variables
+/// need not be registered, so `$HOME` is reported too. Render the result as a
+/// DOT graph or similar to visualize how values are resolved.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{expansion_graph, register};
+/// register!(DATA_DIR = "/var/lib/app");
+/// register!(CACHE_DIR = "${DATA_DIR}/cache");
+///
+/// let graph = expansion_graph();
+/// assert!(graph.contains(&("CACHE_DIR".to_string(), vec!["DATA_DIR".to_string()])));
+/// ```
+pub fn expansion_graph() -> Vec<(String, Vec<String>)> {
+    let mut graph: BTreeMap<String, BTreeSet<String>> = BTreeMap::new();
+    for var in inventory::iter::<RequiredVar>() {
+        let edges = graph.entry(var.name.to_string()).or_default();
+        let condition_values = var
+            .condition
+            .iter()
+            .flat_map(|condition| condition.cases.iter().map(|(_, value)| *value));
+        for value in var.default.into_iter().chain(condition_values) {
+            edges.extend(references(value));
+        }
+        if let Some(derivation) = var.derivation {
+            edges.extend(derivation.from.iter().map(|name| name.to_string()));
+        }
+        if let Ok(value) = env::var(var.name) {
+            edges.extend(references(&value));
+        }
+    }
+
+    graph
+        .into_iter()
+        .map(|(name, edges)| (name, edges.into_iter().collect()))
+        .collect()
+}
+
+/// Lists the registered variables that depend on `name` in the
+/// [`expansion_graph`]: those whose default or current value references it,
+/// and those derived from it. These would break if `name` were removed.
+///
+/// Only direct dependents are listed, sorted by name.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{dependents_of, register};
+/// register!(DATA_DIR = "/var/lib/app");
+/// register!(LOG_DIR = "${DATA_DIR}/logs");
+///
+/// assert_eq!(dependents_of("DATA_DIR"), vec!["LOG_DIR"]);
+/// ```
+pub fn dependents_of(name: &str) -> Vec<String> {
+    expansion_graph()
+        .into_iter()
+        .filter(|(_, edges)| edges.iter().any(|edge| edge == name))
+        .map(|(dependent, _)| dependent)
+        .collect()
+}
+
+/// Orders the variables of `dependencies`, a map of each variable to those
+/// it depends on, so that every variable comes after its dependencies.
+///
+/// Dependencies outside the map are ignored. Ties are broken by name, so the
+/// order doesn't depend on the order of registration.
+///
+/// # Errors
+///
+/// * `CyclicReference`: If variables depend on each other, naming the first
+///   cycle found, such as `A -> B -> A`.
+pub(crate) fn resolution_order<'a>(
+    dependencies: &BTreeMap<&'a str, Vec<String>>,
+) -> Result<Vec<&'a str>, EnvInventoryError> {
+    let mut done = HashMap::new();
+    let mut order = Vec::new();
+    for name in dependencies.keys() {
+        visit(name, dependencies, &mut done, &mut Vec::new(), &mut order)?;
+    }
+    Ok(order)
+}
+
+/// Appends `name` to `order` after its dependencies, tracking the path from
+/// the variable the walk started at in `path` to detect cycles. `done` maps
+/// each visited variable to whether all its dependencies are ordered.
+fn visit<'a>(
+    name: &'a str,
+    dependencies: &BTreeMap<&'a str, Vec<String>>,
+    done: &mut HashMap<&'a str, bool>,
+    path: &mut Vec<&'a str>,
+    order: &mut Vec<&'a str>,
+) -> Result<(), EnvInventoryError> {
+    match done.get(name) {
+        Some(true) => return Ok(()),
+        Some(false) => {
+            let start = path
+                .iter()
+                .position(|visited| *visited == name)
+                .unwrap_or(0);
+            let mut cycle: Vec<String> =
+                path[start..].iter().map(|name| name.to_string()).collect();
+            cycle.push(name.to_string());
+            return Err(EnvInventoryError::CyclicReference(cycle));
+        }
+        None => {}
+    }
+
+    done.insert(name, false);
+    path.push(name);
+    let mut names: Vec<&String> = dependencies[name].iter().collect();
+    names.sort();
+    for dependency in names {
+        if let Some((dependency, _)) = dependencies.get_key_value(dependency.as_str()) {
+            visit(dependency, dependencies, done, path, order)?;
+        }
+    }
+    path.pop();
+    done.insert(name, true);
+    order.push(name);
+    Ok(())
+}
+
+/// Extracts the names of the variables referenced by `value`, in order of
+/// appearance.
+pub(crate) fn references(value: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    let mut rest = value;
+    while let Some(index) = rest.find('$') {
+        rest = &rest[index + 1..];
+        let braced = rest.starts_with('{');
+        if braced {
+            rest = &rest[1..];
+        }
+        let end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        let name = &rest[..end];
+        let valid = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_');
+        if valid && (!braced || rest[end..].starts_with(['}', ':'])) {
+            names.push(name.to_string());
+        }
+        rest = &rest[end..];
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Fallbacks keep the expansion of the default valid while both are unset
+    crate::register!(GRAPH_URL = "${GRAPH_SCHEME:-https}://${GRAPH_HOST:-localhost}/");
+
+    crate::register!(GRAPH_DATA_DIR = "/var/lib/app");
+    crate::register!(GRAPH_LOG_DIR = "${GRAPH_DATA_DIR}/logs");
+    crate::register!(GRAPH_LOG_FILE = "${GRAPH_LOG_DIR}/app.log");
+
+    #[test]
+    fn test_dependents_of() {
+        assert_eq!(dependents_of("GRAPH_DATA_DIR"), vec!["GRAPH_LOG_DIR"]);
+        assert_eq!(dependents_of("GRAPH_LOG_DIR"), vec!["GRAPH_LOG_FILE"]);
+        assert!(dependents_of("GRAPH_LOG_FILE").is_empty());
+        assert!(dependents_of("GRAPH_UNKNOWN").is_empty());
+    }
+
+    #[test]
+    fn test_references() {
+        assert_eq!(
+            references("${A}/$B_2:${C:-fallback}/$1/${}/$"),
+            vec!["A", "B_2", "C"]
+        );
+        assert!(references("plain").is_empty());
+    }
+
+    #[test]
+    fn test_resolution_order() {
+        let dependencies =
+            |edges: &[(&'static str, &[&str])]| -> BTreeMap<&'static str, Vec<String>> {
+                edges
+                    .iter()
+                    .map(|(name, refs)| (*name, refs.iter().map(|r| r.to_string()).collect()))
+                    .collect()
+            };
+
+        let order = resolution_order(&dependencies(&[
+            ("URL", &["HOST", "PORT", "HOME"]),
+            ("HOST", &["DOMAIN"]),
+            ("PORT", &[]),
+            ("DOMAIN", &[]),
+        ]))
+        .unwrap();
+        assert_eq!(order, vec!["DOMAIN", "HOST", "PORT", "URL"]);
+
+        match resolution_order(&dependencies(&[
+            ("A", &["B"]),
+            ("B", &["C"]),
+            ("C", &["A"]),
+        ])) {
+            Err(EnvInventoryError::CyclicReference(cycle)) => {
+                assert_eq!(cycle, vec!["A", "B", "C", "A"]);
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        match resolution_order(&dependencies(&[("A", &[]), ("SELF", &["SELF"])])) {
+            Err(EnvInventoryError::CyclicReference(cycle)) => {
+                assert_eq!(cycle, vec!["SELF", "SELF"]);
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_expansion_graph() {
+        let graph = expansion_graph();
+        let (_, edges) = graph.iter().find(|(name, _)| name == "GRAPH_URL").unwrap();
+        assert_eq!(edges, &["GRAPH_HOST", "GRAPH_SCHEME"]);
+
+        let names: Vec<&String> = graph.iter().map(|(name, _)| name).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+    }
+}
@@ -0,0 +1,220 @@
+//! Constraints over groups of registered variables.
+//!
+//! Some invariants span several variables, such as "if any of the database
+//! settings is given, all of them must be". Groups are registered with
+//! [`register_group!`](crate::register_group) and checked during validation.
+
+use crate::provenance::{self, Origin};
+use crate::{EnvInventoryError, RequiredVar};
+use std::env;
+
+/// The constraint a [`Group`] enforces over its members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GroupKind {
+    /// Either none of the members is set, or all of them are.
+    AllOrNone,
+    /// At least one of the members is set explicitly, in the environment, a
+    /// config file or the platform source; a registered default doesn't
+    /// count.
+    AtLeastOneExplicit,
+}
+
+/// A registered constraint over a group of variables.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Group {
+    kind: GroupKind,
+    members: &'static [&'static str],
+    source: &'static str,
+}
+
+inventory::collect!(Group);
+
+impl Group {
+    /// Creates a new `Group` at compile time.
+    pub const fn new(kind: GroupKind, members: &'static [&'static str]) -> Self {
+        Self {
+            kind,
+            members,
+            source: "<none>",
+        }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_source(self, source: &'static str) -> Self {
+        Self { source, ..self }
+    }
+
+    /// The constraint enforced over the members.
+    pub const fn kind(&self) -> GroupKind {
+        self.kind
+    }
+
+    /// The names of the variables in the group.
+    pub const fn members(&self) -> &'static [&'static str] {
+        self.members
+    }
+
+    /// The file the group was registered in.
+    pub const fn source(&self) -> &'static str {
+        self.source
+    }
+
+    /// Checks the group's constraint against the current values.
+    pub fn check(&self) -> Result<(), EnvInventoryError> {
+        self.check_with(is_present, is_explicit)
+    }
+
+    /// Checks the group's constraint, with `is_present` telling whether a
+    /// member has a value and `is_explicit` whether it was set explicitly.
+    pub(crate) fn check_with<F, G>(
+        &self,
+        is_present: F,
+        is_explicit: G,
+    ) -> Result<(), EnvInventoryError>
+    where
+        F: Fn(&str) -> bool,
+        G: Fn(&str) -> bool,
+    {
+        let set: Vec<&str> = self
+            .members
+            .iter()
+            .copied()
+            .filter(|name| is_present(name))
+            .collect();
+
+        match self.kind {
+            GroupKind::AllOrNone if !set.is_empty() && set.len() < self.members.len() => {
+                Err(EnvInventoryError::GroupConstraintViolated {
+                    members: self.members.iter().map(|name| name.to_string()).collect(),
+                    reason: format!("all or none must be set, but only {:?} are set", set),
+                })
+            }
+            GroupKind::AllOrNone => Ok(()),
+            GroupKind::AtLeastOneExplicit if !self.members.iter().any(|name| is_explicit(name)) => {
+                Err(EnvInventoryError::GroupConstraintViolated {
+                    members: self.members.iter().map(|name| name.to_string()).collect(),
+                    reason: "at least one must be set explicitly, but none is".to_string(),
+                })
+            }
+            GroupKind::AtLeastOneExplicit => Ok(()),
+        }
+    }
+}
+
+/// Checks if `name` has a value, including registered defaults.
+fn is_present(name: &str) -> bool {
+    match inventory::iter::<RequiredVar>().find(|var| var.name == name) {
+        Some(var) => var.value().is_some(),
+        None => env::var(name).is_ok(),
+    }
+}
+
+/// Checks if `name` was set explicitly rather than from a default, as
+/// recorded by the last load.
+fn is_explicit(name: &str) -> bool {
+    let from_env = env::var(name).is_ok()
+        && !matches!(
+            provenance::origin(name),
+            Some(Origin::Default | Origin::Derived)
+        );
+    from_env
+        || inventory::iter::<RequiredVar>()
+            .filter(|var| var.name == name)
+            .any(|var| var.platform_value().is_some())
+}
+
+/// Checks the constraints of all registered groups.
+pub(crate) fn validate_groups() -> Result<(), EnvInventoryError> {
+    inventory::iter::<Group>().try_for_each(Group::check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    crate::register_group!(all_or_none = [GROUP_REGISTERED_A, GROUP_REGISTERED_B]);
+    crate::register!(
+        GROUP_PASSWORD = "changeme",
+        GROUP_TOKEN = "",
+        GROUP_CERT = ""
+    );
+
+    #[test]
+    fn test_all_or_none() {
+        let _guard = crate::env_lock();
+        let group = Group::new(
+            GroupKind::AllOrNone,
+            &["GROUP_DB_HOST", "GROUP_DB_USER", "GROUP_DB_PASS"],
+        );
+        let names = group.members();
+        for name in names {
+            env::remove_var(name);
+        }
+
+        // None set
+        assert!(group.check().is_ok());
+
+        // Partially set
+        env::set_var("GROUP_DB_HOST", "localhost");
+        match group.check() {
+            Err(EnvInventoryError::GroupConstraintViolated { members, reason }) => {
+                assert_eq!(members, names);
+                assert!(reason.contains("GROUP_DB_HOST"));
+            }
+            other => panic!("expected a group violation, got {:?}", other),
+        }
+
+        // All set
+        env::set_var("GROUP_DB_USER", "admin");
+        env::set_var("GROUP_DB_PASS", "secret");
+        assert!(group.check().is_ok());
+
+        for name in names {
+            env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_at_least_one_explicit() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        let group = Group::new(
+            GroupKind::AtLeastOneExplicit,
+            &["GROUP_PASSWORD", "GROUP_TOKEN", "GROUP_CERT"],
+        );
+        for name in group.members() {
+            env::remove_var(name);
+        }
+
+        // All defaulted, even once a load copied the defaults into the environment
+        assert!(group.check().is_err());
+        crate::apply_settings(&HashMap::new(), &HashMap::new()).unwrap();
+        assert_eq!(env::var("GROUP_PASSWORD").unwrap(), "changeme");
+        match group.check() {
+            Err(EnvInventoryError::GroupConstraintViolated { members, reason }) => {
+                assert_eq!(members, group.members());
+                assert!(reason.contains("explicitly"));
+            }
+            other => panic!("expected a group violation, got {:?}", other),
+        }
+
+        // One explicitly set
+        env::set_var("GROUP_TOKEN", "t0ken");
+        assert!(group.check().is_ok());
+
+        for name in group.members() {
+            env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_registered_group() {
+        let group = inventory::iter::<Group>()
+            .find(|group| group.members().contains(&"GROUP_REGISTERED_A"))
+            .unwrap();
+        assert_eq!(group.kind(), GroupKind::AllOrNone);
+        assert_eq!(group.members(), &["GROUP_REGISTERED_A", "GROUP_REGISTERED_B"]);
+        assert_eq!(group.source(), file!());
+    }
+}
@@ -0,0 +1,113 @@
+//! Consistency rules spanning several variables.
+
+use crate::{map, EnvInventoryError};
+use std::collections::HashMap;
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct Invariant {
+    /// Evaluates the rule; `None` means it couldn't be evaluated.
+    pub check: fn(&ResolvedValues) -> Option<bool>,
+    /// The message reported when the rule doesn't hold.
+    pub message: &'static str,
+    /// The file the invariant was registered in.
+    pub source: &'static str,
+}
+
+inventory::collect!(Invariant);
+
+/// The resolved values of all registered variables, as passed to the rules
+/// registered with [`register_invariant!`](crate::register_invariant).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResolvedValues(HashMap<String, String>);
+
+impl ResolvedValues {
+    /// Gets the value of `name`, if it has one.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(String::as_str)
+    }
+
+    /// Gets the value of `name` as an integer, if it has one that parses.
+    pub fn get_int(&self, name: &str) -> Option<i64> {
+        self.get(name)?.trim().parse().ok()
+    }
+
+    /// Gets the value of `name` as a float, if it has one that parses.
+    pub fn get_float(&self, name: &str) -> Option<f64> {
+        self.get(name)?.trim().parse().ok()
+    }
+
+    /// Gets the value of `name` as a boolean, if it is `true` or `false`.
+    pub fn get_bool(&self, name: &str) -> Option<bool> {
+        self.get(name)?.trim().parse().ok()
+    }
+}
+
+impl<K: Into<String>, V: Into<String>> FromIterator<(K, V)> for ResolvedValues {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        Self(
+            iter.into_iter()
+                .map(|(name, value)| (name.into(), value.into()))
+                .collect(),
+        )
+    }
+}
+
+/// Checks every registered invariant against the current values.
+pub(crate) fn validate_invariants() -> Result<(), EnvInventoryError> {
+    let values: ResolvedValues = map().into_iter().collect();
+    check_invariants(inventory::iter::<Invariant>(), &values)
+}
+
+/// Checks `invariants` against `values`, failing on the first one that
+/// doesn't hold or can't be evaluated.
+pub(crate) fn check_invariants<'a, I>(
+    invariants: I,
+    values: &ResolvedValues,
+) -> Result<(), EnvInventoryError>
+where
+    I: IntoIterator<Item = &'a Invariant>,
+{
+    for invariant in invariants {
+        if (invariant.check)(values) != Some(true) {
+            return Err(EnvInventoryError::InvariantViolated {
+                message: invariant.message.to_string(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONNECTIONS: Invariant = crate::register_invariant!(
+        @invariant |vals| vals.get_int("MAX_CONNECTIONS")? >= vals.get_int("MIN_CONNECTIONS")?,
+        "max must be >= min"
+    );
+
+    #[test]
+    fn test_invariants() {
+        let values: ResolvedValues = [("MIN_CONNECTIONS", "2"), ("MAX_CONNECTIONS", "10")]
+            .into_iter()
+            .collect();
+        assert!(check_invariants(&[CONNECTIONS], &values).is_ok());
+
+        let values: ResolvedValues = [("MIN_CONNECTIONS", "20"), ("MAX_CONNECTIONS", "10")]
+            .into_iter()
+            .collect();
+        match check_invariants(&[CONNECTIONS], &values) {
+            Err(EnvInventoryError::InvariantViolated { message }) => {
+                assert_eq!(message, "max must be >= min");
+            }
+            other => panic!("expected a violated invariant, got {:?}", other),
+        }
+
+        // A rule that can't be evaluated doesn't hold
+        let values: ResolvedValues = [("MIN_CONNECTIONS", "2"), ("MAX_CONNECTIONS", "many")]
+            .into_iter()
+            .collect();
+        assert!(check_invariants(&[CONNECTIONS], &values).is_err());
+    }
+}
@@ -0,0 +1,451 @@
+//! JSON sources of config values: files, and blobs in the environment.
+//!
+//! Nested objects are flattened by joining keys with `_`, so
+//! `{"DB": {"HOST": "x"}}` provides `DB_HOST`. Numbers and booleans become
+//! their string form, arrays become comma-separated lists, and nulls are
+//! skipped.
+
+use crate::{
+    access, apply_settings, check_found_schema_version, effective_map, expected_schema_version,
+    EnvInventoryError, ExpectedType, RequiredVar, MASK,
+};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::Path;
+
+/// Loads config values from a JSON object stored in the environment variable
+/// `var`, then validates the registered variables.
+///
+/// Orchestration systems sometimes pass all config as one JSON blob, e.g.
+/// `APP_CONFIG_JSON={"DATABASE_URL": "...", "PORT": 8080}`. The keys of the
+/// blob take the place of a config file: they fill registered variables that
+/// aren't already set in the environment.
+///
+/// # Errors
+///
+/// * `MissingEnvVar`: If `var` isn't set.
+/// * `ParseFileError`: If `var` doesn't hold a JSON object.
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_from_json_env(var: &str) -> Result<(), EnvInventoryError> {
+    let settings = json_env_settings(var)?;
+    let origins = settings
+        .keys()
+        .map(|name| (name.clone(), format!("${}", var)))
+        .collect();
+    apply_settings(&settings, &origins)
+}
+
+/// Returns the resolved value of every registered variable as a JSON object
+/// mapping names to values, for passing the config to other systems.
+///
+/// Values are resolved as in [`effective_map`](crate::effective_map), and
+/// typed after the [`ExpectedType`] of their variable: integers and floats
+/// become numbers and booleans become `true` or `false`. Other values, and
+/// values that don't parse as their type, are strings. Variables without a
+/// value are omitted. The values of `secret` variables are included.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, to_json_value};
+/// register!(WORKERS: u16 = "4");
+/// register!(QUEUE = "jobs");
+///
+/// let config = to_json_value();
+/// assert_eq!(config["WORKERS"], serde_json::json!(4));
+/// assert_eq!(config["QUEUE"], serde_json::json!("jobs"));
+/// ```
+pub fn to_json_value() -> Value {
+    let mut types = HashMap::new();
+    for var in inventory::iter::<RequiredVar>() {
+        if let Some(expected) = var.expected_type {
+            types.entry(var.name).or_insert(expected);
+        }
+    }
+
+    let object = effective_map()
+        .into_iter()
+        .map(|(name, value)| {
+            let value = match types.get(name.as_str()) {
+                Some(expected) => typed(*expected, value),
+                None => Value::String(value),
+            };
+            (name, value)
+        })
+        .collect();
+    Value::Object(object)
+}
+
+/// Gets the value of a registered variable deserialized from JSON, for
+/// structured config passed as a string such as
+/// `FEATURE_FLAGS={"beta":true}`.
+///
+/// Returns `Ok(None)` if `name` isn't registered or has no value.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{get_json, register};
+/// # use std::collections::HashMap;
+/// register!(FEATURE_FLAGS = r#"{"beta": true, "dark_mode": false}"#);
+///
+/// let flags: HashMap<String, bool> = get_json("FEATURE_FLAGS").unwrap().unwrap();
+/// assert!(flags["beta"]);
+/// ```
+///
+/// # Errors
+///
+/// * `InvalidJson`: If the value isn't JSON, or doesn't deserialize as `T`.
+///   The reason is masked if the variable is a secret.
+pub fn get_json<T: DeserializeOwned>(name: &str) -> Result<Option<T>, EnvInventoryError> {
+    match access::read(name) {
+        Some((value, _)) => serde_json::from_str(&value).map(Some).map_err(|e| {
+            let secret =
+                inventory::iter::<RequiredVar>().any(|var| var.name == name && var.is_redacted());
+            EnvInventoryError::InvalidJson {
+                name: name.to_string(),
+                reason: if secret {
+                    MASK.to_string()
+                } else {
+                    e.to_string()
+                },
+            }
+        }),
+        None => Ok(None),
+    }
+}
+
+/// Converts `value` to the JSON value of the `expected` type, or a string if
+/// it doesn't parse as one.
+fn typed(expected: ExpectedType, value: String) -> Value {
+    let typed = match expected {
+        ExpectedType::Int => value.parse::<i64>().ok().map(Value::from),
+        ExpectedType::UInt => value.parse::<u64>().ok().map(Value::from),
+        ExpectedType::Float => value
+            .parse::<f64>()
+            .ok()
+            .and_then(serde_json::Number::from_f64)
+            .map(Value::Number),
+        ExpectedType::Bool => value.parse::<bool>().ok().map(Value::Bool),
+        ExpectedType::String => None,
+    };
+    typed.unwrap_or(Value::String(value))
+}
+
+/// Loads the settings of the `section` object of a JSON config file, or of
+/// the top-level object if `section` is empty.
+///
+/// The settings are flattened as described in the module docs. A missing
+/// section provides no settings, as with TOML files.
+pub(crate) fn load_json_settings<P: AsRef<Path>>(
+    path: P,
+    section: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+    parse_json_settings(&content, &path.display().to_string(), section)
+}
+
+/// Parses the settings of JSON `content` read from `source`, as
+/// [`load_json_settings`] does for files.
+pub(crate) fn parse_json_settings(
+    content: &str,
+    source: &str,
+    section: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let parse_error = || EnvInventoryError::ParseFileError(source.to_string());
+    let value: Value = serde_json::from_str(content).map_err(|_| parse_error())?;
+    if !value.is_object() {
+        return Err(parse_error());
+    }
+
+    check_found_schema_version(
+        expected_schema_version(),
+        value.get("schema_version").and_then(Value::as_i64),
+    )?;
+
+    let section = if section.is_empty() {
+        Some(&value)
+    } else {
+        let nested = || {
+            section
+                .split('.')
+                .try_fold(&value, |value, key| value.get(key))
+        };
+        value.get(section).or_else(nested)
+    };
+    match section {
+        Some(section) if section.is_object() => Ok(flatten(section)),
+        Some(_) => Err(parse_error()),
+        None => Ok(HashMap::new()),
+    }
+}
+
+fn json_env_settings(var: &str) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let blob = env::var(var).map_err(|_| EnvInventoryError::MissingEnvVar(var.to_string()))?;
+    let value: Value = serde_json::from_str(&blob)
+        .map_err(|_| EnvInventoryError::ParseFileError(format!("${}", var)))?;
+
+    if value.is_object() {
+        Ok(flatten(&value))
+    } else {
+        Err(EnvInventoryError::ParseFileError(format!("${}", var)))
+    }
+}
+
+/// Flattens a JSON object into a map of variable names to string values.
+pub(crate) fn flatten(value: &Value) -> HashMap<String, String> {
+    let mut settings = HashMap::new();
+    flatten_into("", value, &mut settings);
+    settings
+}
+
+fn flatten_into(prefix: &str, value: &Value, settings: &mut HashMap<String, String>) {
+    match value {
+        Value::Object(object) => {
+            for (key, value) in object {
+                let name = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}_{}", prefix, key)
+                };
+                flatten_into(&name, value, settings);
+            }
+        }
+        Value::Null => {}
+        value => {
+            if let Some(scalar) = scalar_string(value) {
+                settings.insert(prefix.to_string(), scalar);
+            }
+        }
+    }
+}
+
+fn scalar_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Array(items) => Some(
+            items
+                .iter()
+                .filter_map(scalar_string)
+                .collect::<Vec<_>>()
+                .join(","),
+        ),
+        Value::Null | Value::Object(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    crate::register!(JSON_DATABASE_URL = "postgres://localhost/default", JSON_PORT = "80");
+
+    crate::register!(JSON_WORKERS: u16 = "4");
+    crate::register!(JSON_OFFSET: i32 = "-2");
+    crate::register!(JSON_RATIO: f64 = "0.5");
+    crate::register!(JSON_VERBOSE: bool = "false");
+    crate::register!(JSON_FEATURE_FLAGS = r#"{"beta": true, "rollout": 25}"#);
+    crate::register!(JSON_SECRET_FLAGS = r#"{"beta": true, "rollout": 25}"#; secret);
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct FeatureFlags {
+        beta: bool,
+        rollout: u8,
+    }
+
+    #[test]
+    fn test_get_json() {
+        let _guard = crate::env_lock();
+        env::remove_var("JSON_FEATURE_FLAGS");
+        assert_eq!(
+            get_json::<FeatureFlags>("JSON_FEATURE_FLAGS").unwrap(),
+            Some(FeatureFlags {
+                beta: true,
+                rollout: 25
+            })
+        );
+        assert_eq!(get_json::<FeatureFlags>("JSON_UNREGISTERED").unwrap(), None);
+
+        for value in [r#"{"beta": true"#, r#"{"beta": "yes", "rollout": 25}"#] {
+            env::set_var("JSON_FEATURE_FLAGS", value);
+            match get_json::<FeatureFlags>("JSON_FEATURE_FLAGS") {
+                Err(EnvInventoryError::InvalidJson { name, .. }) => {
+                    assert_eq!(name, "JSON_FEATURE_FLAGS");
+                }
+                other => panic!("expected invalid JSON, got {:?}", other),
+            }
+        }
+        env::remove_var("JSON_FEATURE_FLAGS");
+
+        // The reason may quote the value, so it's masked for secrets
+        env::set_var("JSON_SECRET_FLAGS", r#"{"beta": "hunter2", "rollout": 25}"#);
+        match get_json::<FeatureFlags>("JSON_SECRET_FLAGS") {
+            Err(EnvInventoryError::InvalidJson { reason, .. }) => assert_eq!(reason, "****"),
+            other => panic!("expected invalid JSON, got {:?}", other),
+        }
+        env::remove_var("JSON_SECRET_FLAGS");
+    }
+
+    #[test]
+    fn test_to_json_value() {
+        let _guard = crate::env_lock();
+        for name in [
+            "JSON_WORKERS",
+            "JSON_OFFSET",
+            "JSON_RATIO",
+            "JSON_VERBOSE",
+            "JSON_PORT",
+        ] {
+            env::remove_var(name);
+        }
+
+        let config = to_json_value();
+        assert_eq!(config["JSON_WORKERS"], Value::from(4));
+        assert!(config["JSON_WORKERS"].is_u64());
+        assert_eq!(config["JSON_OFFSET"], Value::from(-2));
+        assert_eq!(config["JSON_RATIO"], Value::from(0.5));
+        assert_eq!(config["JSON_VERBOSE"], Value::Bool(false));
+        // Untyped values stay strings
+        assert_eq!(config["JSON_PORT"], Value::String("80".to_string()));
+
+        // So do values that don't parse as their type
+        env::set_var("JSON_WORKERS", "many");
+        assert_eq!(
+            to_json_value()["JSON_WORKERS"],
+            Value::String("many".to_string())
+        );
+        env::remove_var("JSON_WORKERS");
+    }
+
+    #[test]
+    fn test_load_from_json_env() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        env::remove_var("JSON_DATABASE_URL");
+        env::remove_var("JSON_PORT");
+        env::set_var(
+            "JSON_APP_CONFIG",
+            r#"{"JSON_DATABASE_URL": "postgres://db/app", "JSON_PORT": 8080}"#,
+        );
+
+        load_from_json_env("JSON_APP_CONFIG").unwrap();
+        assert_eq!(env::var("JSON_DATABASE_URL").unwrap(), "postgres://db/app");
+        assert_eq!(env::var("JSON_PORT").unwrap(), "8080");
+
+        env::remove_var("JSON_APP_CONFIG");
+        env::remove_var("JSON_DATABASE_URL");
+        env::remove_var("JSON_PORT");
+    }
+
+    #[test]
+    fn test_json_env_errors() {
+        let _guard = crate::env_lock();
+        env::remove_var("JSON_UNSET_BLOB");
+        assert!(matches!(
+            load_from_json_env("JSON_UNSET_BLOB"),
+            Err(EnvInventoryError::MissingEnvVar(_))
+        ));
+
+        env::set_var("JSON_ARRAY_BLOB", "[1, 2]");
+        assert!(matches!(
+            load_from_json_env("JSON_ARRAY_BLOB"),
+            Err(EnvInventoryError::ParseFileError(_))
+        ));
+        env::remove_var("JSON_ARRAY_BLOB");
+    }
+
+    #[test]
+    fn test_load_json_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.json");
+        fs::write(
+            &path,
+            r#"{
+                "env": {
+                    "DATABASE_URL": "postgres://db/app",
+                    "PORT": 8080,
+                    "RATIO": 0.5,
+                    "DEBUG": false,
+                    "POOL": {"MIN": 1, "MAX": 10}
+                },
+                "other": {"IGNORED": "yes"}
+            }"#,
+        )
+        .unwrap();
+
+        let settings = load_json_settings(&path, "env").unwrap();
+        assert_eq!(settings["DATABASE_URL"], "postgres://db/app");
+        assert_eq!(settings["PORT"], "8080");
+        assert_eq!(settings["RATIO"], "0.5");
+        assert_eq!(settings["DEBUG"], "false");
+        assert_eq!(settings["POOL_MIN"], "1");
+        assert_eq!(settings["POOL_MAX"], "10");
+        assert!(!settings.contains_key("IGNORED"));
+
+        assert!(load_json_settings(&path, "missing").unwrap().is_empty());
+        assert_eq!(
+            load_json_settings(&path, "").unwrap()["other_IGNORED"],
+            "yes"
+        );
+
+        fs::write(&path, r#"{"env": "not an object"}"#).unwrap();
+        assert!(matches!(
+            load_json_settings(&path, "env"),
+            Err(EnvInventoryError::ParseFileError(_))
+        ));
+        fs::write(&path, "{ not json").unwrap();
+        assert!(matches!(
+            load_json_settings(&path, "env"),
+            Err(EnvInventoryError::ParseFileError(_))
+        ));
+    }
+
+    #[test]
+    fn test_load_json_file() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        let dir = tempfile::tempdir().unwrap();
+        let toml = dir.path().join("app.toml");
+        let json = dir.path().join("local.json");
+        env::remove_var("JSON_DATABASE_URL");
+        env::remove_var("JSON_PORT");
+        fs::write(
+            &toml,
+            "[env]\nJSON_DATABASE_URL = \"postgres://toml/app\"\n",
+        )
+        .unwrap();
+        fs::write(&json, r#"{"env": {"JSON_PORT": 9090}}"#).unwrap();
+
+        crate::load_and_validate_env_vars(&[&toml, &json], "env").unwrap();
+        assert_eq!(
+            env::var("JSON_DATABASE_URL").unwrap(),
+            "postgres://toml/app"
+        );
+        assert_eq!(env::var("JSON_PORT").unwrap(), "9090");
+        env::remove_var("JSON_DATABASE_URL");
+        env::remove_var("JSON_PORT");
+    }
+
+    #[test]
+    fn test_flatten() {
+        let value: Value = serde_json::from_str(
+            r#"{"DB": {"HOST": "db", "PORT": 5432}, "DEBUG": true, "TAGS": ["a", "b"], "UNSET": null}"#,
+        )
+        .unwrap();
+        let settings = flatten(&value);
+
+        assert_eq!(settings["DB_HOST"], "db");
+        assert_eq!(settings["DB_PORT"], "5432");
+        assert_eq!(settings["DEBUG"], "true");
+        assert_eq!(settings["TAGS"], "a,b");
+        assert!(!settings.contains_key("UNSET"));
+    }
+}
@@ -0,0 +1,127 @@
+//! Key-value stores as sources of config values.
+//!
+//! Stores such as Redis, etcd or Consul can hold config that changes without
+//! redeploying. The crate ships no backend; implement [`KvStore`] over your
+//! client and pass it to [`load_from_kv_store`].
+
+use crate::{apply_settings, EnvInventoryError};
+use std::collections::HashMap;
+
+/// A key-value store holding config values.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::KvStore;
+/// # use std::collections::BTreeMap;
+/// struct InMemory(BTreeMap<String, String>);
+///
+/// impl KvStore for InMemory {
+///     fn get(&self, key: &str) -> Option<String> {
+///         self.0.get(key).cloned()
+///     }
+///
+///     fn list(&self, prefix: &str) -> Vec<(String, String)> {
+///         self.0
+///             .iter()
+///             .filter(|(key, _)| key.starts_with(prefix))
+///             .map(|(key, value)| (key.clone(), value.clone()))
+///             .collect()
+///     }
+/// }
+/// ```
+pub trait KvStore {
+    /// Gets the value stored under `key`, if any.
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Lists every key starting with `prefix`, with its value. The keys are
+    /// returned in full, prefix included.
+    fn list(&self, prefix: &str) -> Vec<(String, String)>;
+}
+
+/// Loads config values from the keys of `store` under `prefix`, then
+/// validates the registered variables.
+///
+/// The key `{prefix}NAME` provides the variable `NAME`, so with the prefix
+/// `app/` the key `app/DATABASE_URL` provides `DATABASE_URL`. The store takes
+/// the place of a config file: the environment wins over it, and it wins over
+/// the platform source and defaults.
+///
+/// # Errors
+///
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_from_kv_store<S: KvStore + ?Sized>(
+    store: &S,
+    prefix: &str,
+) -> Result<(), EnvInventoryError> {
+    let settings: HashMap<String, String> = store
+        .list(prefix)
+        .into_iter()
+        .filter_map(|(key, value)| Some((key.strip_prefix(prefix)?.to_string(), value)))
+        .collect();
+    let origins = settings
+        .keys()
+        .map(|name| (name.clone(), format!("kv:{}{}", prefix, name)))
+        .collect();
+    apply_settings(&settings, &origins)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::env;
+
+    crate::register!(KV_DATABASE_URL = "postgres://localhost/default");
+    crate::register!(KV_POOL_SIZE = "4");
+    crate::register!(KV_REGION = "local");
+
+    struct InMemory(BTreeMap<String, String>);
+
+    impl KvStore for InMemory {
+        fn get(&self, key: &str) -> Option<String> {
+            self.0.get(key).cloned()
+        }
+
+        fn list(&self, prefix: &str) -> Vec<(String, String)> {
+            self.0
+                .iter()
+                .filter(|(key, _)| key.starts_with(prefix))
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect()
+        }
+    }
+
+    #[test]
+    fn test_load_from_kv_store() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        env::remove_var("KV_DATABASE_URL");
+        env::remove_var("KV_REGION");
+        env::set_var("KV_POOL_SIZE", "16");
+        let store = InMemory(
+            [
+                ("app/KV_DATABASE_URL", "postgres://kv/app"),
+                ("app/KV_POOL_SIZE", "8"),
+                ("other/KV_REGION", "eu-west-1"),
+            ]
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect(),
+        );
+        assert_eq!(store.get("app/KV_POOL_SIZE").as_deref(), Some("8"));
+
+        load_from_kv_store(&store, "app/").unwrap();
+        // The store wins over defaults...
+        assert_eq!(env::var("KV_DATABASE_URL").unwrap(), "postgres://kv/app");
+        // ...but not over the environment
+        assert_eq!(env::var("KV_POOL_SIZE").unwrap(), "16");
+        // Keys outside the prefix are ignored
+        assert_eq!(env::var("KV_REGION").unwrap(), "local");
+
+        for name in ["KV_DATABASE_URL", "KV_POOL_SIZE", "KV_REGION"] {
+            env::remove_var(name);
+        }
+    }
+}
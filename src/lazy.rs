@@ -0,0 +1,175 @@
+//! Lazily resolved, cached configuration.
+//!
+//! Walking the inventory and the environment on every read is wasteful for
+//! apps that read config often. [`LazyConfig`] resolves all registered
+//! variables on first access and serves reads from the cache until it is
+//! explicitly invalidated, e.g. after a reload signal, or its time to live
+//! elapses.
+
+use crate::effective_map;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+
+/// A thread-safe cache of the resolved values of all registered variables.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, LazyConfig};
+/// register!(WORKERS = "4");
+///
+/// static CONFIG: LazyConfig = LazyConfig::new();
+///
+/// assert_eq!(CONFIG.get("WORKERS").as_deref(), Some("4"));
+///
+/// // After a reload, force the next read to resolve again
+/// CONFIG.invalidate();
+/// ```
+#[derive(Debug, Default)]
+pub struct LazyConfig {
+    cache: RwLock<Option<Cached>>,
+    resolutions: AtomicU64,
+    ttl: Option<Duration>,
+}
+
+#[derive(Debug)]
+struct Cached {
+    values: HashMap<String, String>,
+    resolved_at: Instant,
+}
+
+impl LazyConfig {
+    /// Creates an empty cache; nothing is resolved until the first read.
+    pub const fn new() -> Self {
+        Self {
+            cache: RwLock::new(None),
+            resolutions: AtomicU64::new(0),
+            ttl: None,
+        }
+    }
+
+    /// Creates an empty cache whose values expire `ttl` after they were
+    /// resolved, so the first read after that resolves them again.
+    ///
+    /// This picks up config changed externally without a reload signal.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use env_inventory::LazyConfig;
+    /// # use std::time::Duration;
+    /// static CONFIG: LazyConfig = LazyConfig::with_ttl(Duration::from_secs(30));
+    /// ```
+    pub const fn with_ttl(ttl: Duration) -> Self {
+        Self {
+            cache: RwLock::new(None),
+            resolutions: AtomicU64::new(0),
+            ttl: Some(ttl),
+        }
+    }
+
+    /// Gets the cached value of `name`, resolving all variables first if the
+    /// cache is empty.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.with_values(|values| values.get(name).cloned())
+    }
+
+    /// Gets a copy of all cached values, resolving them first if the cache is
+    /// empty.
+    pub fn values(&self) -> HashMap<String, String> {
+        self.with_values(HashMap::clone)
+    }
+
+    /// Drops the cached values, so the next read resolves them again.
+    pub fn invalidate(&self) {
+        *self.cache.write().unwrap_or_else(|e| e.into_inner()) = None;
+    }
+
+    /// How many times the values have been resolved, rather than served from
+    /// the cache.
+    pub fn resolutions(&self) -> u64 {
+        self.resolutions.load(Ordering::Relaxed)
+    }
+
+    fn with_values<T, F>(&self, f: F) -> T
+    where
+        F: Fn(&HashMap<String, String>) -> T,
+    {
+        {
+            let cache = self.cache.read().unwrap_or_else(|e| e.into_inner());
+            if let Some(cached) = cache.as_ref().filter(|cached| self.is_fresh(cached)) {
+                return f(&cached.values);
+            }
+        }
+
+        let mut cache = self.cache.write().unwrap_or_else(|e| e.into_inner());
+        // Another thread may have resolved while we waited for the lock
+        if !matches!(cache.as_ref(), Some(cached) if self.is_fresh(cached)) {
+            self.resolutions.fetch_add(1, Ordering::Relaxed);
+            *cache = Some(Cached {
+                values: effective_map(),
+                resolved_at: Instant::now(),
+            });
+        }
+        f(&cache.as_ref().expect("cache was just filled").values)
+    }
+
+    fn is_fresh(&self, cached: &Cached) -> bool {
+        match self.ttl {
+            Some(ttl) => cached.resolved_at.elapsed() < ttl,
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    crate::register!(LAZY_VAR = "initial");
+    crate::register!(LAZY_TTL_VAR = "initial");
+
+    #[test]
+    fn test_lazy_config() {
+        let _guard = crate::env_lock();
+        let config = LazyConfig::new();
+        env::remove_var("LAZY_VAR");
+        assert_eq!(config.resolutions(), 0);
+
+        assert_eq!(config.get("LAZY_VAR").as_deref(), Some("initial"));
+        assert_eq!(config.resolutions(), 1);
+
+        // Served from the cache, even though the environment changed
+        env::set_var("LAZY_VAR", "changed");
+        assert_eq!(config.get("LAZY_VAR").as_deref(), Some("initial"));
+        assert_eq!(config.values().get("LAZY_VAR").map(String::as_str), Some("initial"));
+        assert_eq!(config.resolutions(), 1);
+
+        config.invalidate();
+        assert_eq!(config.get("LAZY_VAR").as_deref(), Some("changed"));
+        assert_eq!(config.resolutions(), 2);
+        env::remove_var("LAZY_VAR");
+    }
+
+    #[test]
+    fn test_ttl() {
+        let _guard = crate::env_lock();
+        let config = LazyConfig::with_ttl(Duration::from_millis(200));
+        env::remove_var("LAZY_TTL_VAR");
+
+        assert_eq!(config.get("LAZY_TTL_VAR").as_deref(), Some("initial"));
+
+        // Served from the cache until the TTL elapses
+        env::set_var("LAZY_TTL_VAR", "changed");
+        assert_eq!(config.get("LAZY_TTL_VAR").as_deref(), Some("initial"));
+        assert_eq!(config.resolutions(), 1);
+
+        std::thread::sleep(Duration::from_millis(250));
+        assert_eq!(config.get("LAZY_TTL_VAR").as_deref(), Some("changed"));
+        assert_eq!(config.resolutions(), 2);
+        env::remove_var("LAZY_TTL_VAR");
+    }
+}
@@ -17,9 +17,8 @@
 //!
 //! Usage involves registering variables using the provided macros, and then
 //! employing the provided utilities to load and validate these variables either
-//! from the environment or TOML files.
+//! from the environment or TOML and `.env` files.
 //!
-//! Note: `dotenv` file support isn't available currently.
 //! Note: This crate is still in its early stages and is subject to change.
 //! Note: `shell-expansions` (probably using
 //! [https://docs.rs/shellexpand/latest/shellexpand/fn.tilde.html](shellexpand))
@@ -32,11 +31,103 @@ pub extern crate inventory;
 extern crate thiserror;
 extern crate toml;
 
+mod access;
+mod audit;
+mod case;
+mod clobber;
+mod de;
+mod diagnostic;
+mod dotenv;
+mod drift;
+mod encoding;
+mod expand;
+mod generation;
+mod graph;
+mod group;
+mod invariant;
+#[cfg(feature = "json")]
+mod json;
+mod kv;
+mod lazy;
+mod list;
+mod loader;
+mod missing;
+mod output;
+mod placeholder;
+mod platform;
+mod provenance;
+mod report;
+mod resolve;
+#[cfg(feature = "remote-schema")]
+mod schema;
+mod scope;
+mod sections;
+mod sensitivity;
+mod sync;
+#[cfg(feature = "tokio")]
+mod task;
+#[cfg(feature = "metrics")]
+mod telemetry;
+mod template;
+#[cfg(feature = "test-util")]
+mod test_util;
+mod units;
+mod validator;
+#[cfg(feature = "yaml")]
+mod yaml;
+
+pub use access::access_counts;
+pub use audit::{
+    assert_registry_populated, conflicts, find_case_collisions, find_similar_names,
+    validate_completeness, validate_registry, Conflict, Registration,
+};
+pub use case::{is_case_insensitive, set_case_insensitive};
+pub use clobber::would_clobber;
+pub use diagnostic::{set_diagnostic_hook, Diagnostic};
+pub use dotenv::load_dotenv;
+pub use drift::{drift_since_init, ConfigChange};
+pub use encoding::get_base64;
+pub use expand::{expansion_style, set_expansion_style, ExpansionStyle};
+pub use generation::generation;
+pub use graph::{dependents_of, expansion_graph};
+pub use group::{Group, GroupKind};
+#[doc(hidden)]
+pub use invariant::Invariant;
+pub use invariant::ResolvedValues;
+#[cfg(feature = "json")]
+pub use json::{get_json, load_from_json_env, to_json_value};
+pub use kv::{load_from_kv_store, KvStore};
+pub use lazy::LazyConfig;
+pub use list::{get_list, get_list_with};
+pub use loader::{ConfigFormat, Loader, LoaderConfig};
+pub use missing::{default_missing_format, error_message_for, set_missing_formatter, MissingVar};
+pub use output::{export_overrides_toml, export_shell_script, write_effective_config, OutputFormat};
+pub use placeholder::register_placeholder;
+pub use platform::{set_platform_source, NoPlatformSource, PlatformSource};
+pub use provenance::{dump_effective_config_toml, resolved_with_source};
+pub use report::{env_report, EnvReport};
+#[cfg(feature = "remote-schema")]
+pub use schema::{validate_against_schema_url, validate_against_schema_url_with};
+pub use scope::{require_during, ValidationScope};
+pub use sections::Sections;
+pub use sensitivity::{redaction_level, set_redaction_level, Sensitivity};
+pub use sync::clear_loaded_vars;
+#[cfg(feature = "tokio")]
+pub use task::{current_config, with_config};
+pub use template::export_env_template;
+#[cfg(feature = "test-util")]
+pub use test_util::assert_config_matches_golden;
+pub use units::get_bytes;
+pub use validator::register_validator;
+
+use provenance::Origin;
+use resolve::Step;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 use toml::Value;
 
@@ -49,7 +140,7 @@ use toml::Value;
 ///
 /// # Examples
 ///
-/// ```rust (ignore)
+/// ```rust,ignore
 /// # #[macro_use] extern crate env_inventory;
 /// # fn main() {
 /// register!("DATABASE_URL", "REDIS_URL", "API_KEY");
@@ -69,6 +160,77 @@ use toml::Value;
 /// - `$($var:expr => $default:expr),*`: A comma-separated list of pairs, where
 ///   each pair consists of a string literal representing an environment variable
 ///   and its default value.
+/// - `$var:ident: $type:ident $(= $default:expr)? $(; $($option);+)?`: A
+///   single variable with an expected type, one of `String`, the integer
+///   types, `f32`, `f64` or `bool`, such as `register!(PORT: u16 = "8080")`.
+///   Validation fails with `EnvInventoryError::InvalidType` if the value
+///   doesn't parse as the [`ExpectedType`], and with
+///   `EnvInventoryError::InvalidValue` if an integer is out of the range of
+///   the given type, such as `70000` for a `u16`. Options may follow as
+///   below.
+/// - `$var:ident $(= $default:expr)?; $($option);+`: A single variable,
+///   optionally with a default, followed by `;`-separated options:
+///   - `Binary`, `Library` or `Unknown`: the priority of the default.
+///   - `required`: the variable must be provided explicitly; defaults never
+///     satisfy it (see [`validate_registry`]).
+///   - `platform`: fall back to the installed [`PlatformSource`] when the
+///     variable isn't set.
+///   - `error = "message"`: a custom message reported in place of
+///     `(missing)` when the variable is missing.
+///   - `commands = ["server", ...]`: only require the variable when validating
+///     for one of the listed subcommands (see [`validate_for_command`]).
+///   - `secret`: the value is sensitive and is redacted from dumps such as
+///     [`dump_effective_config_toml`] and [`list_all_vars_redacted`], and
+///     from the `Debug` and `Display` forms of the variable.
+///   - `internal`: the value may be shown within the organization; it is
+///     only redacted if [`set_redaction_level`] says so (see
+///     [`Sensitivity`]).
+///   - `base64`: the value is base64-encoded; validation fails if it doesn't
+///     decode (see [`get_base64`]).
+///   - `feature = "name"`: only register the variable when the calling crate
+///     is built with the cargo feature `name`, so a crate can ship
+///     feature-selected defaults. This must be the first option.
+///   - `assert = |value| ..., "message"`: a predicate over the value, checked
+///     during validation; if it returns `false`, validation fails with
+///     `EnvInventoryError::AssertionFailed` carrying the message.
+///   - `example = "value"`: an example value shown in generated templates
+///     (see [`export_env_template`]); it is never used as a value.
+///   - `type = u16`: the expected type, as with the typed form
+///     `PORT: u16` above.
+///   - `allowed = ["fast", "slow"]`: the values the variable may take, as
+///     with the `in` form below.
+///   - `path`: the value is a filesystem path.
+///   - `absolute`: the value is a path that must be absolute once expanded,
+///     so it doesn't depend on the working directory; validation fails with
+///     `EnvInventoryError::PathNotAbsolute` otherwise. Implies `path`.
+///   - `non_empty`: validation fails with `EnvInventoryError::EmptyValue` if
+///     the value is empty or only whitespace, as when the variable is
+///     declared as `TOKEN=` but never filled in.
+///   - `optional`: the variable is never reported as missing, though it
+///     has no default; it is still listed and readable when set. `NAME?`
+///     is a shorthand, as in `register!(PROXY_URL?)`.
+///   - `reject_placeholders`: validation fails with
+///     `EnvInventoryError::PlaceholderValue` if the value is a placeholder
+///     left over from a template, such as `changeme`, `TODO` or `<set-me>`
+///     (see [`register_placeholder`]).
+/// - `$var:ident $(= $default:literal)? in [$($allowed:literal),+] $(; $($option);+)?`:
+///   A single variable restricted to the listed values, such as
+///   `register!(MODE = "fast" in ["fast", "slow", "balanced"])`. Validation
+///   fails with `EnvInventoryError::InvalidValue` listing the allowed values
+///   if the value isn't one of them. A default that isn't one of them fails
+///   to compile; with the `allowed` option, it is reported by
+///   [`validate_registry`] instead. Options may follow as above.
+///
+/// ```rust
+/// # use env_inventory::register;
+/// register!(MODE = "fast" in ["fast", "slow", "balanced"]);
+/// register!(REGION in ["eu", "us"]; error = "set REGION to eu or us");
+/// ```
+///
+/// ```rust,compile_fail
+/// # use env_inventory::register;
+/// register!(MODE = "turbo" in ["fast", "slow"]);
+/// ```
 ///
 /// # Panics
 ///
@@ -76,16 +238,31 @@ use toml::Value;
 /// not string literals or if the pairs don't have the appropriate structure.
 #[macro_export]
 macro_rules! register {
+    ($var:ident = $default:literal in [$($allowed:literal),+ $(,)?] $(; $($opts:tt)+)?) => {
+        const _: () = assert!(
+            $crate::__contains_str(&[$($allowed),+], $default),
+            concat!("the default of ", stringify!($var), " is not one of its allowed values")
+        );
+        $crate::register!($var = $default; $($($opts)+;)? allowed = [$($allowed),+]);
+    };
+
+    ($var:ident in [$($allowed:literal),+ $(,)?] $(; $($opts:tt)+)?) => {
+        $crate::register!($var; $($($opts)+;)? allowed = [$($allowed),+]);
+    };
+
+    ($var:ident ? $(; $($opts:tt)+)?) => {
+        $crate::register!($var; $($($opts)+;)? optional);
+    };
+
     ($var:ident) => {
         const _: () = {
             use $crate::RequiredVar;
-            $crate::inventory::submit!(RequiredVar::new(stringify!($var)));
+            $crate::inventory::submit!(RequiredVar::new(stringify!($var)).with_source(file!()));
         };
     };
 
     ($($var:ident),* $(,)?) => {
         const _: () = {
-            use $crate::RequiredVar;
             $(
                 $crate::register!($var);
             )*
@@ -96,40 +273,44 @@ macro_rules! register {
     ($var:ident = $default:expr) => {
         const _: () = {
             use $crate::RequiredVar;
-            $crate::inventory::submit!(RequiredVar {
-                name: stringify!($var),
-                default: Some($default),
-                source: file!(),
-                priority: $crate::Priority::Library,
-            });
+            $crate::inventory::submit!(RequiredVar::new(stringify!($var))
+                .with_default($default)
+                .with_source(file!()));
         };
     };
 
     ($($var:ident = $default:expr),* $(,)?) => {
         const _: () = {
-            use $crate::RequiredVar;
             $(
                 $crate::register!($var = $default);
             )*
         };
     };
 
-    ($var:ident = $default:expr; $priority:ident) => {
+    ($var:ident : $ty:ident $(= $default:expr)? $(; $($opts:tt)+)?) => {
+        $crate::register!($var $(= $default)?; $($($opts)+;)? type = $ty);
+    };
+
+    ($var:ident $(= $default:expr)?; feature = $feature:literal $(; $($opts:tt)+)?) => {
+        #[cfg(feature = $feature)]
+        $crate::register!($var $(= $default)? $(; $($opts)+)?);
+    };
+
+    ($var:ident $(= $default:expr)?; $($opts:tt)+) => {
         const _: () = {
             use $crate::RequiredVar;
-            $crate::inventory::submit!(RequiredVar {
-                name: stringify!($var),
-                default: Some($default),
-                source: file!(),
-                priority: $crate::Priority::$priority,
-            });
+            $crate::inventory::submit!($crate::__register_opts!(
+                RequiredVar::new(stringify!($var))
+                    $(.with_default($default))?
+                    .with_source(file!());
+                $($opts)+
+            ));
         };
     };
 
 
     ($($var:ident = $default:expr),* $(,)?; $priority:ident) => {
         const _: () = {
-            use $crate::RequiredVar;
             $(
                 $crate::register!($var = $default; $priority);
             )*
@@ -138,6 +319,422 @@ macro_rules! register {
 
 }
 
+/// Registers environment variables under a common prefix, joined to each
+/// name with `_`.
+///
+/// The variables are registered under their full names, so lookups and
+/// validation work as for [`register!`]; [`vars_with_prefix`] lists them.
+/// Defaults may be given as with `register!`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register_prefixed, vars_with_prefix};
+/// register_prefixed!("MYAPP", DATABASE_URL, REDIS_URL = "redis://localhost");
+///
+/// let names: Vec<&str> = vars_with_prefix("MYAPP").iter().map(|var| var.name()).collect();
+/// assert_eq!(names, vec!["MYAPP_DATABASE_URL", "MYAPP_REDIS_URL"]);
+/// ```
+#[macro_export]
+macro_rules! register_prefixed {
+    ($prefix:literal, $($var:ident $(= $default:expr)?),+ $(,)?) => {
+        $(
+            const _: () = {
+                use $crate::RequiredVar;
+                $crate::inventory::submit!(RequiredVar::new(concat!($prefix, "_", stringify!($var)))
+                    $(.with_default($default))?
+                    .with_source(file!()));
+            };
+        )+
+    };
+}
+
+/// Registers an environment variable computed from other variables.
+///
+/// When the variable isn't set explicitly, `compute` is called with the
+/// values of the `from` variables, in order, once they all resolve. A derived
+/// variable is never required from the environment: it is only missing if
+/// one of its inputs is.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, register_derived, RequiredVar};
+/// fn build_url(parts: &[&str]) -> String {
+///     format!("{}://{}:{}", parts[0], parts[1], parts[2])
+/// }
+///
+/// register!(SCHEME = "https", HOST = "example.com", PORT = "8443");
+/// register_derived!(FULL_URL, from = [SCHEME, HOST, PORT], compute = build_url);
+///
+/// let full_url = env_inventory::resolve_one("FULL_URL").unwrap().0;
+/// assert_eq!(full_url, "https://example.com:8443");
+/// ```
+#[macro_export]
+macro_rules! register_derived {
+    ($var:ident, from = [$($input:ident),+ $(,)?], compute = $compute:expr $(,)?) => {
+        const _: () = {
+            use $crate::RequiredVar;
+            $crate::inventory::submit!(RequiredVar::new(stringify!($var))
+                .with_source(file!())
+                .with_derivation($crate::Derivation {
+                    from: &[$(stringify!($input)),+],
+                    compute: $compute,
+                }));
+        };
+    };
+}
+
+/// Registers an environment variable whose default depends on the value of
+/// another variable.
+///
+/// When the variable isn't set explicitly, the value of `based_on` is looked
+/// up (in the environment, or in the loaded configuration files while
+/// loading) and matched against `cases`. The first matching case supplies the
+/// default; otherwise the optional `default` is used.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::register_conditional;
+/// register_conditional!(
+///     LOG_LEVEL,
+///     based_on = "ENVIRONMENT",
+///     cases = { "production" => "warn", "staging" => "info" },
+///     default = "debug"
+/// );
+/// ```
+///
+/// With `ENVIRONMENT=production`, `LOG_LEVEL` defaults to `"warn"`; with any
+/// other (or no) value of `ENVIRONMENT`, it defaults to `"debug"`.
+#[macro_export]
+macro_rules! register_conditional {
+    (
+        $var:ident,
+        based_on = $based_on:expr,
+        cases = { $($case:expr => $value:expr),* $(,)? }
+        $(, default = $default:expr)?
+        $(,)?
+    ) => {
+        const _: () = {
+            use $crate::RequiredVar;
+            $crate::inventory::submit!(RequiredVar::new(stringify!($var))
+                $(.with_default($default))?
+                .with_source(file!())
+                .with_condition($crate::Condition {
+                    based_on: $based_on,
+                    cases: &[$(($case, $value)),*],
+                }));
+        };
+    };
+}
+
+/// Registers a constraint over a group of environment variables, checked by
+/// [`validate_env_vars`] and [`validate_for_command`].
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::register_group;
+/// // Either configure the database completely, or not at all
+/// register_group!(all_or_none = [DB_HOST, DB_USER, DB_PASS]);
+///
+/// // At least one credential must be configured, not just defaulted
+/// register_group!(at_least_one_explicit = [PASSWORD, TOKEN, CERT]);
+/// ```
+///
+/// Validation fails with `EnvInventoryError::GroupConstraintViolated` when
+/// only some of `DB_HOST`, `DB_USER` and `DB_PASS` are set, or when none of
+/// `PASSWORD`, `TOKEN` and `CERT` is set other than by a default.
+#[macro_export]
+macro_rules! register_group {
+    (all_or_none = [$($var:ident),+ $(,)?]) => {
+        $crate::register_group!(@kind AllOrNone [$($var),+]);
+    };
+    (at_least_one_explicit = [$($var:ident),+ $(,)?]) => {
+        $crate::register_group!(@kind AtLeastOneExplicit [$($var),+]);
+    };
+    (@kind $kind:ident [$($var:ident),+]) => {
+        const _: () = {
+            $crate::inventory::submit!($crate::Group::new(
+                $crate::GroupKind::$kind,
+                &[$(stringify!($var)),+],
+            )
+            .with_source(file!()));
+        };
+    };
+}
+
+/// Registers a consistency rule over the resolved values of several
+/// variables, checked by [`validate_env_vars`] after the values resolve.
+///
+/// The rule is a closure taking the [`ResolvedValues`] and returning a
+/// `bool`, in which the typed getters can be used with `?`. If it returns
+/// `false`, or a value is missing or doesn't parse, validation fails with
+/// `EnvInventoryError::InvariantViolated` carrying the message.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, register_invariant, validate_env_vars, EnvInventoryError};
+/// register!(MIN_CONNECTIONS = "2", MAX_CONNECTIONS = "10");
+/// register_invariant!(
+///     |vals| vals.get_int("MAX_CONNECTIONS")? >= vals.get_int("MIN_CONNECTIONS")?,
+///     "max must be >= min"
+/// );
+///
+/// validate_env_vars().unwrap();
+///
+/// std::env::set_var("MIN_CONNECTIONS", "20");
+/// assert!(matches!(
+///     validate_env_vars(),
+///     Err(EnvInventoryError::InvariantViolated { .. })
+/// ));
+/// ```
+#[macro_export]
+macro_rules! register_invariant {
+    (|$vals:ident| $body:expr, $message:expr $(,)?) => {
+        const _: () = {
+            $crate::inventory::submit!($crate::register_invariant!(
+                @invariant |$vals| $body,
+                $message
+            ));
+        };
+    };
+    (@invariant |$vals:ident| $body:expr, $message:expr) => {
+        $crate::Invariant {
+            check: |$vals| Some($body),
+            message: $message,
+            source: file!(),
+        }
+    };
+}
+
+/// Registers a variable whose value must parse as a Rust enum, or any other
+/// type implementing `FromStr`.
+///
+/// Validation fails with `EnvInventoryError::ParseValueError` if the value
+/// doesn't parse; read the parsed value with [`get_enum`]. The default may
+/// be given as a variant name, which must parse as the enum, or as a string.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{get_enum, register_enum};
+/// # use std::str::FromStr;
+/// #[derive(Debug, PartialEq)]
+/// enum LogLevel {
+///     Debug,
+///     Info,
+/// }
+///
+/// impl FromStr for LogLevel {
+///     type Err = String;
+///
+///     fn from_str(s: &str) -> Result<Self, Self::Err> {
+///         match s.to_ascii_lowercase().as_str() {
+///             "debug" => Ok(LogLevel::Debug),
+///             "info" => Ok(LogLevel::Info),
+///             _ => Err(format!("unknown log level {}", s)),
+///         }
+///     }
+/// }
+///
+/// register_enum!(LogLevel, "LOG_LEVEL", default = Info);
+///
+/// assert_eq!(get_enum::<LogLevel>("LOG_LEVEL").unwrap(), Some(LogLevel::Info));
+/// ```
+#[macro_export]
+macro_rules! register_enum {
+    ($enum:ty, $name:literal $(,)?) => {
+        $crate::register_enum!(@submit $enum, $crate::RequiredVar::new($name));
+    };
+    ($enum:ty, $name:literal, default = $default:ident $(,)?) => {
+        $crate::register_enum!($enum, $name, default = stringify!($default));
+    };
+    ($enum:ty, $name:literal, default = $default:expr $(,)?) => {
+        $crate::register_enum!(
+            @submit $enum,
+            $crate::RequiredVar::new($name).with_default($default)
+        );
+    };
+    (@submit $enum:ty, $var:expr) => {
+        const _: () = {
+            $crate::inventory::submit!($var
+                .with_parse_check($crate::ParseCheck {
+                    check: |value| value.parse::<$enum>().is_ok(),
+                    type_name: ::std::any::type_name::<$enum>,
+                })
+                .with_source(file!()));
+        };
+    };
+}
+
+/// Registers the config schema version the application expects.
+///
+/// Once registered, every config file read by the loaders must declare a
+/// matching top-level `schema_version` key, or loading fails with
+/// `EnvInventoryError::SchemaVersionMismatch`. This prevents loading a config
+/// written for an incompatible version of the application. `.env` files
+/// declare it with a `SCHEMA_VERSION` assignment, and the directories read
+/// by [`load_from_dir`] with a `SCHEMA_VERSION` file.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{load_and_validate_env_vars, register_schema_version, EnvInventoryError};
+/// register_schema_version!(2);
+///
+/// let dir = tempfile::tempdir().unwrap();
+/// let path = dir.path().join("app.toml");
+/// std::fs::write(&path, "schema_version = 1\n[env]\n").unwrap();
+///
+/// let result = load_and_validate_env_vars(&[&path], "env");
+/// assert!(matches!(
+///     result,
+///     Err(EnvInventoryError::SchemaVersionMismatch { expected: 2, found: Some(1) })
+/// ));
+///
+/// std::fs::write(&path, "schema_version = 2\n[env]\n").unwrap();
+/// load_and_validate_env_vars(&[&path], "env").unwrap();
+///
+/// let dotenv = dir.path().join(".env");
+/// std::fs::write(&dotenv, "QUEUE=jobs\n").unwrap();
+/// assert!(matches!(
+///     load_and_validate_env_vars(&[&dotenv], "env"),
+///     Err(EnvInventoryError::SchemaVersionMismatch { expected: 2, found: None })
+/// ));
+/// std::fs::write(&dotenv, "SCHEMA_VERSION=2\nQUEUE=jobs\n").unwrap();
+/// load_and_validate_env_vars(&[&dotenv], "env").unwrap();
+///
+/// let secrets = dir.path().join("secrets");
+/// std::fs::create_dir(&secrets).unwrap();
+/// assert!(env_inventory::load_from_dir(&secrets).is_err());
+/// std::fs::write(secrets.join("SCHEMA_VERSION"), "2\n").unwrap();
+/// env_inventory::load_from_dir(&secrets).unwrap();
+/// ```
+#[macro_export]
+macro_rules! register_schema_version {
+    ($version:expr) => {
+        const _: () = {
+            $crate::inventory::submit!($crate::SchemaVersion {
+                version: $version,
+                source: file!(),
+            });
+        };
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_opts {
+    ($var:expr;) => {
+        $var
+    };
+    ($var:expr; required $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_required(); $($($rest)*)?)
+    };
+    ($var:expr; secret $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_secret(); $($($rest)*)?)
+    };
+    ($var:expr; internal $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!(
+            $var.with_sensitivity($crate::Sensitivity::Internal); $($($rest)*)?
+        )
+    };
+    ($var:expr; platform $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_platform(); $($($rest)*)?)
+    };
+    ($var:expr; error = $error:expr $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_error($error); $($($rest)*)?)
+    };
+    ($var:expr; path $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_path(); $($($rest)*)?)
+    };
+    ($var:expr; absolute $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_absolute(); $($($rest)*)?)
+    };
+    ($var:expr; non_empty $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_non_empty(); $($($rest)*)?)
+    };
+    ($var:expr; optional $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_optional(); $($($rest)*)?)
+    };
+    ($var:expr; reject_placeholders $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_reject_placeholders(); $($($rest)*)?)
+    };
+    ($var:expr; base64 $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_base64(); $($($rest)*)?)
+    };
+    ($var:expr; assert = $check:expr, $message:expr $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!(
+            $var.with_assertion($crate::Assertion { check: $check, message: $message });
+            $($($rest)*)?
+        )
+    };
+    ($var:expr; example = $example:expr $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_example($example); $($($rest)*)?)
+    };
+    ($var:expr; allowed = [$($allowed:expr),+ $(,)?] $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_allowed(&[$($allowed),+]); $($($rest)*)?)
+    };
+    ($var:expr; commands = [$($command:expr),* $(,)?] $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!($var.with_commands(&[$($command),*]); $($($rest)*)?)
+    };
+    ($var:expr; type = $ty:ident $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!(
+            $crate::__register_type!($var, $ty);
+            $($($rest)*)?
+        )
+    };
+    ($var:expr; feature = $feature:literal $(; $($rest:tt)*)?) => {
+        compile_error!("`feature` must be the first option")
+    };
+    ($var:expr; $priority:ident $(; $($rest:tt)*)?) => {
+        $crate::__register_opts!(
+            $var.with_priority($crate::Priority::$priority);
+            $($($rest)*)?
+        )
+    };
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __register_type {
+    ($var:expr, String) => { $var.with_expected_type($crate::ExpectedType::String) };
+    ($var:expr, str) => { $var.with_expected_type($crate::ExpectedType::String) };
+    ($var:expr, i8) => { $crate::__register_type!(@int $var, i8, Int) };
+    ($var:expr, i16) => { $crate::__register_type!(@int $var, i16, Int) };
+    ($var:expr, i32) => { $crate::__register_type!(@int $var, i32, Int) };
+    ($var:expr, i64) => { $crate::__register_type!(@int $var, i64, Int) };
+    ($var:expr, i128) => { $crate::__register_type!(@int $var, i128, Int) };
+    ($var:expr, isize) => { $crate::__register_type!(@int $var, isize, Int) };
+    ($var:expr, u8) => { $crate::__register_type!(@int $var, u8, UInt) };
+    ($var:expr, u16) => { $crate::__register_type!(@int $var, u16, UInt) };
+    ($var:expr, u32) => { $crate::__register_type!(@int $var, u32, UInt) };
+    ($var:expr, u64) => { $crate::__register_type!(@int $var, u64, UInt) };
+    ($var:expr, u128) => { $crate::__register_type!(@int $var, u128, UInt) };
+    ($var:expr, usize) => { $crate::__register_type!(@int $var, usize, UInt) };
+    ($var:expr, f32) => { $var.with_expected_type($crate::ExpectedType::Float) };
+    ($var:expr, f64) => { $var.with_expected_type($crate::ExpectedType::Float) };
+    ($var:expr, bool) => { $var.with_expected_type($crate::ExpectedType::Bool) };
+    (@int $var:expr, $ty:ident, $kind:ident) => {
+        $var.with_expected_type($crate::ExpectedType::$kind)
+            .with_int_range($crate::IntRange {
+                min: $ty::MIN as i128,
+                max: $ty::MAX as u128,
+                type_name: stringify!($ty),
+            })
+    };
+    ($var:expr, $ty:ident) => {
+        compile_error!(concat!(
+            "unsupported type `",
+            stringify!($ty),
+            "`; expected String, an integer, a float or bool"
+        ))
+    };
+}
+
 /// Represents the potential errors that can be encountered by the
 /// `env-inventory` module.
 ///
@@ -183,516 +780,4404 @@ pub enum EnvInventoryError {
     #[error("Failed to parse the settings file at {0}")]
     ParseFileError(String),
 
+    /// Represents the absence of every candidate config file.
+    ///
+    /// Contains the paths that were tried, in order.
+    #[error("None of the config files could be read: {0:?}")]
+    NoConfigFile(Vec<String>),
+
     /// Represents the absence of required environment variables.
     ///
-    /// Contains a vector of strings, each representing a missing environment
-    /// variable.
-    #[error("Missing required environment variables: {0:?}")]
-    MissingEnvVars(Vec<String>),
+    /// Contains a description of each missing environment variable, sorted
+    /// by its rendering.
+    #[error("Missing required environment variables: {:?}", missing::rendered(.0))]
+    MissingEnvVars(Vec<MissingVar>),
 
     /// Represents the absence of required environment variables.
     /// variable.
     #[error("Missing required environment variables: {0:?}")]
     MissingEnvVar(String),
+
+    /// Represents a failure to deserialize the resolved variables into a typed
+    /// config struct.
+    ///
+    /// Contains a string describing the field or value that failed.
+    #[error("Failed to deserialize the environment into a typed config: {0}")]
+    DeserializeError(String),
+
+    /// Represents a failure to fetch a remote schema.
+    ///
+    /// Contains a string describing the network or HTTP failure.
+    #[error("Failed to fetch the config schema: {0}")]
+    SchemaFetchError(String),
+
+    /// Represents values that violate the config schema.
+    ///
+    /// Contains a vector of strings, each describing one violation.
+    #[error("Config violates the schema: {0:?}")]
+    SchemaViolations(Vec<String>),
+
+    /// Represents a config file written for an incompatible schema version.
+    #[error("Config schema version mismatch: expected {expected}, found {found:?}")]
+    SchemaVersionMismatch {
+        /// The schema version registered with [`register_schema_version!`].
+        expected: u32,
+        /// The `schema_version` declared by the config file, if any.
+        found: Option<i64>,
+    },
+
+    /// Represents a registration whose constraints contradict each other.
+    #[error("Contradictory constraints on {name}: {reason}")]
+    ContradictoryConstraints {
+        /// The name of the variable.
+        name: String,
+        /// Why the constraints contradict each other.
+        reason: String,
+    },
+
+    /// Represents config files that set the same variable to different
+    /// values, when conflict detection is enabled.
+    ///
+    /// Contains one entry per conflicting variable.
+    #[error("Conflicting values across config files: {0:?}")]
+    ConfigValueConflict(Vec<ConfigConflict>),
+
+    /// Represents a config file that doesn't parse in any of the formats
+    /// tried by [`ConfigFormat::Auto`].
+    #[error("Could not parse the settings file at {path} as any of {tried:?}")]
+    UnrecognizedConfigFormat {
+        /// The path to the file.
+        path: String,
+        /// The formats tried, in order.
+        tried: Vec<&'static str>,
+    },
+
+    /// Represents variables whose values reference each other, so they can't
+    /// be expanded.
+    ///
+    /// Contains the cycle, starting and ending with the same variable, such
+    /// as `["A", "B", "A"]`.
+    #[error("Cyclic reference between variables: {}", .0.join(" -> "))]
+    CyclicReference(Vec<String>),
+
+    /// Represents a config file larger than the configured limit.
+    #[error("Config file {path} is {size} bytes, over the limit of {limit}")]
+    FileTooLarge {
+        /// The path to the file.
+        path: String,
+        /// The size of the file in bytes.
+        size: u64,
+        /// The maximum size in bytes.
+        limit: u64,
+    },
+
+    /// Represents a secret that would be loaded from a plaintext config file
+    /// while [`LoaderConfig::forbid_secrets_in_files`] is enabled.
+    #[error("Secret {name} must not be set in the config file {path}")]
+    SecretInConfigFile {
+        /// The name of the secret variable.
+        name: String,
+        /// The path to the config file setting it.
+        path: String,
+    },
+
+    /// Represents a value that can't be interpreted as the requested type.
+    #[error("Invalid value {value:?} for {name}: {reason}")]
+    InvalidValue {
+        /// The name of the variable.
+        name: String,
+        /// The offending value.
+        value: String,
+        /// Why the value is invalid.
+        reason: String,
+    },
+
+    /// Represents a relative path in a variable registered with the
+    /// `absolute` option of [`register!`].
+    #[error("{name} must be an absolute path, got {path:?}")]
+    PathNotAbsolute {
+        /// The name of the variable.
+        name: String,
+        /// The relative path, after expansion.
+        path: String,
+    },
+
+    /// Represents an empty or whitespace-only value in a variable registered
+    /// with the `non_empty` option of [`register!`].
+    #[error("Environment variable {0} is set but empty")]
+    EmptyValue(String),
+
+    /// Represents a placeholder value in a variable registered with the
+    /// `reject_placeholders` option of [`register!`].
+    #[error("{name} is set to the placeholder {value:?}")]
+    PlaceholderValue {
+        /// The name of the variable.
+        name: String,
+        /// The placeholder value.
+        value: String,
+    },
+
+    /// Represents a value rejected by the `assert` option of [`register!`].
+    #[error("Assertion failed for {name}: {message}")]
+    AssertionFailed {
+        /// The name of the variable.
+        name: String,
+        /// The message registered with the assertion.
+        message: String,
+    },
+
+    /// Represents a violated rule registered with [`register_invariant!`].
+    #[error("Invariant violated: {message}")]
+    InvariantViolated {
+        /// The message registered with the rule.
+        message: String,
+    },
+
+    /// Represents a violated constraint over a group of variables.
+    ///
+    /// Contains the members of the group and why the constraint failed.
+    #[error("Group constraint violated for {members:?}: {reason}")]
+    GroupConstraintViolated {
+        /// The variables in the group.
+        members: Vec<String>,
+        /// A description of how the constraint was violated.
+        reason: String,
+    },
+
+    /// Represents a value that can't be parsed as the type requested from
+    /// [`RequiredVar::get_as`] or [`get_enum`], or that isn't valid base64.
+    #[error("Cannot parse {value:?} for {name} as {type_name}")]
+    ParseValueError {
+        /// The name of the variable.
+        name: String,
+        /// The offending value.
+        value: String,
+        /// The name of the requested type.
+        type_name: &'static str,
+    },
+
+    /// Represents a variable without an exact match in the environment but
+    /// several ignoring case, while [`set_case_insensitive`] is enabled.
+    #[error("Environment variable {name} is ambiguous between {candidates:?}")]
+    AmbiguousVar {
+        /// The name of the variable.
+        name: String,
+        /// The environment variables matching it ignoring case.
+        candidates: Vec<String>,
+    },
+
+    /// Represents a value that doesn't parse as the type the variable was
+    /// registered with, or declared with in the `types` table of a config
+    /// file.
+    #[error("Invalid value {value:?} for {name}: expected {expected}")]
+    InvalidType {
+        /// The name of the variable.
+        name: String,
+        /// The offending value.
+        value: String,
+        /// The type the variable was registered or declared with.
+        expected: ExpectedType,
+    },
+
+    /// Represents a registry holding fewer variables than expected, usually
+    /// because the linker stripped the registrations.
+    #[error("Expected at least {min} registered variables, found {found}")]
+    EmptyRegistry {
+        /// The number of distinct variables registered.
+        found: usize,
+        /// The minimum number expected.
+        min: usize,
+    },
+
+    /// Represents a value that doesn't deserialize as the type requested from
+    /// `get_json`, with the `json` feature.
+    #[error("Environment variable {name} doesn't hold the expected JSON: {reason}")]
+    InvalidJson {
+        /// The name of the variable.
+        name: String,
+        /// Why the value doesn't deserialize, masked for a variable
+        /// registered with the `secret` option, as the reason may quote it.
+        reason: String,
+    },
+
+    /// Represents a registry out of sync with the schema file passed to
+    /// [`validate_completeness`].
+    #[error("Registry doesn't match the schema: unregistered {unregistered:?}, not in the schema {undeclared:?}")]
+    IncompleteRegistry {
+        /// Variables listed in the schema but never registered, sorted.
+        unregistered: Vec<String>,
+        /// Variables registered but missing from the schema, sorted.
+        undeclared: Vec<String>,
+    },
+
+    /// Represents a value rejected by a validator attached with
+    /// [`register_validator`].
+    #[error("Validation failed for {name}: {message}")]
+    ValidationFailed {
+        /// The name of the variable.
+        name: String,
+        /// The message returned by the validator.
+        message: String,
+    },
+
+    /// Represents every failure found by [`ValidationScope::finish`].
+    ///
+    /// Contains a `MissingEnvVars` listing the missing variables, if any,
+    /// followed by the error for each invalid value.
+    #[error("Validation failed: {0:?}")]
+    ValidationErrors(Vec<EnvInventoryError>),
 }
 
-#[doc(hidden)]
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+/// A variable set to different values by more than one config file.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ConfigConflict {
+    /// The name of the variable.
+    pub name: String,
+    /// Pairs of `(file, value)` for every file setting the variable, in load
+    /// order.
+    pub values: Vec<(String, String)>,
+}
+
+/// The priority of a registered default, used to pick a default when a
+/// variable is registered more than once.
+///
+/// Defaults registered by the binary win over those registered by libraries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Priority {
     /// The default value is from a library or unknown.
     Unknown,
+    /// The default value is from a library.
     Library,
+    /// The default value is from the binary, and wins over library defaults.
     Binary,
-
 }
 
-#[doc(hidden)]
-#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct RequiredVar {
-    pub name: &'static str,
-    pub default: Option<&'static str>,
-    pub source: &'static str,
-    pub priority: Priority,
+/// The type of value expected for a variable registered with a type, as in
+/// `register!(PORT: u16 = "8080")`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, ExpectedType, RequiredVar};
+/// register!(PORT: u16 = "8080");
+/// register!(VERBOSE: bool; error = "set VERBOSE to true or false");
+///
+/// let port = env_inventory::inventory::iter::<RequiredVar>()
+///     .find(|var| var.name() == "PORT")
+///     .unwrap();
+/// assert_eq!(port.expected_type(), Some(ExpectedType::UInt));
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ExpectedType {
+    /// Any value.
+    String,
+    /// A signed integer.
+    Int,
+    /// An unsigned integer.
+    UInt,
+    /// A floating-point number.
+    Float,
+    /// `true` or `false`.
+    Bool,
 }
 
-inventory::collect!(RequiredVar);
+impl ExpectedType {
+    /// Gets the type declared by `name` in the `types` table of a config
+    /// file: `string`, `int`, `uint`, `float` or `bool`.
+    pub(crate) fn from_declared(name: &str) -> Option<Self> {
+        match name {
+            "string" | "str" => Some(ExpectedType::String),
+            "int" | "integer" => Some(ExpectedType::Int),
+            "uint" | "unsigned" => Some(ExpectedType::UInt),
+            "float" | "number" => Some(ExpectedType::Float),
+            "bool" | "boolean" => Some(ExpectedType::Bool),
+            _ => None,
+        }
+    }
 
-impl RequiredVar {
-    /// Creates a new `RequiredVar` instance at compile time.
-    pub const fn new(name: &'static str) -> Self {
-        Self {
-            name,
-            default: None,
-            source: "<none>",
-            priority: Priority::Library,
+    /// Checks if `value` parses as this type.
+    pub fn accepts(self, value: &str) -> bool {
+        match self {
+            ExpectedType::String => true,
+            ExpectedType::Int => value.parse::<i128>().is_ok(),
+            ExpectedType::UInt => value.parse::<u128>().is_ok(),
+            ExpectedType::Float => value.parse::<f64>().is_ok(),
+            ExpectedType::Bool => value.parse::<bool>().is_ok(),
         }
     }
+}
 
-    /// Checks if the variable is set in the environment or has a default value.
-    pub fn is_set(&self) -> bool {
-        // If the variable is set in the environment, or 
-        // we have a default value, we're good
-        env::var(self.name).is_ok() || self.default.is_some()
+impl std::fmt::Display for ExpectedType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ExpectedType::String => "a string",
+            ExpectedType::Int => "an integer",
+            ExpectedType::UInt => "an unsigned integer",
+            ExpectedType::Float => "a number",
+            ExpectedType::Bool => "true or false",
+        };
+        f.write_str(name)
     }
+}
 
-    /// Gets the value of the variable from the environment or the default.
-    pub fn get(&self) -> Option<String> {
-        match env::var(self.name) {
-            Ok(value) => Some(value),
-            Err(_) => match self.default {
-                Some(value) => Some(value.to_string()),
-                None => None,
-            },
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct IntRange {
+    /// The smallest accepted value.
+    pub min: i128,
+    /// The largest accepted value.
+    pub max: u128,
+    /// The name of the integer type the range is taken from.
+    pub type_name: &'static str,
+}
+
+impl IntRange {
+    /// Checks that `value` is an integer within the range, explaining why
+    /// not otherwise.
+    fn check(&self, value: &str) -> Result<(), String> {
+        let in_range = match value.parse::<i128>() {
+            Ok(parsed) if parsed < 0 => parsed >= self.min,
+            Ok(parsed) => parsed as u128 <= self.max,
+            Err(_) => value.parse::<u128>().map_or(false, |parsed| parsed <= self.max),
+        };
+        if in_range {
+            Ok(())
+        } else {
+            Err(format!(
+                "out of range for {} ({}..={})",
+                self.type_name, self.min, self.max
+            ))
         }
     }
 }
 
-/// Validates that all registered environment variables are set.
-///
-/// This function checks if the previously registered environment variables (via
-/// the `register!` macro or other means) are present either in the system's
-/// environment or the loaded configuration files.
-///
-/// If any of the registered variables are missing, an
-/// `EnvInventoryError::MissingEnvVars` error is returned, containing a list of
-/// the missing variables.
-///
-/// # Parameters
-///
-/// * `config_paths`: A slice of file paths (as `&str`) pointing to the
-///   configuration files that might contain the environment variables. These
-///   files are expected to be in TOML format with a dedicated `[env]` section.
-/// * `section_name`: The name of the section in the TOML files that contains
-///   the environment variables. By default, this is `"env"`.
-///
-/// # Returns
-///
-/// * `Ok(())`: If all registered environment variables are found.
-/// * `Err(EnvInventoryError)`: If there's an error reading or parsing the
-///   config files or if any registered environment variable is missing.
-///
-/// # Examples
-///
-/// ```rust (ignore)
-/// # use env_inventory::validate_env_vars;
-/// let result = validate_env_vars(&["/path/to/settings.conf"], "env");
-/// if result.is_err() {
-///     eprintln!("Failed to validate environment variables: {:?}", result);
-/// }
-/// ```
-///
-/// # Errors
-///
-/// This function can return the following errors:
-/// * `ReadFileError`: If a provided config file cannot be read.
-/// * `ParseFileError`: If a provided config file cannot be parsed as TOML or
-///   lacks the expected structure.
-/// * `MissingEnvVars`: If one or more registered environment variables are
-///   missing.
-
-pub fn validate_env_vars() -> Result<(), EnvInventoryError> {
-    let missing_vars: HashSet<String> = inventory::iter::<RequiredVar>()
-    .filter_map(|var| {
-        if var.is_set() {
-            None
-        } else {
-            Some(var.name.to_string())
-            // format!("{} (default: \"{}\")", var.name.to_string(), var.default.unwrap_or("")).into()
+/// Checks if `values` contains `value`, at compile time.
+#[doc(hidden)]
+pub const fn __contains_str(values: &[&str], value: &str) -> bool {
+    let value = value.as_bytes();
+    let mut i = 0;
+    while i < values.len() {
+        let candidate = values[i].as_bytes();
+        if candidate.len() == value.len() {
+            let mut j = 0;
+            while j < value.len() && candidate[j] == value[j] {
+                j += 1;
+            }
+            if j == value.len() {
+                return true;
+            }
         }
-    })
-    .collect();
-    let mut missing_vars = missing_vars.into_iter().collect::<Vec<String>>();
-    missing_vars.sort();
+        i += 1;
+    }
+    false
+}
 
-    if missing_vars.is_empty() {
-        Ok(())
-    } else {
-        tracing::warn!("Missing required environment variables: {:?}", missing_vars);
-        Err(EnvInventoryError::MissingEnvVars(missing_vars))
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SchemaVersion {
+    /// The schema version config files must declare.
+    pub version: u32,
+    /// The file the version was registered in.
+    pub source: &'static str,
+}
+
+inventory::collect!(SchemaVersion);
+
+#[doc(hidden)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Condition {
+    /// The variable whose value selects the default.
+    pub based_on: &'static str,
+    /// Pairs of `(value of based_on, default)`, checked in order.
+    pub cases: &'static [(&'static str, &'static str)],
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct Derivation {
+    /// The variables whose values are passed to `compute`, in order.
+    pub from: &'static [&'static str],
+    /// Computes the value from the values of `from`.
+    pub compute: fn(&[&str]) -> String,
+}
+
+// Function pointers can't be meaningfully compared, so derivations are
+// compared by their inputs only.
+impl PartialEq for Derivation {
+    fn eq(&self, other: &Self) -> bool {
+        self.from == other.from
     }
 }
 
-/// List all the registered environment variables.
-/// that are expected from different parts of the application.
-pub fn list_all_vars() -> Vec<String> {
-    let mut v: Vec<String> = inventory::iter::<RequiredVar>()
-        .map(|var| {
-            var.name.to_string()
-        })
-        .collect();
-    v.sort();
-    v
+impl Eq for Derivation {}
+
+impl std::hash::Hash for Derivation {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.from.hash(state);
+    }
 }
 
-/// Dump all the registered environment variables.
+impl PartialOrd for Derivation {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
 
-pub fn dump_all_vars() {
-    let mut v: Vec<String> = inventory::iter::<RequiredVar>()
-        .map(|v| format!("{:#?}", v)).collect();
-    v.sort();
-    dbg!(v);
+impl Ord for Derivation {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.from.cmp(other.from)
+    }
 }
 
 #[doc(hidden)]
-pub fn map() -> HashMap<&'static str, String> {
-    let mut seen_vars: HashMap<&'static str, String> = HashMap::new();
+#[derive(Debug, Clone, Copy)]
+pub struct Assertion {
+    /// Checks the value.
+    pub check: fn(&str) -> bool,
+    /// The message reported when the check fails.
+    pub message: &'static str,
+}
 
-    for var in inventory::iter::<RequiredVar>() {
-        if !seen_vars.contains_key(var.name) {
-            if let Some(value) = var.get() {
-                seen_vars.insert(var.name, value);
-            }
-        }
+// As with `Derivation`, only the comparable part is compared.
+impl PartialEq for Assertion {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
     }
-    seen_vars
 }
 
-/// Expand all the registered environment variables.
-/// that are expected from different parts of the application.
-/// So for instance if you have a variable like this:
-/// ```rust (ignore)
-/// // somewhere
-/// register!(TEST_ENV_VAR = "~/test");
-/// // elsewhere you do this:
-/// register!(LIBDIR = "${TEST_ENV_VAR}/lib");
-/// // then you can do this:
-///
-/// ```
-/// then expanded_map will update the env and expand the env.
-/// TODO:
-/// 1. This should be done in the register macro.
-pub fn expanded_map() -> Result<HashMap<String, String>, EnvInventoryError> {
-    let mut seen_vars: HashMap<String, String> = HashMap::new();
+impl Eq for Assertion {}
 
-    for var in inventory::iter::<RequiredVar>() {
-        if !seen_vars.contains_key(var.name) {
-            if let Some(value) = var.get() {
-                let value = shellexpand::full(&value)
-                    .map_err(|e| EnvInventoryError::MissingEnvVar(
-                        e.to_string()) 
-                    )?
-                    .to_string();
-                std::env::set_var(var.name, &value);
-                seen_vars.insert(var.name.to_string(), value);
-            }
-        }
+impl std::hash::Hash for Assertion {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.message.hash(state);
     }
-    for (key, value) in seen_vars.iter() {
-        let value = shellexpand::full(&value)
-            .map_err(|e| EnvInventoryError::MissingEnvVar(
-                e.to_string()) 
-            )?
-            .to_string();
-        std::env::set_var(key, &value);
+}
+
+impl PartialOrd for Assertion {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
     }
-    Ok(seen_vars)
 }
 
-/// Loads the settings from a TOML file and returns them as a `HashMap`.
-pub(crate) fn load_toml_settings<P: AsRef<Path>>(
-    path: P,
-    section: &str,
-) -> Result<HashMap<String, String>, EnvInventoryError> {
-    let content = fs::read_to_string(&path)
-        .map_err(|_| EnvInventoryError::ReadFileError(path.as_ref().display().to_string()))?;
+impl Ord for Assertion {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.message.cmp(other.message)
+    }
+}
 
-    let value = content
-        .parse::<Value>()
-        .map_err(|_| EnvInventoryError::ParseFileError(path.as_ref().display().to_string()))?;
+#[doc(hidden)]
+#[derive(Debug, Clone, Copy)]
+pub struct ParseCheck {
+    /// Checks that the value parses.
+    pub check: fn(&str) -> bool,
+    /// The name of the type the value must parse as.
+    pub type_name: fn() -> &'static str,
+}
 
-    let env_section = match value.get(section) {
-        Some(env) => env.as_table(),
-        None => None,
-    };
+// As with `Derivation`, only the comparable part is compared.
+impl PartialEq for ParseCheck {
+    fn eq(&self, other: &Self) -> bool {
+        (self.type_name)() == (other.type_name)()
+    }
+}
 
-    let mut settings = HashMap::new();
+impl Eq for ParseCheck {}
 
-    if let Some(env_table) = env_section {
-        for (key, val) in env_table.iter() {
-            if let Some(val_str) = val.as_str() {
-                settings.insert(key.clone(), val_str.to_string());
-            }
-        }
+impl std::hash::Hash for ParseCheck {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.type_name)().hash(state);
     }
+}
 
-    Ok(settings)
+impl PartialOrd for ParseCheck {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
 }
 
-/// Loads environment variables from specified configuration files and validates
-/// their presence.
-///
-/// This function goes through the provided list of configuration file paths,
-/// merges the environment settings from each file, and ensures that all the
-/// registered environment variables are set. If an environment variable
-/// is not already present in the system's environment, it will be set using the
-/// value from the merged settings.
-///
-/// Environment variables present in the system's environment take precedence
-/// over those in the configuration files.
-///
-/// # Parameters
-///
-/// * `config_paths`: A slice containing paths to the configuration files that
-///   should be loaded. The files are expected to be in TOML format and have a
-///   dedicated section for environment variables.
-/// * `section`: The name of the section in the TOML files that contains the
-///   environment variables.
-///
-/// # Returns
-///
-/// * `Ok(())`: If all registered environment variables are present either in
-///   the system's environment or in the merged settings.
-/// * `Err(EnvInventoryError)`: If there's an error reading or parsing the
-///   config files or if any registered environment variable is missing.
-///
-/// # Behavior
-///
-/// The first file in the `config_paths` slice is mandatory and if it can't be
-/// read or parsed, an error is immediately returned. Subsequent files are
-/// optional, and while they will generate a warning if they cannot be read or
-/// parsed, they won't cause the function to return an error.
-///
-/// After merging the settings from all files and overlaying them on the
-/// system's environment variables, the function checks for missing required
-/// environment variables and returns an error if any are found.
-///
-/// # Examples
-///
-/// ```rust (ignore)
-/// # use env_inventory::load_and_validate_env_vars;
-/// # use std::path::Path;
-/// let paths = [Path::new("/path/to/shipped.conf"), Path::new("/path/to/system.conf")];
-/// let result = load_and_validate_env_vars(&paths, "env");
-/// if result.is_err() {
-///     eprintln!("Failed to load and validate environment variables: {:?}", result);
-/// }
-/// ```
+impl Ord for ParseCheck {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.type_name)().cmp((other.type_name)())
+    }
+}
+
+/// A registered environment variable.
 ///
-/// # Errors
+/// Instances are created by the [`register!`] family of macros and collected
+/// into the inventory; use the getters to inspect a registration.
 ///
-/// This function can return the following errors:
-/// * `ReadFileError`: If a provided config file cannot be read.
-/// * `ParseFileError`: If a provided config file cannot be parsed as TOML or
-///   lacks the expected structure.
-/// * `MissingEnvVars`: If one or more registered environment variables are
-///   missing.
+/// The `Debug` and `Display` forms of a variable registered with the `secret`
+/// option, or any sensitivity at or above the [`redaction_level`], show its
+/// default and value as `****`.
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RequiredVar {
+    pub(crate) name: &'static str,
+    pub(crate) default: Option<&'static str>,
+    pub(crate) source: &'static str,
+    pub(crate) priority: Priority,
+    pub(crate) condition: Option<Condition>,
+    pub(crate) platform: bool,
+    pub(crate) error: Option<&'static str>,
+    pub(crate) commands: &'static [&'static str],
+    pub(crate) required: bool,
+    pub(crate) sensitivity: Sensitivity,
+    pub(crate) example: Option<&'static str>,
+    pub(crate) base64: bool,
+    pub(crate) derivation: Option<Derivation>,
+    pub(crate) assertion: Option<Assertion>,
+    pub(crate) parse_check: Option<ParseCheck>,
+    pub(crate) expected_type: Option<ExpectedType>,
+    pub(crate) int_range: Option<IntRange>,
+    pub(crate) allowed: &'static [&'static str],
+    pub(crate) path: bool,
+    pub(crate) absolute: bool,
+    pub(crate) non_empty: bool,
+    pub(crate) reject_placeholders: bool,
+    pub(crate) optional: bool,
+}
+
+inventory::collect!(RequiredVar);
+
+/// Shown in place of the values of secret variables.
+pub(crate) const MASK: &str = "****";
+
+impl RequiredVar {
+    fn fmt_debug(&self, f: &mut std::fmt::Formatter<'_>, redact: bool) -> std::fmt::Result {
+        let default = match self.default {
+            Some(_) if redact && self.is_redacted() => Some(MASK),
+            default => default,
+        };
+        f.debug_struct("RequiredVar")
+            .field("name", &self.name)
+            .field("default", &default)
+            .field("source", &self.source)
+            .field("priority", &self.priority)
+            .field("condition", &self.condition)
+            .field("platform", &self.platform)
+            .field("error", &self.error)
+            .field("commands", &self.commands)
+            .field("required", &self.required)
+            .field("sensitivity", &self.sensitivity)
+            .field("example", &self.example)
+            .field("base64", &self.base64)
+            .field("derivation", &self.derivation)
+            .field("assertion", &self.assertion)
+            .field("parse_check", &self.parse_check)
+            .field("expected_type", &self.expected_type)
+            .field("int_range", &self.int_range)
+            .field("allowed", &self.allowed)
+            .field("path", &self.path)
+            .field("absolute", &self.absolute)
+            .field("non_empty", &self.non_empty)
+            .field("reject_placeholders", &self.reject_placeholders)
+            .field("optional", &self.optional)
+            .finish()
+    }
+
+    fn fmt_value(&self, f: &mut std::fmt::Formatter<'_>, redact: bool) -> std::fmt::Result {
+        match self.value() {
+            Some(_) if redact && self.is_redacted() => write!(f, "{}={}", self.name, MASK),
+            Some(value) => write!(f, "{}={}", self.name, value),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+impl std::fmt::Debug for RequiredVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_debug(f, true)
+    }
+}
+
+/// Shows the name of the variable and, if it has one, its current value as
+/// `NAME=value`.
+impl std::fmt::Display for RequiredVar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_value(f, true)
+    }
+}
+
+/// Formats a variable like its `Debug` and `Display` forms, secrets included.
+struct Unredacted<'a>(&'a RequiredVar);
+
+impl std::fmt::Debug for Unredacted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_debug(f, false)
+    }
+}
+
+impl std::fmt::Display for Unredacted<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt_value(f, false)
+    }
+}
+
+impl RequiredVar {
+    /// Creates a new `RequiredVar` instance at compile time.
+    pub const fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            default: None,
+            source: "<none>",
+            priority: Priority::Library,
+            condition: None,
+            platform: false,
+            error: None,
+            commands: &[],
+            required: false,
+            sensitivity: Sensitivity::Public,
+            example: None,
+            base64: false,
+            derivation: None,
+            assertion: None,
+            parse_check: None,
+            expected_type: None,
+            int_range: None,
+            allowed: &[],
+            path: false,
+            absolute: false,
+            non_empty: false,
+            reject_placeholders: false,
+            optional: false,
+        }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_default(self, default: &'static str) -> Self {
+        Self { default: Some(default), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_source(self, source: &'static str) -> Self {
+        Self { source, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_priority(self, priority: Priority) -> Self {
+        Self { priority, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_condition(self, condition: Condition) -> Self {
+        Self { condition: Some(condition), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_platform(self) -> Self {
+        Self { platform: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_error(self, error: &'static str) -> Self {
+        Self { error: Some(error), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_required(self) -> Self {
+        Self { required: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_secret(self) -> Self {
+        self.with_sensitivity(Sensitivity::Secret)
+    }
+
+    #[doc(hidden)]
+    pub const fn with_sensitivity(self, sensitivity: Sensitivity) -> Self {
+        Self { sensitivity, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_example(self, example: &'static str) -> Self {
+        Self { example: Some(example), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_base64(self) -> Self {
+        Self { base64: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_derivation(self, derivation: Derivation) -> Self {
+        Self { derivation: Some(derivation), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_assertion(self, assertion: Assertion) -> Self {
+        Self { assertion: Some(assertion), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_parse_check(self, parse_check: ParseCheck) -> Self {
+        Self { parse_check: Some(parse_check), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_expected_type(self, expected_type: ExpectedType) -> Self {
+        Self { expected_type: Some(expected_type), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_int_range(self, int_range: IntRange) -> Self {
+        Self { int_range: Some(int_range), ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_path(self) -> Self {
+        Self { path: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_absolute(self) -> Self {
+        Self { path: true, absolute: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_non_empty(self) -> Self {
+        Self { non_empty: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_reject_placeholders(self) -> Self {
+        Self { reject_placeholders: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_optional(self) -> Self {
+        Self { optional: true, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_allowed(self, allowed: &'static [&'static str]) -> Self {
+        Self { allowed, ..self }
+    }
+
+    #[doc(hidden)]
+    pub const fn with_commands(self, commands: &'static [&'static str]) -> Self {
+        Self { commands, ..self }
+    }
+
+    /// The name of the environment variable.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// The registered default, if any.
+    ///
+    /// This is the unconditional default; see [`RequiredVar::default_with`]
+    /// for defaults that depend on another variable.
+    pub const fn default(&self) -> Option<&'static str> {
+        self.default
+    }
+
+    /// The custom message reported when the variable is missing, if any.
+    pub const fn error(&self) -> Option<&'static str> {
+        self.error
+    }
+
+    /// An example value documenting the expected format, if any.
+    pub const fn example(&self) -> Option<&'static str> {
+        self.example
+    }
+
+    /// The file the variable was registered in.
+    pub const fn source(&self) -> &'static str {
+        self.source
+    }
+
+    /// The priority of the registered default.
+    pub const fn priority(&self) -> Priority {
+        self.priority
+    }
+
+    /// The subcommands the variable is scoped to; empty if it is required by
+    /// every subcommand.
+    pub const fn commands(&self) -> &'static [&'static str] {
+        self.commands
+    }
+
+    /// Checks if the variable must be provided explicitly, ignoring defaults.
+    pub const fn is_required(&self) -> bool {
+        self.required
+    }
+
+    /// Checks if the variable holds a secret whose value must not be shown.
+    pub const fn is_secret(&self) -> bool {
+        matches!(self.sensitivity, Sensitivity::Secret)
+    }
+
+    /// How sensitive the value of the variable is.
+    pub const fn sensitivity(&self) -> Sensitivity {
+        self.sensitivity
+    }
+
+    /// Checks if the value is redacted from dumps under the current
+    /// [`redaction_level`].
+    pub fn is_redacted(&self) -> bool {
+        self.sensitivity >= redaction_level()
+    }
+
+    /// Checks if the value is expected to be base64-encoded.
+    pub const fn is_base64(&self) -> bool {
+        self.base64
+    }
+
+    /// The type the value is expected to parse as, if the variable was
+    /// registered with one.
+    pub const fn expected_type(&self) -> Option<ExpectedType> {
+        self.expected_type
+    }
+
+    /// Checks if the value is a filesystem path.
+    pub const fn is_path(&self) -> bool {
+        self.path
+    }
+
+    /// Checks if the value must be an absolute path.
+    pub const fn requires_absolute(&self) -> bool {
+        self.absolute
+    }
+
+    /// Checks if an empty or whitespace-only value fails validation.
+    pub const fn requires_non_empty(&self) -> bool {
+        self.non_empty
+    }
+
+    /// Checks if placeholder values, such as `changeme`, fail validation.
+    pub const fn rejects_placeholders(&self) -> bool {
+        self.reject_placeholders
+    }
+
+    /// Checks if the variable is never reported as missing.
+    pub const fn is_optional(&self) -> bool {
+        self.optional
+    }
+
+    /// The values the variable may take; empty if any value is allowed.
+    pub const fn allowed_values(&self) -> &'static [&'static str] {
+        self.allowed
+    }
+
+    /// The variables this variable is computed from, if it was registered
+    /// with [`register_derived!`].
+    pub fn derived_from(&self) -> Option<&'static [&'static str]> {
+        self.derivation.map(|derivation| derivation.from)
+    }
+
+    /// Checks if the variable is required when running `command`.
+    pub fn applies_to(&self, command: &str) -> bool {
+        self.commands.is_empty() || self.commands.contains(&command)
+    }
+
+    /// Checks if the variable is set in the environment, is available from the
+    /// platform source, or has a default value.
+    ///
+    /// A variable whose name matches several environment variables ignoring
+    /// case isn't set; see [`set_case_insensitive`].
+    pub fn is_set(&self) -> bool {
+        self.value().is_some()
+    }
+
+    /// Gets the value of the variable from the environment, the platform
+    /// source, or the default.
+    ///
+    /// Returns `None` if case-insensitive lookups are enabled and several
+    /// environment variables match the name ignoring case but none exactly,
+    /// rather than falling back to the default; validation reports such a
+    /// variable as `AmbiguousVar`. See [`set_case_insensitive`].
+    ///
+    /// Each call is counted; see [`access_counts`].
+    pub fn get(&self) -> Option<String> {
+        access::record(self.name);
+        self.value()
+    }
+
+    /// Gets the value of the variable like [`RequiredVar::get`], parsed as `T`.
+    ///
+    /// Returns `Ok(None)` if the variable has no value.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use env_inventory::RequiredVar;
+    /// # std::env::remove_var("HTTP_PORT");
+    /// let port = RequiredVar::new("HTTP_PORT").with_default("8080");
+    /// assert_eq!(port.get_as::<u16>().unwrap(), Some(8080));
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// * `ParseValueError`: If the value can't be parsed as `T`. The value is
+    ///   masked if the variable is a secret.
+    pub fn get_as<T: std::str::FromStr>(&self) -> Result<Option<T>, EnvInventoryError> {
+        match self.get() {
+            Some(value) => match value.parse() {
+                Ok(parsed) => Ok(Some(parsed)),
+                Err(_) => Err(EnvInventoryError::ParseValueError {
+                    name: self.name.to_string(),
+                    value: if self.is_redacted() {
+                        MASK.to_string()
+                    } else {
+                        value
+                    },
+                    type_name: std::any::type_name::<T>(),
+                }),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Like [`RequiredVar::get`], without counting the access. Used
+    /// internally so that bulk operations don't skew the counts.
+    pub(crate) fn value(&self) -> Option<String> {
+        match case::lookup(self.name).ok()? {
+            Some(value) => Some(value),
+            None => self
+                .platform_value()
+                .or_else(|| self.derived_value())
+                .or_else(|| self.default_with(case::var).map(|value| value.to_string())),
+        }
+    }
+
+    /// Gets the value of the variable from the installed [`PlatformSource`],
+    /// if the variable was registered with the `platform` option.
+    pub fn platform_value(&self) -> Option<String> {
+        if self.platform {
+            platform::lookup(self.name)
+        } else {
+            None
+        }
+    }
+
+    /// Computes the value of a variable registered with [`register_derived!`]
+    /// from the current values of its inputs.
+    ///
+    /// Returns `None` if the variable isn't derived or an input has no value.
+    pub fn derived_value(&self) -> Option<String> {
+        let derivation = self.derivation?;
+        // Variables derived from each other have no value rather than
+        // recursing forever; `expanded_map` reports the cycle
+        if DERIVING.with(|deriving| deriving.borrow().contains(&self.name)) {
+            return None;
+        }
+        DERIVING.with(|deriving| deriving.borrow_mut().push(self.name));
+        let inputs = derivation
+            .from
+            .iter()
+            .map(|name| {
+                case::var(name).or_else(|| {
+                    inventory::iter::<RequiredVar>()
+                        .filter(|var| var.name == *name)
+                        .find_map(RequiredVar::value)
+                })
+            })
+            .collect::<Option<Vec<String>>>();
+        DERIVING.with(|deriving| deriving.borrow_mut().pop());
+        let inputs = inputs?;
+        let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        Some((derivation.compute)(&inputs))
+    }
+
+    /// Gets the default of the variable, resolving a conditional default by
+    /// looking up the variable it is based on with `lookup`.
+    ///
+    /// Variables registered as `required` never use a default.
+    pub fn default_with<F>(&self, lookup: F) -> Option<&'static str>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        if self.required {
+            return None;
+        }
+        if let Some(condition) = &self.condition {
+            if let Some(value) = lookup(condition.based_on) {
+                let case = condition.cases.iter().find(|(case, _)| *case == value);
+                if let Some((_, default)) = case {
+                    return Some(default);
+                }
+            }
+        }
+        self.default
+    }
+}
+
+thread_local! {
+    /// The derived variables whose value is being computed on this thread.
+    static DERIVING: std::cell::RefCell<Vec<&'static str>> =
+        const { std::cell::RefCell::new(Vec::new()) };
+}
+
+/// Where the value of a variable came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum VarSource {
+    /// The process environment.
+    Environment,
+    /// A config file or another settings source read by a loader, as
+    /// reported by [`resolved_with_source`].
+    ConfigFile,
+    /// The installed [`PlatformSource`].
+    Platform,
+    /// Computed from other variables by [`register_derived!`].
+    Derived,
+    /// A default registered with [`Priority::Binary`].
+    BinaryDefault,
+    /// A default registered with [`Priority::Library`].
+    LibraryDefault,
+}
+
+/// Gets the most significant default registered for `name` at `priority`,
+/// resolving conditional defaults with `lookup`.
+pub(crate) fn registered_default<F>(
+    name: &str,
+    priority: Priority,
+    lookup: F,
+) -> Option<&'static str>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    inventory::iter::<RequiredVar>()
+        .filter(|v| v.name == name && v.priority == priority)
+        .last() // Get the most significant default
+        .and_then(|v| v.default_with(lookup))
+}
+
+/// Resolves the current value of a single registered variable and where it
+/// came from, without reading any configuration files.
+///
+/// The environment wins, then the platform source, then derived values, then
+/// binary defaults, then library defaults, mirroring [`load_and_validate_env_vars`]. After a load,
+/// values taken from config files live in the environment and are reported as
+/// [`VarSource::Environment`]; [`resolved_with_source`] attributes them to
+/// [`VarSource::ConfigFile`].
+///
+/// Returns `None` if `name` isn't registered or has no value, or if its
+/// name is ambiguous, as for [`RequiredVar::get`].
+pub fn resolve_one(name: &str) -> Option<(String, VarSource)> {
+    let var = inventory::iter::<RequiredVar>().find(|var| var.name == name)?;
+    let lookup = case::var;
+
+    if let Some(value) = case::lookup(name).ok()? {
+        return Some((value, VarSource::Environment));
+    }
+    let platform_value = inventory::iter::<RequiredVar>()
+        .filter(|v| v.name == name)
+        .find_map(RequiredVar::platform_value);
+    if let Some(value) = platform_value {
+        return Some((value, VarSource::Platform));
+    }
+    let derived_value = inventory::iter::<RequiredVar>()
+        .filter(|v| v.name == name)
+        .find_map(RequiredVar::derived_value);
+    if let Some(value) = derived_value {
+        return Some((value, VarSource::Derived));
+    }
+    if let Some(value) = registered_default(var.name, Priority::Binary, lookup) {
+        return Some((value.to_string(), VarSource::BinaryDefault));
+    }
+    registered_default(var.name, Priority::Library, lookup)
+        .map(|value| (value.to_string(), VarSource::LibraryDefault))
+}
+
+/// Gets the first of `names` that is set in the environment, which holds the
+/// values of loaded config files, with its value.
+///
+/// This expresses an ordered fallback such as "`PRIMARY_DB_URL`, else
+/// `DB_URL`, else `DATABASE_URL`". The names don't have to be registered, and
+/// defaults aren't considered, so a default on an earlier name doesn't hide a
+/// later one that is set.
+///
+/// Returns `Ok(None)` if none of `names` is set. Names are matched ignoring
+/// case if enabled; see [`set_case_insensitive`].
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::get_first_set;
+/// std::env::remove_var("PRIMARY_DB_URL");
+/// std::env::set_var("DB_URL", "postgres://db/app");
+///
+/// assert_eq!(
+///     get_first_set(&["PRIMARY_DB_URL", "DB_URL", "DATABASE_URL"]).unwrap(),
+///     Some(("DB_URL".to_string(), "postgres://db/app".to_string()))
+/// );
+/// ```
+///
+/// # Errors
+///
+/// * `AmbiguousVar`: If a name tried before the first one set matches
+///   several variables ignoring case.
+pub fn get_first_set(names: &[&str]) -> Result<Option<(String, String)>, EnvInventoryError> {
+    for name in names {
+        access::record_registered(name);
+        if let Some(value) = case::lookup(name)? {
+            return Ok(Some((name.to_string(), value)));
+        }
+    }
+    Ok(None)
+}
+
+/// Gets the value of a registered variable, as resolved by [`resolve_one`],
+/// parsed as `T`, typically an enum registered with [`register_enum!`].
+///
+/// Returns `Ok(None)` if `name` isn't registered or has no value.
+///
+/// # Errors
+///
+/// * `ParseValueError`: If the value can't be parsed as `T`. The value is
+///   masked if the variable is a secret.
+pub fn get_enum<T: std::str::FromStr>(name: &str) -> Result<Option<T>, EnvInventoryError> {
+    match access::read(name) {
+        Some((value, _)) => match value.parse() {
+            Ok(parsed) => Ok(Some(parsed)),
+            Err(_) => {
+                let secret = inventory::iter::<RequiredVar>()
+                    .any(|var| var.name == name && var.is_redacted());
+                Err(EnvInventoryError::ParseValueError {
+                    name: name.to_string(),
+                    value: if secret { MASK.to_string() } else { value },
+                    type_name: std::any::type_name::<T>(),
+                })
+            }
+        },
+        None => Ok(None),
+    }
+}
+
+/// Validates that all registered environment variables are set.
+///
+/// This function checks if the previously registered environment variables (via
+/// the `register!` macro or other means) are present either in the system's
+/// environment or the loaded configuration files.
+///
+/// If any of the registered variables are missing, an
+/// `EnvInventoryError::MissingEnvVars` error is returned, containing a
+/// [`MissingVar`] for each missing variable, displayed as `NAME=(missing)`,
+/// or as `NAME=<message>` when the variable was registered with a custom
+/// `error`.
+///
+/// # Parameters
+///
+/// * `config_paths`: A slice of file paths (as `&str`) pointing to the
+///   configuration files that might contain the environment variables. These
+///   files are expected to be in TOML format with a dedicated `[env]` section.
+/// * `section_name`: The name of the section in the TOML files that contains
+///   the environment variables. By default, this is `"env"`.
+///
+/// # Returns
+///
+/// * `Ok(())`: If all registered environment variables are found.
+/// * `Err(EnvInventoryError)`: If there's an error reading or parsing the
+///   config files or if any registered environment variable is missing.
+///
+/// # Examples
+///
+/// ```rust,ignore
+/// # use env_inventory::validate_env_vars;
+/// let result = validate_env_vars(&["/path/to/settings.conf"], "env");
+/// if result.is_err() {
+///     eprintln!("Failed to validate environment variables: {:?}", result);
+/// }
+/// ```
+///
+/// # Errors
+///
+/// This function can return the following errors:
+/// * `ReadFileError`: If a provided config file cannot be read.
+/// * `ParseFileError`: If a provided config file cannot be parsed as TOML or
+///   lacks the expected structure.
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing, apart from `optional` ones.
+/// * `AmbiguousVar`: If [`set_case_insensitive`] is enabled and a variable
+///   matches several environment variables ignoring case.
+/// * `InvalidValue`: If the value of a variable isn't one of its allowed
+///   values.
+/// * `ParseValueError`: If the value of a variable registered with the
+///   `base64` option doesn't decode.
+/// * `PathNotAbsolute`: If the value of a variable registered with the
+///   `absolute` option is a relative path.
+/// * `EmptyValue`: If the value of a variable registered with the
+///   `non_empty` option is empty or only whitespace.
+/// * `PlaceholderValue`: If the value of a variable registered with the
+///   `reject_placeholders` option is a placeholder.
+/// * `AssertionFailed`: If the value of a variable fails its `assert`.
+/// * `ValidationFailed`: If the value of a variable is rejected by a
+///   validator attached with [`register_validator`].
+/// * `GroupConstraintViolated`: If a group registered with
+///   [`register_group!`] is violated.
+/// * `InvariantViolated`: If a rule registered with [`register_invariant!`]
+///   doesn't hold.
+///
+/// The values of secrets are masked in these errors.
+pub fn validate_env_vars() -> Result<(), EnvInventoryError> {
+    validate_vars(inventory::iter::<RequiredVar>())?;
+    group::validate_groups()?;
+    invariant::validate_invariants()
+}
+
+/// Validates the registered environment variables required by a subcommand.
+///
+/// Variables registered with `commands = [...]` are only required when
+/// `command` is one of the listed subcommands; variables without a command
+/// scope are always required.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, validate_for_command};
+/// register!(DB_URL; commands = ["server", "migrate"]);
+///
+/// // `DB_URL` isn't needed to print the version
+/// # std::env::set_var("DB_URL", "postgres://localhost/db");
+/// validate_for_command("version").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `MissingEnvVars`: If one or more variables required by `command` are
+///   missing.
+/// * `GroupConstraintViolated`: If a group registered with
+///   [`register_group!`] is violated.
+/// * `InvariantViolated`: If a rule registered with [`register_invariant!`]
+///   doesn't hold.
+pub fn validate_for_command(command: &str) -> Result<(), EnvInventoryError> {
+    validate_vars(inventory::iter::<RequiredVar>().filter(|var| var.applies_to(command)))?;
+    group::validate_groups()?;
+    invariant::validate_invariants()
+}
+
+/// Validates only the variables, groups and invariants registered in files
+/// whose path starts with `prefix`, such as a workspace crate's
+/// `crates/billing/src/`.
+///
+/// Paths are as reported by `file!()`, relative to the workspace root. This
+/// lets a crate's tests check the variables it registered without requiring
+/// those of its dependencies.
+///
+/// # Errors
+///
+/// * `MissingEnvVars`: If one or more matching variables are missing.
+/// * `GroupConstraintViolated`: If a matching group registered with
+///   [`register_group!`] is violated.
+/// * `InvariantViolated`: If a matching rule registered with
+///   [`register_invariant!`] doesn't hold.
+pub fn validate_from_source_prefix(prefix: &str) -> Result<(), EnvInventoryError> {
+    validate_vars(inventory::iter::<RequiredVar>().filter(|var| var.source.starts_with(prefix)))?;
+    inventory::iter::<Group>()
+        .filter(|group| group.source().starts_with(prefix))
+        .try_for_each(Group::check)?;
+    let values: ResolvedValues = map().into_iter().collect();
+    invariant::check_invariants(
+        inventory::iter::<Invariant>().filter(|invariant| invariant.source.starts_with(prefix)),
+        &values,
+    )
+}
+
+fn validate_vars<'a, I>(vars: I) -> Result<(), EnvInventoryError>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let vars: Vec<&RequiredVar> = vars.into_iter().collect();
+    for var in &vars {
+        case::lookup(var.name)?;
+    }
+    let missing_vars = missing_vars(vars.iter().copied());
+
+    if !missing_vars.is_empty() {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            "Missing required environment variables: {:?}",
+            missing::rendered(&missing_vars)
+        );
+        return Err(EnvInventoryError::MissingEnvVars(missing_vars));
+    }
+    vars.into_iter().try_for_each(check_value)
+}
+
+/// Checks that the value of `var`, if any, is well-formed.
+pub(crate) fn check_value(var: &RequiredVar) -> Result<(), EnvInventoryError> {
+    match var.value() {
+        Some(value) => check_resolved_value(var, value),
+        None => Ok(()),
+    }
+}
+
+/// Checks that `value`, resolved for `var`, is well-formed.
+pub(crate) fn check_resolved_value(
+    var: &RequiredVar,
+    value: String,
+) -> Result<(), EnvInventoryError> {
+    // The values of secrets aren't shown in the errors
+    let shown = |value: String| {
+        if var.is_redacted() {
+            MASK.to_string()
+        } else {
+            value
+        }
+    };
+    if var.non_empty && value.trim().is_empty() {
+        return Err(EnvInventoryError::EmptyValue(var.name.to_string()));
+    }
+    if var.reject_placeholders && placeholder::is_placeholder(&value) {
+        return Err(EnvInventoryError::PlaceholderValue {
+            name: var.name.to_string(),
+            value: shown(value),
+        });
+    }
+    if let Some(expected) = var.expected_type {
+        if !expected.accepts(&value) {
+            return Err(EnvInventoryError::InvalidType {
+                name: var.name.to_string(),
+                value: shown(value),
+                expected,
+            });
+        }
+    }
+    if let Some(range) = var.int_range {
+        if let Err(reason) = range.check(&value) {
+            return Err(EnvInventoryError::InvalidValue {
+                name: var.name.to_string(),
+                value: shown(value),
+                reason,
+            });
+        }
+    }
+    if !var.allowed.is_empty() && !var.allowed.contains(&value.as_str()) {
+        return Err(EnvInventoryError::InvalidValue {
+            name: var.name.to_string(),
+            value: shown(value),
+            reason: format!("expected one of {:?}", var.allowed),
+        });
+    }
+    if var.absolute {
+        // `~` and variable references make a path absolute once expanded
+        let path = expand::expand(&value).unwrap_or_else(|_| value.clone());
+        if !Path::new(&path).is_absolute() {
+            return Err(EnvInventoryError::PathNotAbsolute {
+                name: var.name.to_string(),
+                path: shown(path),
+            });
+        }
+    }
+    if var.base64 && encoding::decode_base64(&value).is_err() {
+        return Err(EnvInventoryError::ParseValueError {
+            name: var.name.to_string(),
+            value: shown(value),
+            type_name: "base64",
+        });
+    }
+    if let Some(parse_check) = var.parse_check {
+        if !(parse_check.check)(&value) {
+            return Err(EnvInventoryError::ParseValueError {
+                name: var.name.to_string(),
+                value: shown(value),
+                type_name: (parse_check.type_name)(),
+            });
+        }
+    }
+    if let Some(assertion) = var.assertion {
+        if !(assertion.check)(&value) {
+            return Err(EnvInventoryError::AssertionFailed {
+                name: var.name.to_string(),
+                message: assertion.message.to_string(),
+            });
+        }
+    }
+    validator::run(var.name, &value).map_err(|message| EnvInventoryError::ValidationFailed {
+        name: var.name.to_string(),
+        message,
+    })
+}
+
+/// Lists the variables in `vars` that aren't set, sorted by their rendering
+/// as `NAME=(missing)` or `NAME=<custom error>`.
+pub(crate) fn missing_vars<'a, I>(vars: I) -> Vec<MissingVar>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    format_missing(vars.into_iter().filter(|var| !var.optional && !var.is_set()))
+}
+
+/// Describes each of the missing `vars` as in [`missing_vars`], without
+/// checking whether they are set.
+///
+/// Registrations of the same variable rendered the same way are listed once.
+pub(crate) fn format_missing<'a, I>(vars: I) -> Vec<MissingVar>
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+{
+    let missing_vars: HashMap<String, MissingVar> = vars
+        .into_iter()
+        .map(MissingVar::new)
+        .map(|var| (var.rendered.clone(), var))
+        .collect();
+    let mut missing_vars = missing_vars.into_values().collect::<Vec<MissingVar>>();
+    missing_vars.sort_by(|a, b| a.rendered.cmp(&b.rendered));
+    missing_vars
+}
+
+/// Lists the registered variables named `prefix` followed by `_`, such as
+/// those registered with [`register_prefixed!`], sorted by name.
+///
+/// `prefix` may be given with or without its trailing `_`.
+pub fn vars_with_prefix(prefix: &str) -> Vec<&'static RequiredVar> {
+    let prefix = prefix.strip_suffix('_').unwrap_or(prefix);
+    let mut vars: Vec<&'static RequiredVar> = inventory::iter::<RequiredVar>()
+        .filter(|var| {
+            var.name
+                .strip_prefix(prefix)
+                .map_or(false, |rest| rest.starts_with('_'))
+        })
+        .collect();
+    vars.sort_by_key(|var| var.name);
+    vars
+}
+
+/// List all the registered environment variables.
+/// that are expected from different parts of the application.
+pub fn list_all_vars() -> Vec<String> {
+    let mut v: Vec<String> = inventory::iter::<RequiredVar>()
+        .map(|var| {
+            var.name.to_string()
+        })
+        .collect();
+    v.sort();
+    v
+}
+
+/// List all the registered environment variables with their current values,
+/// as `NAME=value`, with the values of secrets, or any sensitivity at or
+/// above the [`redaction_level`], masked as `****`.
+///
+/// The list is safe to log; [`list_all_vars_unredacted`] shows the secrets.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{list_all_vars_redacted, register};
+/// register!(SERVICE_NAME = "billing");
+/// register!(SIGNING_KEY = "dev-key"; secret);
+///
+/// let vars = list_all_vars_redacted();
+/// assert!(vars.contains(&"SERVICE_NAME=billing".to_string()));
+/// assert!(vars.contains(&"SIGNING_KEY=****".to_string()));
+/// ```
+pub fn list_all_vars_redacted() -> Vec<String> {
+    let mut v: Vec<String> = inventory::iter::<RequiredVar>().map(|var| var.to_string()).collect();
+    v.sort();
+    v
+}
+
+/// Like [`list_all_vars_redacted`], with the values of secrets shown. For
+/// debugging only: don't log the result.
+pub fn list_all_vars_unredacted() -> Vec<String> {
+    let mut v: Vec<String> = inventory::iter::<RequiredVar>()
+        .map(|var| Unredacted(var).to_string())
+        .collect();
+    v.sort();
+    v
+}
+
+/// Dump all the registered environment variables to stderr.
+///
+/// The defaults of secrets are masked; [`dump_all_vars_unredacted`] shows
+/// them. Use [`dump_all_vars_to`] to dump elsewhere.
+pub fn dump_all_vars() {
+    let _ = dump_all_vars_to(&mut std::io::stderr());
+}
+
+/// Writes all the registered environment variables to `w`, sorted, each in
+/// its `{:#?}` form on its own lines, as in [`dump_all_vars`].
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{dump_all_vars_to, register};
+/// register!(QUEUE_NAME = "jobs");
+///
+/// let mut out = Vec::new();
+/// dump_all_vars_to(&mut out).unwrap();
+/// assert!(String::from_utf8(out).unwrap().contains("name: \"QUEUE_NAME\""));
+/// ```
+///
+/// # Errors
+///
+/// Returns any error from writing to `w`.
+pub fn dump_all_vars_to<W: std::io::Write + ?Sized>(w: &mut W) -> std::io::Result<()> {
+    let v: Vec<String> = inventory::iter::<RequiredVar>()
+        .map(|v| format!("{:#?}", v)).collect();
+    write_dump(w, v)
+}
+
+/// Like [`dump_all_vars`], with the defaults of secrets shown. For debugging
+/// only.
+pub fn dump_all_vars_unredacted() {
+    let v: Vec<String> = inventory::iter::<RequiredVar>()
+        .map(|v| format!("{:#?}", Unredacted(v))).collect();
+    let _ = write_dump(&mut std::io::stderr(), v);
+}
+
+fn write_dump<W: std::io::Write + ?Sized>(w: &mut W, mut v: Vec<String>) -> std::io::Result<()> {
+    v.sort();
+    for var in v {
+        writeln!(w, "{}", var)?;
+    }
+    Ok(())
+}
+
+/// Returns the effective value of every registered variable that has one,
+/// keyed by name.
+///
+/// Each value resolves as in [`resolve_one`]: the environment, which holds
+/// the values of loaded config files, then the platform source, derived
+/// values, and finally the binary and library defaults, with conditional
+/// defaults applied. Unlike [`expanded_map`], values are not expanded and
+/// nothing is written to the environment.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{effective_map, register};
+/// register!(CACHE_DIR = "/var/cache/app");
+///
+/// assert_eq!(effective_map()["CACHE_DIR"], "/var/cache/app");
+/// ```
+pub fn effective_map() -> HashMap<String, String> {
+    map()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect()
+}
+
+/// Like [`effective_map`], keyed by the registered names.
+pub(crate) fn map() -> HashMap<&'static str, String> {
+    let mut seen_vars: HashMap<&'static str, String> = HashMap::new();
+
+    for var in inventory::iter::<RequiredVar>() {
+        if !seen_vars.contains_key(var.name) {
+            if let Some(value) = var.value() {
+                seen_vars.insert(var.name, value);
+            }
+        }
+    }
+    seen_vars
+}
+
+/// Returns the resolved name/value pairs of all registered variables, sorted
+/// by name.
+///
+/// Unlike the maps returned by other functions, the order is deterministic,
+/// which makes this suitable for display and snapshot assertions. Variables
+/// without a value are skipped, and the values of variables at or above the
+/// [`redaction_level`] are replaced by `****`.
+pub fn sorted_values() -> Vec<(String, String)> {
+    let redacted: HashSet<&str> = inventory::iter::<RequiredVar>()
+        .filter(|var| var.is_redacted())
+        .map(|var| var.name)
+        .collect();
+    sorted_unredacted_values()
+        .into_iter()
+        .map(|(name, value)| {
+            if redacted.contains(name.as_str()) {
+                (name, MASK.to_string())
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
+/// Like [`sorted_values`], with the values of secrets, for the renderings
+/// that redact them differently or need them.
+pub(crate) fn sorted_unredacted_values() -> Vec<(String, String)> {
+    let mut values: Vec<(String, String)> = effective_map().into_iter().collect();
+    values.sort();
+    values
+}
+
+/// Passes the resolved value of every registered variable to a child process.
+///
+/// This calls [`Command::env`](std::process::Command::env) for each variable
+/// that has a value, secrets included since the child needs them.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::process::Command;
+/// let mut cmd = Command::new("worker");
+/// env_inventory::apply_to_command(&mut cmd).spawn().unwrap();
+/// ```
+pub fn apply_to_command(cmd: &mut std::process::Command) -> &mut std::process::Command {
+    apply_to_command_filtered(cmd, |_| true)
+}
+
+/// Like [`apply_to_command`], but only passes the variables whose name
+/// satisfies `filter`, e.g. a prefix or membership of a [`Group`].
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::process::Command;
+/// let mut cmd = Command::new("worker");
+/// env_inventory::apply_to_command_filtered(&mut cmd, |name| name.starts_with("WORKER_"));
+/// ```
+pub fn apply_to_command_filtered<F>(
+    cmd: &mut std::process::Command,
+    filter: F,
+) -> &mut std::process::Command
+where
+    F: Fn(&str) -> bool,
+{
+    for (name, value) in map() {
+        if filter(name) {
+            cmd.env(name, value);
+        }
+    }
+    cmd
+}
+
+/// Expand all the registered environment variables.
+/// that are expected from different parts of the application.
+/// So for instance if you have a variable like this:
+/// ```rust,ignore
+/// // somewhere
+/// register!(TEST_ENV_VAR = "~/test");
+/// // elsewhere you do this:
+/// register!(LIBDIR = "${TEST_ENV_VAR}/lib");
+/// // then you can do this:
+///
+/// ```
+/// then expanded_map will update the env and expand the env.
+///
+/// Variables are expanded after the variables they reference, so chains of
+/// references resolve regardless of the order of registration. Derived
+/// variables and conditional defaults are resolved after their inputs.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{expanded_map, register_derived, EnvInventoryError};
+/// fn first(inputs: &[&str]) -> String {
+///     inputs[0].to_string()
+/// }
+/// register_derived!(PRIMARY_HOST, from = [REPLICA_HOST], compute = first);
+/// register_derived!(REPLICA_HOST, from = [PRIMARY_HOST], compute = first);
+///
+/// match expanded_map() {
+///     Err(EnvInventoryError::CyclicReference(cycle)) => {
+///         assert_eq!(cycle, ["PRIMARY_HOST", "REPLICA_HOST", "PRIMARY_HOST"]);
+///     }
+///     other => panic!("expected a cycle, got {:?}", other),
+/// }
+///
+/// // Setting either one breaks the cycle
+/// std::env::set_var("REPLICA_HOST", "db.internal");
+/// assert_eq!(expanded_map().unwrap()["PRIMARY_HOST"], "db.internal");
+/// ```
+///
+/// # Errors
+///
+/// * `CyclicReference`: If values reference each other, directly or through
+///   other variables, or variables are derived from each other. Nothing is
+///   expanded in that case.
+/// * `MissingEnvVar`: If a reference can't be expanded.
+///
+/// TODO:
+/// 1. This should be done in the register macro.
+pub fn expanded_map() -> Result<HashMap<String, String>, EnvInventoryError> {
+    let _lock = sync::lock_env();
+    let resolution = resolve::resolve(case::lookup)?;
+    for (name, value) in &resolution.values {
+        sync::set_var(name, value);
+    }
+    Ok(resolution.values)
+}
+
+/// Gets the value of a registered variable before and after expansion of
+/// `~`, `$VAR` and `${VAR}` references, or those of the selected
+/// [`ExpansionStyle`].
+///
+/// The loaders store expanded values in the environment, so for a variable
+/// they expanded, the value before expansion is the one they started from.
+/// Otherwise it is the current value.
+///
+/// As with [`expanded_map`], references are looked up in the environment.
+/// Returns `None` if `name` isn't registered, has no value, or its expansion
+/// fails; [`expanded_map`] reports why.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{get_raw_and_expanded, register};
+/// register!(APP_CACHE = "${APP_DATA}/cache");
+/// std::env::set_var("APP_DATA", "/srv/app");
+///
+/// let (raw, expanded) = get_raw_and_expanded("APP_CACHE").unwrap();
+/// assert_eq!(raw, "${APP_DATA}/cache");
+/// assert_eq!(expanded, "/srv/app/cache");
+/// ```
+pub fn get_raw_and_expanded(name: &str) -> Option<(String, String)> {
+    let (value, _) = resolve_one(name)?;
+    let raw = provenance::raw_value(name).unwrap_or(value);
+    let expanded = expand::expand(&raw).ok()?;
+    Some((raw, expanded))
+}
+
+/// Loads the settings of each of `sections` from a config file in the given
+/// `format`, with later sections winning. No sections read the top level.
+///
+/// If a TOML file has the `profile` section, it must be a table.
+pub(crate) fn load_file_sections(
+    path: &Path,
+    sections: &[&str],
+    format: ConfigFormat,
+    profile: Option<&str>,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    if sections.is_empty() {
+        return load_file_settings_as(path, "", format, false);
+    }
+    let mut settings = HashMap::new();
+    for section in sections {
+        let require_table = profile == Some(*section);
+        settings.extend(load_file_settings_as(path, section, format, require_table)?);
+    }
+    Ok(settings)
+}
+
+/// Loads the settings from a config file in the given `format`.
+fn load_file_settings_as(
+    path: &Path,
+    section: &str,
+    format: ConfigFormat,
+    require_table: bool,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    match format {
+        ConfigFormat::ByExtension => load_file_settings(path, section, require_table),
+        ConfigFormat::Auto if has_known_extension(path) => {
+            match load_file_settings(path, section, require_table) {
+                Err(EnvInventoryError::ParseFileError(_)) => sniff_file_settings(path, section),
+                loaded => loaded,
+            }
+        }
+        ConfigFormat::Auto => sniff_file_settings(path, section),
+        format => {
+            let content = fs::read_to_string(path)
+                .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+            parse_settings(&content, &path.display().to_string(), section, format)
+        }
+    }
+}
+
+/// Parses the settings of `content` read from `source`, which has no name to
+/// tell its format: [`ConfigFormat::ByExtension`] reads it as TOML.
+fn parse_settings(
+    content: &str,
+    source: &str,
+    section: &str,
+    format: ConfigFormat,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    match format {
+        ConfigFormat::ByExtension | ConfigFormat::Toml => {
+            parse_toml_settings(content, source, section, false)
+        }
+        ConfigFormat::Auto => sniff_settings(content, source, section),
+        ConfigFormat::Dotenv => dotenv::parse_dotenv_config(content, source),
+        #[cfg(feature = "json")]
+        ConfigFormat::Json => json::parse_json_settings(content, source, section),
+        #[cfg(feature = "yaml")]
+        ConfigFormat::Yaml => yaml::parse_yaml_settings(content, source, section),
+    }
+}
+
+/// Loads config values in the given `format` from `reader`, then validates
+/// the registered variables.
+///
+/// This is [`load_and_validate_env_vars`] for config that doesn't come from a
+/// named file: a pipe, a socket, an inherited file descriptor or bytes in
+/// memory. As there is no file name, [`ConfigFormat::ByExtension`] reads
+/// TOML.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{load_from_reader, register, ConfigFormat};
+/// # use std::io::Cursor;
+/// register!(QUEUE_URL);
+///
+/// let config = Cursor::new("[env]\nQUEUE_URL = \"amqp://localhost\"\n");
+/// load_from_reader(config, "env", ConfigFormat::Toml).unwrap();
+/// assert_eq!(std::env::var("QUEUE_URL").unwrap(), "amqp://localhost");
+/// ```
+///
+/// # Errors
+///
+/// * `ReadFileError`: If `reader` fails or doesn't provide UTF-8.
+/// * `ParseFileError`: If the config can't be parsed in `format`.
+/// * `UnrecognizedConfigFormat`: If `format` is [`ConfigFormat::Auto`] and
+///   the config doesn't parse in any format tried.
+/// * `SchemaVersionMismatch`: If the config doesn't declare the schema
+///   version registered with [`register_schema_version!`].
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_from_reader<R: Read>(
+    mut reader: R,
+    section: &str,
+    format: ConfigFormat,
+) -> Result<(), EnvInventoryError> {
+    let mut content = String::new();
+    reader
+        .read_to_string(&mut content)
+        .map_err(|_| EnvInventoryError::ReadFileError(READER_SOURCE.to_string()))?;
+    let settings = parse_settings(&content, READER_SOURCE, section, format)?;
+    let origins = settings
+        .keys()
+        .map(|name| (name.clone(), READER_SOURCE.to_string()))
+        .collect();
+    apply_settings(&settings, &origins)
+}
+
+/// How errors and provenance refer to config read by [`load_from_reader`].
+const READER_SOURCE: &str = "<reader>";
+
+/// Checks if the name of `path` tells its format.
+fn has_known_extension(path: &Path) -> bool {
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    dotenv::is_dotenv(path)
+        || extension == Some("toml")
+        || (cfg!(feature = "json") && extension == Some("json"))
+        || (cfg!(feature = "yaml") && matches!(extension, Some("yaml") | Some("yml")))
+}
+
+/// Loads the settings from a config file in the first format it parses as:
+/// TOML, then JSON and YAML if enabled. A format providing settings from
+/// `section` is preferred over an earlier one that parses without it.
+fn sniff_file_settings(
+    path: &Path,
+    section: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let content = fs::read_to_string(path)
+        .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+    sniff_settings(&content, &path.display().to_string(), section)
+}
+
+/// Parses the settings of `content` read from `source` as
+/// [`sniff_file_settings`] does for files.
+fn sniff_settings(
+    content: &str,
+    source: &str,
+    section: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    #[allow(clippy::type_complexity)]
+    let formats: &[(
+        &str,
+        fn(&str, &str, &str) -> Result<HashMap<String, String>, EnvInventoryError>,
+    )] = &[
+        ("TOML", |content, source, section| parse_toml_settings(content, source, section, false)),
+        #[cfg(feature = "json")]
+        ("JSON", json::parse_json_settings),
+        #[cfg(feature = "yaml")]
+        ("YAML", yaml::parse_yaml_settings),
+    ];
+
+    let mut parsed = None;
+    for (_, parse) in formats {
+        match parse(content, source, section) {
+            Ok(settings) if !settings.is_empty() => return Ok(settings),
+            Ok(settings) => {
+                parsed.get_or_insert(settings);
+            }
+            Err(EnvInventoryError::ParseFileError(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    parsed.ok_or_else(|| EnvInventoryError::UnrecognizedConfigFormat {
+        path: source.to_string(),
+        tried: formats.iter().map(|(name, _)| *name).collect(),
+    })
+}
+
+/// Loads the settings from a config file, read as a `.env` file if its name
+/// says so, as JSON or YAML if it has the `json`, or `yaml` or `yml`,
+/// extension and the matching feature is enabled, and as TOML otherwise.
+///
+/// With `require_table`, a TOML `section` that isn't a table fails with
+/// `ParseFileError`.
+fn load_file_settings(
+    path: &Path,
+    section: &str,
+    require_table: bool,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    #[cfg(feature = "json")]
+    if path.extension().map_or(false, |ext| ext == "json") {
+        return json::load_json_settings(path, section);
+    }
+    #[cfg(feature = "yaml")]
+    if path.extension().map_or(false, |ext| ext == "yaml" || ext == "yml") {
+        return yaml::load_yaml_settings(path, section);
+    }
+    if dotenv::is_dotenv(path) {
+        dotenv::load_dotenv_config(path)
+    } else {
+        let content = fs::read_to_string(path)
+            .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+        parse_toml_settings(&content, &path.display().to_string(), section, require_table)
+    }
+}
+
+/// Loads the settings from a TOML file and returns them as a `HashMap`.
+///
+/// Only string values of the `section` table are read; an empty `section`
+/// reads the string values at the top level of the file.
+pub(crate) fn load_toml_settings<P: AsRef<Path>>(
+    path: P,
+    section: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let content = fs::read_to_string(&path)
+        .map_err(|_| EnvInventoryError::ReadFileError(path.as_ref().display().to_string()))?;
+    parse_toml_settings(&content, &path.as_ref().display().to_string(), section, false)
+}
+
+/// Parses the settings of TOML `content` read from `source`, as
+/// [`load_toml_settings`] does for files. With `require_table`, a `section`
+/// that isn't a table fails with `ParseFileError`.
+fn parse_toml_settings(
+    content: &str,
+    source: &str,
+    section: &str,
+    require_table: bool,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let value = content
+        .parse::<Value>()
+        .map_err(|_| EnvInventoryError::ParseFileError(source.to_string()))?;
+
+    check_schema_version(expected_schema_version(), &value)?;
+
+    // An empty section name means the variables live at the top level
+    let env_section = if section.is_empty() {
+        value.as_table()
+    } else {
+        match toml_section(&value, section) {
+            Some(found) if require_table && !found.is_table() => {
+                return Err(EnvInventoryError::ParseFileError(source.to_string()));
+            }
+            found => found.and_then(Value::as_table),
+        }
+    };
+
+    let mut settings = HashMap::new();
+
+    if let Some(env_table) = env_section {
+        for (key, val) in env_table.iter() {
+            if let Some(val_str) = val.as_str() {
+                settings.insert(key.clone(), val_str.to_string());
+            }
+        }
+        // A `types` string is an ordinary setting, not type declarations
+        if let Some(types) = env_table.get("types").and_then(Value::as_table) {
+            check_declared_types(types, &settings, source)?;
+        }
+    }
+
+    Ok(settings)
+}
+
+/// Gets `section` of a parsed TOML file: the key named `section`, or else
+/// the nested tables its dots separate.
+fn toml_section<'a>(value: &'a Value, section: &str) -> Option<&'a Value> {
+    value
+        .get(section)
+        .or_else(|| section.split('.').try_fold(value, |value, key| value.get(key)))
+}
+
+/// Checks the `settings` of a section against the types its `types` table
+/// declares, such as `PORT = "int"`.
+fn check_declared_types(
+    types: &toml::Table,
+    settings: &HashMap<String, String>,
+    source: &str,
+) -> Result<(), EnvInventoryError> {
+    let parse_error = || EnvInventoryError::ParseFileError(source.to_string());
+    for (name, declared) in types {
+        let expected = declared
+            .as_str()
+            .and_then(ExpectedType::from_declared)
+            .ok_or_else(parse_error)?;
+        match settings.get(name) {
+            Some(value) if !expected.accepts(value) => {
+                return Err(EnvInventoryError::InvalidType {
+                    name: name.clone(),
+                    value: value.clone(),
+                    expected,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// The schema version expected by the registrations, if any. The newest
+/// version wins if several are registered.
+pub(crate) fn expected_schema_version() -> Option<u32> {
+    inventory::iter::<SchemaVersion>()
+        .map(|version| version.version)
+        .max()
+}
+
+/// Checks that a parsed config file declares the `expected` `schema_version`.
+fn check_schema_version(expected: Option<u32>, value: &Value) -> Result<(), EnvInventoryError> {
+    check_found_schema_version(expected, value.get("schema_version").and_then(Value::as_integer))
+}
+
+/// Checks that flat config `settings`, such as a `.env` file or a directory
+/// of files, declare the expected schema version as `SCHEMA_VERSION`, which
+/// is removed from them. Without a registered schema version,
+/// `SCHEMA_VERSION` is an ordinary setting.
+pub(crate) fn take_schema_version(
+    settings: &mut HashMap<String, String>,
+) -> Result<(), EnvInventoryError> {
+    let expected = match expected_schema_version() {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+    let found = settings
+        .remove("SCHEMA_VERSION")
+        .and_then(|version| version.trim().parse().ok());
+    check_found_schema_version(Some(expected), found)
+}
+
+/// Checks that the `schema_version` `found` in a config file, of any format,
+/// is the `expected` one.
+pub(crate) fn check_found_schema_version(
+    expected: Option<u32>,
+    found: Option<i64>,
+) -> Result<(), EnvInventoryError> {
+    let expected = match expected {
+        Some(expected) => expected,
+        None => return Ok(()),
+    };
+
+    if found == Some(i64::from(expected)) {
+        Ok(())
+    } else {
+        Err(EnvInventoryError::SchemaVersionMismatch { expected, found })
+    }
+}
+
+/// Loads environment variables from specified configuration files and validates
+/// their presence.
+///
+/// This function goes through the provided list of configuration file paths,
+/// merges the environment settings from each file, and ensures that all the
+/// registered environment variables are set. If an environment variable
+/// is not already present in the system's environment, it will be set using the
+/// value from the merged settings.
+///
+/// Environment variables present in the system's environment take precedence
+/// over those in the configuration files.
+///
+/// # Parameters
+///
+/// * `config_paths`: A slice containing paths to the configuration files that
+///   should be loaded. The files are expected to be in TOML format and have a
+///   dedicated section for environment variables. Files named `.env` or
+///   `.env.*`, or with the `env` extension, are read as `.env` files instead
+///   (see [`load_dotenv`]), without sections. With the `json` feature, files
+///   with the `json` extension are read as JSON, with nested objects
+///   flattened as for `load_from_json_env`. With the `yaml` feature, files
+///   with the `yaml` or `yml` extension are read as YAML, with nested
+///   mappings flattened by joining keys with `.`.
+/// * `section`: The name of the section in the TOML files that contains the
+///   environment variables. Pass `""` for files that put the variables at the
+///   top level, without a section. A list of sections, such as
+///   `&["env", "env.production"]`, reads each of them in order, with later
+///   sections winning and missing ones skipped; see [`Sections`]. A `types`
+///   table in a TOML section, such as `[env.types]`, declares the types of
+///   its values, as in `PORT = "int"`; the types are `string`, `int`,
+///   `uint`, `float` and `bool`.
+///
+/// # Returns
+///
+/// * `Ok(())`: If all registered environment variables are present either in
+///   the system's environment or in the merged settings.
+/// * `Err(EnvInventoryError)`: If there's an error reading or parsing the
+///   config files or if any registered environment variable is missing.
+///
+/// # Behavior
+///
+/// The first file in the `config_paths` slice is mandatory and if it can't be
+/// read or parsed, an error is immediately returned. Subsequent files are
+/// optional, and while they will generate a warning if they cannot be read or
+/// parsed, they won't cause the function to return an error.
+///
+/// After merging the settings from all files and overlaying them on the
+/// system's environment variables, the function checks for missing required
+/// environment variables and returns an error if any are found.
+///
+/// # Thread safety
+///
+/// The loaders, [`expanded_map`] and [`load_dotenv`] hold a crate-wide lock
+/// while they write to the environment and validate it, so concurrent loads
+/// run one after the other. The lock doesn't cover other writes or reads made
+/// through the C library, which `std::env::set_var` can race with on some
+/// platforms; load the config before spawning threads that read the
+/// environment that way.
+///
+/// # Examples
+///
+/// ```rust (ignore)
+/// # use env_inventory::load_and_validate_env_vars;
+/// # use std::path::Path;
+/// let paths = [Path::new("/path/to/shipped.conf"), Path::new("/path/to/system.conf")];
+/// let result = load_and_validate_env_vars(&paths, "env");
+/// if result.is_err() {
+///     eprintln!("Failed to load and validate environment variables: {:?}", result);
+/// }
+/// ```
+///
+/// # Errors
+///
+/// This function can return the following errors:
+/// * `ReadFileError`: If a provided config file cannot be read.
+/// * `ParseFileError`: If a provided config file cannot be parsed in its
+///   format or lacks the expected structure.
+/// * `SchemaVersionMismatch`: If a config file doesn't declare the schema
+///   version registered with [`register_schema_version!`].
+/// * `InvalidType`: If a value in a config file doesn't have the type its
+///   section declares.
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_and_validate_env_vars<P: AsRef<Path>, S: Sections + ?Sized>(
+    config_paths: &[P],
+    section: &S,
+) -> Result<(), EnvInventoryError> {
+    load_and_validate_files(&positional_file_kinds(config_paths), section)
+}
+
+/// Pairs `config_paths` with their kind: the first file is mandatory, the
+/// rest optional.
+pub(crate) fn positional_file_kinds<P: AsRef<Path>>(config_paths: &[P]) -> Vec<(&Path, FileKind)> {
+    config_paths
+        .iter()
+        .enumerate()
+        .map(|(index, path)| {
+            // The first file is mandatory
+            let kind = if index == 0 {
+                FileKind::Mandatory
+            } else {
+                FileKind::Optional
+            };
+            (path.as_ref(), kind)
+        })
+        .collect()
+}
+
+/// Whether a configuration file must be present for loading to succeed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileKind {
+    /// The file must exist and parse; otherwise loading fails.
+    Mandatory,
+    /// The file only supplies fallbacks; if it can't be read or parsed, a
+    /// warning is printed and loading continues.
+    Optional,
+}
+
+/// Loads environment variables from configuration files, each explicitly
+/// marked as mandatory or optional, and validates their presence.
+///
+/// This behaves like [`load_and_validate_env_vars`], except that whether a
+/// file is required is given by its [`FileKind`] rather than its position in
+/// the list. Later files still override earlier ones.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::{load_and_validate_files, FileKind};
+/// load_and_validate_files(
+///     &[
+///         ("/etc/app/defaults.toml", FileKind::Optional),
+///         ("/etc/app/app.toml", FileKind::Mandatory),
+///     ],
+///     "env",
+/// )
+/// .unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ReadFileError`: If a mandatory config file cannot be read.
+/// * `ParseFileError`: If a mandatory config file cannot be parsed as TOML.
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_and_validate_files<P: AsRef<Path>, S: Sections + ?Sized>(
+    files: &[(P, FileKind)],
+    section: &S,
+) -> Result<(), EnvInventoryError> {
+    load_and_validate_files_with(files, section, false)
+}
+
+/// Like [`load_and_validate_files`], but when `detect_config_conflicts` is
+/// `true`, two files setting the same variable to different values is an
+/// error rather than the later file silently winning.
+///
+/// See [`LoaderConfig`] for more options.
+///
+/// # Errors
+///
+/// Returns any error [`load_and_validate_files`] can return, and
+/// `ConfigValueConflict` listing every conflicting variable.
+pub fn load_and_validate_files_with<P: AsRef<Path>, S: Sections + ?Sized>(
+    files: &[(P, FileKind)],
+    section: &S,
+    detect_config_conflicts: bool,
+) -> Result<(), EnvInventoryError> {
+    LoaderConfig::new()
+        .detect_config_conflicts(detect_config_conflicts)
+        .load_files(files, section)
+        .map(|_| ())
+}
+
+/// Loads a base config file followed by its drop-in fragments, then validates
+/// the registered environment variables.
+///
+/// Every `*.conf` and `*.toml` file in `confd_dir` is loaded after
+/// `base_file` in lexical order of file name, so later fragments override
+/// earlier ones, as with systemd or nginx drop-in directories. A missing
+/// `confd_dir` is treated as having no fragments.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::load_confd;
+/// # use std::path::Path;
+/// load_confd(Path::new("/etc/app/app.toml"), Path::new("/etc/app/conf.d"), "env").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ReadFileError`: If the base file, `confd_dir` or a fragment can't be
+///   read.
+/// * `ParseFileError`: If the base file or a fragment can't be parsed as TOML.
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_confd<S: Sections + ?Sized>(
+    base_file: &Path,
+    confd_dir: &Path,
+    section: &S,
+) -> Result<(), EnvInventoryError> {
+    let mut files = vec![(base_file.to_path_buf(), FileKind::Mandatory)];
+    files.extend(
+        confd_fragments(confd_dir)?
+            .into_iter()
+            .map(|path| (path, FileKind::Mandatory)),
+    );
+    load_and_validate_files(&files, section)
+}
+
+/// Loads the first of `candidates` that can be read, ignoring the rest, then
+/// validates the registered environment variables.
+///
+/// This suits applications that search standard locations, such as
+/// `./app.toml`, then `~/.config/app/app.toml`, then `/etc/app/app.toml`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::load_first_existing;
+/// load_first_existing(&["app.toml", "/etc/app/app.toml"], "env").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `NoConfigFile`: If none of the candidates can be read.
+/// * `ParseFileError`: If the chosen file cannot be parsed as TOML.
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_first_existing<P: AsRef<Path>, S: Sections + ?Sized>(
+    candidates: &[P],
+    section: &S,
+) -> Result<(), EnvInventoryError> {
+    let readable = |path: &&P| path.as_ref().is_file() && fs::File::open(path).is_ok();
+    match candidates.iter().find(readable) {
+        Some(path) => load_and_validate_files(&[(path, FileKind::Mandatory)], section),
+        None => Err(EnvInventoryError::NoConfigFile(
+            candidates
+                .iter()
+                .map(|path| path.as_ref().display().to_string())
+                .collect(),
+        )),
+    }
+}
+
+/// Loads the config files listed in the environment variable `var`, then
+/// validates the registered environment variables.
+///
+/// `var` holds a list of paths separated like `PATH`, by `:` on Unix and `;`
+/// on Windows, so a deployment can choose the whole set of config files with
+/// one variable. The files are loaded as by [`load_and_validate_env_vars`]:
+/// in order, with later files overriding earlier ones, the first mandatory
+/// and the rest optional.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::load_from_path_list_env;
+/// // APP_CONFIG_PATH=/etc/app/app.toml:/etc/app/local.toml
+/// load_from_path_list_env("APP_CONFIG_PATH", "env").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `MissingEnvVar`: If `var` isn't set.
+///
+/// Returns any other error [`load_and_validate_env_vars`] can return.
+pub fn load_from_path_list_env<S: Sections + ?Sized>(
+    var: &str,
+    section: &S,
+) -> Result<(), EnvInventoryError> {
+    let paths = env::var_os(var).ok_or_else(|| EnvInventoryError::MissingEnvVar(var.to_string()))?;
+    let paths: Vec<PathBuf> = env::split_paths(&paths).collect();
+    load_and_validate_env_vars(&paths, section)
+}
+
+/// Loads `config_paths` like [`load_and_validate_env_vars`], layering the
+/// section of the profile named by the environment variable `profile_var`
+/// over `base_section`.
+///
+/// With `APP_ENV=staging`, `load_with_profile(&paths, "env", "APP_ENV")`
+/// reads `[env]` then `[env.staging]`, whose values win. If `profile_var`
+/// isn't set or is empty, only `base_section` is read, as is a file without
+/// the profile section.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::load_with_profile;
+/// std::env::set_var("APP_ENV", "staging");
+/// load_with_profile(&["/etc/app/config.toml"], "env", "APP_ENV").unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ParseFileError`: If the profile section exists but isn't a table.
+///
+/// Returns any other error [`load_and_validate_env_vars`] can return.
+pub fn load_with_profile<P: AsRef<Path>>(
+    config_paths: &[P],
+    base_section: &str,
+    profile_var: &str,
+) -> Result<(), EnvInventoryError> {
+    match env::var(profile_var) {
+        Ok(profile) if !profile.is_empty() => {
+            let profile_section = format!("{}.{}", base_section, profile);
+            let config = LoaderConfig {
+                profile_section: Some(profile_section.clone()),
+                ..LoaderConfig::new()
+            };
+            config
+                .load(config_paths, &[base_section, &profile_section])
+                .map(|_| ())
+        }
+        _ => load_and_validate_env_vars(config_paths, base_section),
+    }
+}
+
+/// Loads config values from a directory holding one file per variable, then
+/// validates the registered environment variables.
+///
+/// Each file provides the variable named after it, with its contents, minus
+/// surrounding whitespace, as the value. This is the layout of Kubernetes
+/// secret and ConfigMap volumes. Hidden files, such as the `..data` links
+/// Kubernetes maintains, and subdirectories are skipped. The directory takes
+/// the place of a config file: the environment wins over it, and it wins
+/// over the platform source and defaults.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::load_from_dir;
+/// # use std::path::Path;
+/// load_from_dir(Path::new("/var/run/secrets/app")).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ReadFileError`: If `dir` or one of its files can't be read.
+/// * `SchemaVersionMismatch`: If a schema version is registered with
+///   [`register_schema_version!`] and the `SCHEMA_VERSION` file doesn't
+///   hold it.
+/// * `MissingEnvVars`: If one or more registered environment variables are
+///   missing.
+pub fn load_from_dir(dir: &Path) -> Result<(), EnvInventoryError> {
+    let entries = fs::read_dir(dir)
+        .map_err(|_| EnvInventoryError::ReadFileError(dir.display().to_string()))?;
+
+    let mut settings = HashMap::new();
+    let mut origins = HashMap::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|_| EnvInventoryError::ReadFileError(dir.display().to_string()))?
+            .path();
+        let name = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if !name.starts_with('.') && path.is_file() => name.to_string(),
+            _ => continue,
+        };
+        let value = fs::read_to_string(&path)
+            .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+        settings.insert(name.clone(), value.trim().to_string());
+        origins.insert(name, path.display().to_string());
+    }
+    take_schema_version(&mut settings)?;
+    apply_settings(&settings, &origins)
+}
+
+/// Lists the `*.conf` and `*.toml` files in `dir`, sorted by file name.
+fn confd_fragments(dir: &Path) -> Result<Vec<PathBuf>, EnvInventoryError> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(_) => return Err(EnvInventoryError::ReadFileError(dir.display().to_string())),
+    };
+
+    let mut fragments = Vec::new();
+    for entry in entries {
+        let path = entry
+            .map_err(|_| EnvInventoryError::ReadFileError(dir.display().to_string()))?
+            .path();
+        let is_fragment = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("conf") | Some("toml")
+        );
+        if is_fragment && path.is_file() {
+            fragments.push(path);
+        }
+    }
+    fragments.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+    Ok(fragments)
+}
+
+/// Merges the settings of `config_paths`, then the registered variables set
+/// in the environment, combining the values of a key found in more than one
+/// of them with `reducer`.
+///
+/// Sources are merged from least to most significant: the config files in
+/// order, then the environment. `reducer` is called with the key, the value
+/// merged so far and the incoming value, and returns the merged value;
+/// [`incoming_wins`] gives the usual precedence, while a reducer
+/// concatenating the values lets list variables accumulate across sources.
+/// As for [`load_and_validate_env_vars`], the first file is mandatory and the
+/// rest optional.
+///
+/// Nothing is set in the environment, and defaults aren't included. Values a
+/// loader set in the environment, such as those of config files loaded
+/// earlier, aren't merged again: only values set by the caller count as the
+/// environment.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::resolve_with_reducer;
+/// fn append(_key: &str, existing: &str, incoming: &str) -> String {
+///     format!("{},{}", existing, incoming)
+/// }
+///
+/// let merged = resolve_with_reducer(&["base.toml", "local.toml"], "env", append).unwrap();
+/// ```
+///
+/// # Errors
+///
+/// * `ReadFileError`: If the first config file cannot be read.
+/// * `ParseFileError`: If the first config file cannot be parsed.
+/// * `SchemaVersionMismatch`: If a config file doesn't declare the schema
+///   version registered with [`register_schema_version!`].
+pub fn resolve_with_reducer<P: AsRef<Path>, S: Sections + ?Sized>(
+    config_paths: &[P],
+    section: &S,
+    reducer: fn(&str, &str, &str) -> String,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let (mut merged, _) = merge_settings_with(
+        &positional_file_kinds(config_paths),
+        &section.names(),
+        &LoaderConfig::new(),
+        reducer,
+    )?;
+    let names: HashSet<&str> = inventory::iter::<RequiredVar>().map(|var| var.name).collect();
+    for name in names {
+        // A value a loader set is already merged from its source, or is a default
+        if provenance::origin(name).is_some() {
+            continue;
+        }
+        if let Ok(value) = env::var(name) {
+            reduce_into(&mut merged, name.to_string(), value, reducer);
+        }
+    }
+    Ok(merged)
+}
+
+/// The reducer giving the usual precedence: the incoming, more significant
+/// value replaces the existing one.
+///
+/// See [`resolve_with_reducer`].
+pub fn incoming_wins(_key: &str, _existing: &str, incoming: &str) -> String {
+    incoming.to_string()
+}
+
+fn reduce_into(
+    merged: &mut HashMap<String, String>,
+    key: String,
+    incoming: String,
+    reducer: fn(&str, &str, &str) -> String,
+) {
+    let value = match merged.get(&key) {
+        Some(existing) => reducer(&key, existing, &incoming),
+        None => incoming,
+    };
+    merged.insert(key, value);
+}
+
+/// Merges the settings of `files`, with the nth file being most significant.
+///
+/// Also returns the file each merged value came from.
+#[allow(clippy::type_complexity)]
+pub(crate) fn merge_settings<P: AsRef<Path>>(
+    files: &[(P, FileKind)],
+    sections: &[&str],
+    config: &LoaderConfig,
+) -> Result<(HashMap<String, String>, HashMap<String, String>), EnvInventoryError> {
+    merge_settings_with(files, sections, config, incoming_wins)
+}
+
+/// Merges the settings of `files` like [`merge_settings`], combining the
+/// values of a key set by more than one file with `reducer`.
+#[allow(clippy::type_complexity)]
+fn merge_settings_with<P: AsRef<Path>>(
+    files: &[(P, FileKind)],
+    sections: &[&str],
+    config: &LoaderConfig,
+    reducer: fn(&str, &str, &str) -> String,
+) -> Result<(HashMap<String, String>, HashMap<String, String>), EnvInventoryError> {
+    let mut merged_settings = HashMap::new();
+    let mut sources = HashMap::new();
+    let mut origins: HashMap<String, Vec<(String, String)>> = HashMap::new();
+
+    for (path, kind) in files.iter() {
+        let loaded = loader::check_file_size(path.as_ref(), config.max_file_bytes)
+            .and_then(|()| {
+                let profile = config.profile_section.as_deref();
+                load_file_sections(path.as_ref(), sections, config.format, profile)
+            });
+        match loaded {
+            Ok(current_settings) => {
+                if config.detect_config_conflicts {
+                    for (name, value) in current_settings.iter() {
+                        origins
+                            .entry(name.clone())
+                            .or_default()
+                            .push((path.as_ref().display().to_string(), value.clone()));
+                    }
+                }
+                // Merge settings with nth file being most significant
+                for (name, value) in current_settings {
+                    sources.insert(name.clone(), path.as_ref().display().to_string());
+                    reduce_into(&mut merged_settings, name, value, reducer);
+                }
+            }
+            // A file written for another schema, too large to be config, or
+            // with values contradicting its declared types is never silently
+            // skipped
+            Err(e @ EnvInventoryError::SchemaVersionMismatch { .. })
+            | Err(e @ EnvInventoryError::FileTooLarge { .. })
+            | Err(e @ EnvInventoryError::InvalidType { .. }) => return Err(e),
+            Err(e) => match kind {
+                FileKind::Mandatory => return Err(e),
+                FileKind::Optional => {
+                    // Optional files may be absent, but let's warn for transparency
+                    eprintln!(
+                        "Warning: Could not load settings from {:?}. Reason: {}",
+                        path.as_ref(),
+                        e
+                    );
+                }
+            },
+        }
+    }
+
+    let mut conflicts: Vec<ConfigConflict> = origins
+        .into_iter()
+        .filter(|(_, values)| values.iter().any(|(_, value)| *value != values[0].1))
+        .map(|(name, values)| ConfigConflict { name, values })
+        .collect();
+    if !conflicts.is_empty() {
+        conflicts.sort_by(|a, b| a.name.cmp(&b.name));
+        return Err(EnvInventoryError::ConfigValueConflict(conflicts));
+    }
+
+    Ok((merged_settings, sources))
+}
+
+/// Resolves the registered variables from the environment, then the merged
+/// settings, the way the loaders that leave the environment untouched do,
+/// sets the expanded values in the environment and validates them.
+///
+/// `origins` maps each merged setting to the file it came from; where each
+/// value came from is recorded for [`dump_effective_config_toml`].
+pub(crate) fn apply_settings(
+    merged_settings: &HashMap<String, String>,
+    origins: &HashMap<String, String>,
+) -> Result<(), EnvInventoryError> {
+    let _lock = sync::lock_env();
+
+    // Conditional defaults may depend on values that only exist in the config
+    // files at this point
+    let lookup = |name: &str| Ok(case::lookup(name)?.or_else(|| merged_settings.get(name).cloned()));
+    let resolution = resolve::resolve(lookup)?;
+
+    let mut loaded = HashMap::new();
+    for (name, step) in &resolution.steps {
+        let origin = match step {
+            Step::Lookup if case::var(name).is_some() => continue,
+            Step::Lookup => match origins.get(name) {
+                Some(origin) => Origin::File(origin.clone()),
+                None => continue,
+            },
+            Step::Platform => Origin::Platform,
+            Step::Derived => Origin::Derived,
+            Step::Default => Origin::Default,
+        };
+        #[cfg(feature = "tracing")]
+        if let Origin::File(origin) = &origin {
+            tracing::info!("Loaded {} from {}", name, origin);
+        }
+        loaded.insert(name.clone(), origin);
+    }
+    for (name, value) in &resolution.values {
+        sync::set_var(name, value);
+    }
+
+    provenance::record_raw(resolution.raw.clone());
+    #[cfg(feature = "metrics")]
+    telemetry::record_load(&loaded);
+    provenance::record(loaded);
+    resolution.validate()?;
+    generation::record(resolution.values);
+    Ok(())
+}
+
+/// Loads and validates the registered environment variables at startup.
+///
+/// This runs [`load_and_validate_env_vars`] and, on success, snapshots the
+/// resolved values so that later changes can be reported by
+/// [`drift_since_init`].
+///
+/// # Errors
+///
+/// Returns any error [`load_and_validate_env_vars`] can return.
+pub fn init_config<P: AsRef<Path>, S: Sections + ?Sized>(
+    config_paths: &[P],
+    section: &S,
+) -> Result<(), EnvInventoryError> {
+    load_and_validate_env_vars(config_paths, section)?;
+    drift::capture();
+    Ok(())
+}
+
+/// Loads, validates, and deserializes the registered environment variables
+/// into a typed config struct in one call.
+///
+/// This is the recommended startup entry point: it runs [`init_config`] with
+/// the given paths and section, then deserializes the resolved values of
+/// every registered variable into `T`.
+///
+/// Variable names are lowercased before being matched against field names, so
+/// `DATABASE_URL` fills a `database_url` field. Values are parsed according to
+/// the field's type; sequence fields are split on commas. `Option` fields are
+/// `None` when the variable has no value.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::{init_typed, register};
+/// # use serde::Deserialize;
+/// register!(DATABASE_URL);
+/// register!(PORT = "8080");
+///
+/// #[derive(Deserialize)]
+/// struct Config {
+///     database_url: String,
+///     port: u16,
+/// }
+///
+/// let config: Config = init_typed(&["/path/to/shipped.conf"], "env").unwrap();
+/// println!("listening on {}", config.port);
+/// ```
+///
+/// # Errors
+///
+/// Returns any error [`load_and_validate_env_vars`] can return, and
+/// `DeserializeError` if the resolved values don't fit `T`.
+pub fn init_typed<T, P: AsRef<Path>, S: Sections + ?Sized>(
+    config_paths: &[P],
+    section: &S,
+) -> Result<T, EnvInventoryError>
+where
+    T: serde::de::DeserializeOwned,
+{
+    init_config(config_paths, section)?;
+    de::from_map(map()).map_err(|e| EnvInventoryError::DeserializeError(e.to_string()))
+}
+
+#[doc(hidden)]
+pub fn __old_load_and_validate_env_vars<P: AsRef<Path>>(
+    config_paths: &[P],
+    section: &str,
+) -> Result<(), EnvInventoryError> {
+    let mut merged_settings = HashMap::new();
+
+    for (index, path) in config_paths.iter().enumerate() {
+        let settings = load_toml_settings(path.as_ref(), section);
+
+        match settings {
+            Ok(current_settings) => {
+                // Merge settings
+                for (key, value) in current_settings.iter() {
+                    if !merged_settings.contains_key(key) {
+                        merged_settings.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Err(e) => {
+                if index == 0 {
+                    // The first file is mandatory
+                    return Err(e);
+                } else {
+                    // Subsequent files are optional, but let's warn for transparency
+                    eprintln!(
+                        "Warning: Could not load settings from {:?}. Reason: {}",
+                        path.as_ref(),
+                        e
+                    );
+                }
+            }
+        }
+    }
+
+    // Override the environment variables with our merged settings if they aren't
+    // already set
+    let _lock = sync::lock_env();
+    for (key, value) in merged_settings.iter() {
+        if env::var(key).is_err() {
+            sync::set_var(key, value);
+        }
+        #[cfg(feature = "tracing")]
+        tracing::info!("{} = {}", key, env::var(key).unwrap());
+    }
+    validate_env_vars()
+}
+
+/// The registry and the environment are process-global, so tests that mutate
+/// the environment must not interleave.
+#[cfg(test)]
+pub(crate) fn env_lock() -> std::sync::MutexGuard<'static, ()> {
+    static LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+    LOCK.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Sets the variables registered without a default by this crate's tests, so
+/// tests of other loaders only fail for the reason they exercise.
+#[cfg(test)]
+pub(crate) fn satisfy_required_vars() {
+    for (name, value) in [
+        ("TEST_ENV_VAR", "test_value"),
+        ("MISSING_VAR", "missing_value"),
+        ("PRESENT_VAR", "present_value"),
+    ] {
+        if env::var(name).is_err() {
+            env::set_var(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    register!(TEST_ENV_VAR);
+    register!(GETTER_VAR = "value"; Binary; error = "set GETTER_VAR to the service URL");
+    register!(SCOPED_VAR = "value"; commands = ["server", "migrate"]);
+    register!(
+        ASSERTED_WORKERS = "4";
+        assert = |v| v.parse::<u32>().map(|n| n > 0).unwrap_or(false), "workers must be positive"
+    );
+    register!(TYPED_HOST = "localhost", TYPED_PORT = "80", TYPED_TAGS = "");
+    register!(FEATURE_DEFAULT = "base");
+    register!(DATABASE_URL = "postgres://localhost/default");
+    register!(TYPED_TIMEOUT_MS: i64 = "250");
+    register!(TYPED_RETRIES: u8 = "3"; Binary);
+    register!(TYPED_LISTEN_PORT: u16 = "8080");
+    register!(TYPED_OFFSET: i8 = "0");
+    register!(API_KEY = "none"; secret);
+    register!(ALLOWED_MODE = "fast" in ["fast", "slow", "balanced"]);
+    register!(ALLOWED_REGION = "eu" in ["eu", "us"]; Binary);
+    register!(ABSOLUTE_DATA_DIR = "/var/lib/app"; path; absolute);
+    register_prefixed!("PREFIXED", DATABASE_URL = "postgres://localhost/app", REDIS_URL = "redis://localhost");
+    register!(PREFIXEDNESS = "unrelated");
+    register!(NON_EMPTY_TOKEN = "dev-token"; non_empty; secret);
+    register!(INTERNAL_HOST = "billing.internal"; internal);
+    register!(OPTIONAL_PROXY_URL?);
+    register!(OPTIONAL_SIGNING_KEY?; secret);
+    register!(CHAIN_ROOT = "root", CHAIN_MIDDLE = "middle", CHAIN_LEAF = "leaf");
+    // Registered in reverse order of their references
+    register!(DEFAULT_CHAIN_BIN = "${DEFAULT_CHAIN_PREFIX}/bin");
+    register!(DEFAULT_CHAIN_PREFIX = "${DEFAULT_CHAIN_ROOT}/usr");
+    register!(DEFAULT_CHAIN_ROOT = "/opt");
+    // The feature is the calling crate's, here this crate's `json`
+    register!(FEATURE_DEFAULT = "with-json"; feature = "json"; Binary);
+    register_derived!(DERIVED_URL, from = [GETTER_VAR, TYPED_HOST, TYPED_PORT], compute = join_url);
+
+    fn join_url(parts: &[&str]) -> String {
+        format!("{}://{}:{}", parts[0], parts[1], parts[2])
+    }
+    register_conditional!(
+        COND_LOG_LEVEL,
+        based_on = "COND_ENVIRONMENT",
+        cases = { "production" => "warn", "staging" => "info" },
+        default = "debug"
+    );
+
+    /// Settings satisfying every variable this module registers without a
+    /// default, so loader tests only fail for the reason they exercise.
+    const REQUIRED_SETTINGS: &str = "MISSING_VAR = \"missing_value\"\nPRESENT_VAR = \"present_value\"\n";
+
+    #[test]
+    fn test_load_single_toml() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+        env::remove_var("TEST_ENV_VAR");
+
+        fs::write(&file_path, format!("[env]\nTEST_ENV_VAR = \"test_value\"\n{}", REQUIRED_SETTINGS)).unwrap();
+
+        load_and_validate_env_vars(&[file_path], "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "test_value");
+    }
+
+    #[test]
+    fn test_load_from_reader() {
+        let _guard = env_lock();
+        env::remove_var("TEST_ENV_VAR");
+        let config = format!("[env]\nTEST_ENV_VAR = \"from_reader\"\n{}", REQUIRED_SETTINGS);
+        load_from_reader(Cursor::new(config.as_bytes()), "env", ConfigFormat::Toml).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "from_reader");
+
+        // Without a file name, the default format is TOML
+        env::remove_var("TEST_ENV_VAR");
+        load_from_reader(Cursor::new(config.as_bytes()), "env", ConfigFormat::ByExtension).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "from_reader");
+
+        env::remove_var("TEST_ENV_VAR");
+        let config = "TEST_ENV_VAR=from_dotenv\nMISSING_VAR=missing_value\nPRESENT_VAR=present_value\n";
+        load_from_reader(Cursor::new(config), "", ConfigFormat::Dotenv).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "from_dotenv");
+
+        match load_from_reader(Cursor::new("[env"), "env", ConfigFormat::Toml) {
+            Err(EnvInventoryError::ParseFileError(source)) => assert_eq!(source, "<reader>"),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(matches!(
+            load_from_reader(Cursor::new(&b"\xff"[..]), "env", ConfigFormat::Toml),
+            Err(EnvInventoryError::ReadFileError(_))
+        ));
+        env::remove_var("TEST_ENV_VAR");
+    }
+
+    #[test]
+    fn test_load_sections() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.toml");
+        env::remove_var("TEST_ENV_VAR");
+        env::remove_var("DATABASE_URL");
+        fs::write(
+            &file_path,
+            format!(
+                "[env]\nTEST_ENV_VAR = \"shared\"\nDATABASE_URL = \"postgres://localhost/app\"\n{}\n\
+                 [env.production]\nTEST_ENV_VAR = \"production\"\n",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
+
+        load_and_validate_env_vars(&[&file_path], &["env", "env.staging", "env.production"]).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "production");
+        assert_eq!(env::var("DATABASE_URL").unwrap(), "postgres://localhost/app");
+
+        // Later sections win
+        env::remove_var("TEST_ENV_VAR");
+        load_and_validate_env_vars(&[&file_path], &["env.production", "env"]).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "shared");
+
+        // A section named with dots wins over nested tables
+        fs::write(
+            &file_path,
+            format!(
+                "[env]\nTEST_ENV_VAR = \"shared\"\n{}\n[env.production]\nTEST_ENV_VAR = \"nested\"\n\
+                 [\"env.production\"]\nTEST_ENV_VAR = \"quoted\"\n",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
+        env::remove_var("TEST_ENV_VAR");
+        let section: &str = "env.production";
+        load_and_validate_env_vars(&[&file_path], &section).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "quoted");
+
+        env::remove_var("TEST_ENV_VAR");
+        env::remove_var("DATABASE_URL");
+    }
+
+    #[test]
+    fn test_load_with_profile() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.toml");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(
+            &file_path,
+            format!(
+                "[env]\nTEST_ENV_VAR = \"base\"\n{}\n[env.staging]\nTEST_ENV_VAR = \"staging\"\n",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
+
+        env::set_var("PROFILE_APP_ENV", "staging");
+        load_with_profile(&[&file_path], "env", "PROFILE_APP_ENV").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "staging");
+
+        // Unknown and unset profiles fall back to the base section
+        for profile in [Some("qa"), Some(""), None] {
+            env::remove_var("TEST_ENV_VAR");
+            match profile {
+                Some(profile) => env::set_var("PROFILE_APP_ENV", profile),
+                None => env::remove_var("PROFILE_APP_ENV"),
+            }
+            load_with_profile(&[&file_path], "env", "PROFILE_APP_ENV").unwrap();
+            assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "base");
+        }
+
+        // A profile that isn't a table
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(
+            &file_path,
+            format!("[env]\nTEST_ENV_VAR = \"base\"\nprod = \"oops\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+        env::set_var("PROFILE_APP_ENV", "prod");
+        assert!(matches!(
+            load_with_profile(&[&file_path], "env", "PROFILE_APP_ENV"),
+            Err(EnvInventoryError::ParseFileError(_))
+        ));
+        // Only profiles are checked
+        load_and_validate_env_vars(&[&file_path], &["env", "env.prod"]).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "base");
+
+        env::remove_var("PROFILE_APP_ENV");
+        env::remove_var("TEST_ENV_VAR");
+    }
+
+    #[test]
+    fn test_declared_types() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.toml");
+        env::remove_var("TEST_ENV_VAR");
+        let write = |port: &str| {
+            let config = format!(
+                "[env]\nTEST_ENV_VAR = \"{}\"\n{}\n[env.types]\nTEST_ENV_VAR = \"uint\"\nUNSET_VAR = \"bool\"\n",
+                port, REQUIRED_SETTINGS
+            );
+            fs::write(&file_path, config).unwrap();
+        };
+
+        write("8080");
+        load_and_validate_env_vars(&[&file_path], "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "8080");
+        env::remove_var("TEST_ENV_VAR");
+
+        write("eighty");
+        match load_and_validate_env_vars(&[&file_path], "env") {
+            Err(EnvInventoryError::InvalidType { name, value, expected }) => {
+                assert_eq!(name, "TEST_ENV_VAR");
+                assert_eq!(value, "eighty");
+                assert_eq!(expected, ExpectedType::UInt);
+            }
+            other => panic!("expected an invalid type, got {:?}", other),
+        }
+        assert!(env::var("TEST_ENV_VAR").is_err());
+
+        fs::write(&file_path, "[env]\nTEST_ENV_VAR = \"x\"\n[env.types]\nTEST_ENV_VAR = \"port\"\n").unwrap();
+        assert!(matches!(
+            load_and_validate_env_vars(&[&file_path], "env"),
+            Err(EnvInventoryError::ParseFileError(_))
+        ));
+
+        // A `types` string is an ordinary setting
+        fs::write(&file_path, "[env]\nTEST_ENV_VAR = \"x\"\ntypes = \"plain\"\n").unwrap();
+        let settings = load_toml_settings(&file_path, "env").unwrap();
+        assert_eq!(settings["types"], "plain");
+        assert_eq!(settings["TEST_ENV_VAR"], "x");
+    }
+
+    #[test]
+    fn test_concurrent_loads() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(&file_path, format!("[env]\nTEST_ENV_VAR = \"shared\"\n{}", REQUIRED_SETTINGS)).unwrap();
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let file_path = file_path.clone();
+                std::thread::spawn(move || {
+                    (0..10)
+                        .map(|_| load_and_validate_env_vars(&[&file_path], "env"))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap().unwrap();
+        }
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "shared");
+    }
+
+    #[test]
+    fn test_merge_priority() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path1 = dir.path().join("settings1.conf");
+        let file_path2 = dir.path().join("settings2.conf");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(&file_path1, format!("[env]\nTEST_ENV_VAR = \"value1\"\n{}", REQUIRED_SETTINGS)).unwrap();
+        fs::write(&file_path2, "[env]\nTEST_ENV_VAR = \"value2\"").unwrap();
+
+        load_and_validate_env_vars(&[file_path1, file_path2], "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "value2");
+    }
+
+    #[test]
+    fn test_expanded_map_order_and_cycles() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let chain = ["CHAIN_ROOT", "CHAIN_MIDDLE", "CHAIN_LEAF"];
+
+        // Each value references the next, whatever the registration order
+        env::set_var("CHAIN_ROOT", "${CHAIN_MIDDLE}/root");
+        env::set_var("CHAIN_MIDDLE", "${CHAIN_LEAF}/middle");
+        env::set_var("CHAIN_LEAF", "/leaf");
+        let expanded = expanded_map().unwrap();
+        assert_eq!(expanded["CHAIN_ROOT"], "/leaf/middle/root");
+        assert_eq!(env::var("CHAIN_ROOT").unwrap(), "/leaf/middle/root");
+
+        env::set_var("CHAIN_ROOT", "${CHAIN_LEAF}");
+        env::set_var("CHAIN_LEAF", "${CHAIN_ROOT}");
+        match expanded_map() {
+            Err(EnvInventoryError::CyclicReference(cycle)) => {
+                assert_eq!(cycle, vec!["CHAIN_LEAF", "CHAIN_ROOT", "CHAIN_LEAF"]);
+            }
+            other => panic!("expected a cycle, got {:?}", other),
+        }
+        // Nothing is expanded when there is a cycle
+        assert_eq!(env::var("CHAIN_ROOT").unwrap(), "${CHAIN_LEAF}");
+
+        env::set_var("CHAIN_LEAF", "leaf");
+        env::set_var("CHAIN_MIDDLE", "$CHAIN_MIDDLE/more");
+        let error = expanded_map().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Cyclic reference between variables: CHAIN_MIDDLE -> CHAIN_MIDDLE"
+        );
+
+        for name in chain {
+            env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_expanded_map_chained_defaults() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let chain = ["DEFAULT_CHAIN_BIN", "DEFAULT_CHAIN_PREFIX", "DEFAULT_CHAIN_ROOT"];
+        for name in chain {
+            env::remove_var(name);
+        }
+
+        let expanded = expanded_map().unwrap();
+        assert_eq!(expanded["DEFAULT_CHAIN_BIN"], "/opt/usr/bin");
+        assert_eq!(expanded["DEFAULT_CHAIN_PREFIX"], "/opt/usr");
+        // Expanding again gives the same result
+        assert_eq!(expanded_map().unwrap(), expanded);
+
+        for name in chain {
+            env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_resolve_with_reducer() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let base = dir.path().join("base.toml");
+        let local = dir.path().join("local.toml");
+        fs::write(&base, "[env]\nREDUCED_PLUGINS = \"auth,metrics\"\nREDUCED_NAME = \"base\"\n").unwrap();
+        fs::write(&local, "[env]\nREDUCED_PLUGINS = \"tracing\"\nREDUCED_ONLY_LOCAL = \"local\"\n").unwrap();
+
+        fn append(key: &str, existing: &str, incoming: &str) -> String {
+            assert_eq!(key, "REDUCED_PLUGINS");
+            format!("{},{}", existing, incoming)
+        }
+        let merged = resolve_with_reducer(&[&base, &local], "env", append).unwrap();
+        assert_eq!(merged["REDUCED_PLUGINS"], "auth,metrics,tracing");
+        assert_eq!(merged["REDUCED_NAME"], "base");
+        assert_eq!(merged["REDUCED_ONLY_LOCAL"], "local");
+
+        let merged = resolve_with_reducer(&[&base, &local], "env", incoming_wins).unwrap();
+        assert_eq!(merged["REDUCED_PLUGINS"], "tracing");
+        assert!(env::var("REDUCED_PLUGINS").is_err());
+
+        // Registered variables set in the environment are the most significant
+        env::set_var("DATABASE_URL", "postgres://env/app");
+        fs::write(&local, "[env]\nDATABASE_URL = \"postgres://local/app\"\n").unwrap();
+        fn pick_both(_key: &str, existing: &str, incoming: &str) -> String {
+            format!("{} then {}", existing, incoming)
+        }
+        let merged = resolve_with_reducer(&[&base, &local], "env", pick_both).unwrap();
+        assert_eq!(merged["DATABASE_URL"], "postgres://local/app then postgres://env/app");
+
+        // A value loaded from a file isn't taken from the environment again
+        satisfy_required_vars();
+        env::remove_var("DATABASE_URL");
+        load_and_validate_env_vars(&[&local], "env").unwrap();
+        assert_eq!(env::var("DATABASE_URL").unwrap(), "postgres://local/app");
+        let merged = resolve_with_reducer(&[&base, &local], "env", pick_both).unwrap();
+        assert_eq!(merged["DATABASE_URL"], "postgres://local/app");
+        env::remove_var("DATABASE_URL");
+    }
+
+    #[test]
+    fn test_missing_mandatory_config() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path1 = dir.path().join("does_not_exist.conf");
+        let file_path2 = dir.path().join("settings.conf");
+        fs::write(&file_path2, "[env]\nTEST_ENV_VAR = \"test_value\"").unwrap();
+
+        assert!(load_and_validate_env_vars(&[file_path1, file_path2], "env").is_err());
+    }
+
+    #[test]
+    fn test_missing_env_vars() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+
+        // Write a file without MISSING_VAR
+        fs::write(&file_path, "[env]\nSOME_OTHER_VAR = \"some_value\"").unwrap();
+
+        // Ensure the environment variable isn't set before the test
+        env::remove_var("MISSING_VAR");
+
+        // Register MISSING_VAR as a required environment variable
+        register!(MISSING_VAR);
+
+        // Since MISSING_VAR isn't in the environment and also isn't in the TOML files,
+        // the function should return an error.
+        assert!(load_and_validate_env_vars(&[file_path], "env").is_err());
+    }
+
+    #[test]
+    fn test_present_env_vars() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+
+        // Write a file with PRESENT_VAR
+        fs::write(
+            &file_path,
+            r#"
+        [env]
+        PRESENT_VAR = "present_value"
+        MISSING_VAR = "missing_value"
+        TEST_ENV_VAR = "test_value"
+        "#,
+        )
+        .unwrap();
+
+        // Ensure the environment variable isn't set before the test
+        env::remove_var("PRESENT_VAR");
+
+        // Register PRESENT_VAR as a required environment variable
+        register!(PRESENT_VAR);
+
+        // Since PRESENT_VAR is in the TOML file, the function should run without errors
+        load_and_validate_env_vars(&[file_path], "env").unwrap();
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct TypedConfig {
+        typed_host: String,
+        typed_port: u16,
+        typed_tags: Vec<String>,
+        typed_absent: Option<String>,
+    }
+
+    #[test]
+    fn test_init_typed() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+        for name in ["TYPED_HOST", "TYPED_PORT", "TYPED_TAGS"] {
+            env::remove_var(name);
+        }
+        fs::write(
+            &file_path,
+            format!(
+                "[env]\nTEST_ENV_VAR = \"test_value\"\nTYPED_HOST = \"example.com\"\nTYPED_PORT = \"8080\"\nTYPED_TAGS = \"a, b\"\n{}",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
+
+        let config: TypedConfig = init_typed(&[file_path], "env").unwrap();
+        assert_eq!(config.typed_host, "example.com");
+        assert_eq!(config.typed_port, 8080);
+        assert_eq!(config.typed_tags, vec!["a", "b"]);
+        assert_eq!(config.typed_absent, None);
+    }
+
+    #[test]
+    fn test_init_typed_invalid_value() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+        env::remove_var("TYPED_PORT");
+        fs::write(
+            &file_path,
+            format!(
+                "[env]\nTEST_ENV_VAR = \"test_value\"\nTYPED_PORT = \"not-a-port\"\n{}",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
+
+        let result = init_typed::<TypedConfig, _, _>(&[file_path], "env");
+        assert!(matches!(result, Err(EnvInventoryError::DeserializeError(_))));
+        env::remove_var("TYPED_PORT");
+    }
+
+    fn registered(name: &str) -> &'static RequiredVar {
+        inventory::iter::<RequiredVar>()
+            .find(|var| var.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_conditional_default() {
+        let _guard = env_lock();
+        let var = registered("COND_LOG_LEVEL");
+        env::remove_var("COND_LOG_LEVEL");
+
+        env::set_var("COND_ENVIRONMENT", "production");
+        assert_eq!(var.get().as_deref(), Some("warn"));
+        env::set_var("COND_ENVIRONMENT", "staging");
+        assert_eq!(var.get().as_deref(), Some("info"));
+        env::set_var("COND_ENVIRONMENT", "development");
+        assert_eq!(var.get().as_deref(), Some("debug"));
+        env::remove_var("COND_ENVIRONMENT");
+        assert_eq!(var.get().as_deref(), Some("debug"));
+
+        // An explicit value always wins over the condition
+        env::set_var("COND_ENVIRONMENT", "production");
+        env::set_var("COND_LOG_LEVEL", "trace");
+        assert_eq!(var.get().as_deref(), Some("trace"));
+        env::remove_var("COND_ENVIRONMENT");
+        env::remove_var("COND_LOG_LEVEL");
+    }
+
+    #[test]
+    fn test_conditional_default_without_fallback() {
+        let var = RequiredVar {
+            condition: Some(Condition {
+                based_on: "MODE",
+                cases: &[("fast", "1")],
+            }),
+            ..RequiredVar::new("THREADS")
+        };
+        let lookup = |mode: &'static str| move |_: &str| Some(mode.to_string());
+
+        assert_eq!(var.default_with(lookup("fast")), Some("1"));
+        assert_eq!(var.default_with(lookup("slow")), None);
+        assert_eq!(var.default_with(|_| None), None);
+    }
+
+    #[test]
+    fn test_conditional_default_from_config() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+        env::remove_var("COND_LOG_LEVEL");
+        env::remove_var("COND_ENVIRONMENT");
+        fs::write(
+            &file_path,
+            format!(
+                "[env]\nTEST_ENV_VAR = \"test_value\"\nCOND_ENVIRONMENT = \"production\"\n{}",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
+
+        load_and_validate_env_vars(&[file_path], "env").unwrap();
+        assert_eq!(env::var("COND_LOG_LEVEL").unwrap(), "warn");
+        env::remove_var("COND_LOG_LEVEL");
+    }
+
+    #[test]
+    fn test_getters() {
+        let var = registered("GETTER_VAR");
+        assert_eq!(var.name(), "GETTER_VAR");
+        assert_eq!(var.default(), Some("value"));
+        assert_eq!(var.error(), Some("set GETTER_VAR to the service URL"));
+        assert_eq!(var.source(), file!());
+        assert_eq!(var.priority(), Priority::Binary);
+
+        let var = registered("TEST_ENV_VAR");
+        assert_eq!(var.default(), None);
+        assert_eq!(var.error(), None);
+        assert_eq!(var.priority(), Priority::Library);
+    }
+
+    #[test]
+    fn test_missing_vars_format() {
+        let vars = [
+            RequiredVar::new("FORMAT_PLAIN_VAR"),
+            RequiredVar::new("FORMAT_ERROR_VAR").with_error("see the deployment guide"),
+            RequiredVar::new("FORMAT_DEFAULT_VAR").with_default("set"),
+        ];
+
+        let missing = missing_vars(&vars);
+        assert_eq!(
+            missing::rendered(&missing),
+            vec![
+                "FORMAT_ERROR_VAR=see the deployment guide",
+                "FORMAT_PLAIN_VAR=(missing)",
+            ]
+        );
+        assert_eq!(missing[0].name, "FORMAT_ERROR_VAR");
+        assert_eq!(missing[0].custom_error.as_deref(), Some("see the deployment guide"));
+        assert_eq!(missing[1].custom_error, None);
+
+        let error = EnvInventoryError::MissingEnvVars(missing);
+        assert_eq!(
+            error.to_string(),
+            "Missing required environment variables: \
+             [\"FORMAT_ERROR_VAR=see the deployment guide\", \"FORMAT_PLAIN_VAR=(missing)\"]"
+        );
+    }
+
+    #[test]
+    fn test_source_prefix() {
+        // Variables registered without a default still record their source
+        assert_eq!(registered("TEST_ENV_VAR").source(), file!());
+        assert!(validate_from_source_prefix("crates/does-not-exist/").is_ok());
+
+        let vars = [
+            RequiredVar::new("BILLING_KEY").with_source("crates/billing/src/lib.rs"),
+            RequiredVar::new("SEARCH_KEY").with_source("crates/search/src/lib.rs"),
+        ];
+        let validate = |prefix: &str| {
+            validate_vars(vars.iter().filter(|var| var.source().starts_with(prefix)))
+        };
+
+        match validate("crates/billing/") {
+            Err(EnvInventoryError::MissingEnvVars(missing)) => {
+                assert_eq!(missing::rendered(&missing), vec!["BILLING_KEY=(missing)"]);
+                assert_eq!(missing[0].source, "crates/billing/src/lib.rs");
+            }
+            other => panic!("expected missing vars, got {:?}", other),
+        }
+        assert!(validate("crates/other/").is_ok());
+    }
+
+    #[test]
+    fn test_base64_validation() {
+        let vars = [RequiredVar::new("CHECKED_B64_KEY")
+            .with_default("!!not base64!!")
+            .with_base64()];
+        assert!(matches!(
+            validate_vars(&vars),
+            Err(EnvInventoryError::ParseValueError {
+                type_name: "base64",
+                ..
+            })
+        ));
+
+        // The value of a secret isn't shown
+        let vars = [RequiredVar::new("CHECKED_B64_KEY")
+            .with_default("!!not base64!!")
+            .with_base64()
+            .with_secret()];
+        match validate_vars(&vars) {
+            Err(EnvInventoryError::ParseValueError { value, .. }) => assert_eq!(value, MASK),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        let vars = [RequiredVar::new("CHECKED_B64_KEY").with_default("aGk=").with_base64()];
+        assert!(validate_vars(&vars).is_ok());
+    }
+
+    #[test]
+    fn test_derived() {
+        let _guard = env_lock();
+        for name in ["DERIVED_URL", "GETTER_VAR", "TYPED_HOST", "TYPED_PORT"] {
+            env::remove_var(name);
+        }
+
+        assert_eq!(
+            registered("DERIVED_URL").derived_from(),
+            Some(&["GETTER_VAR", "TYPED_HOST", "TYPED_PORT"][..])
+        );
+        assert_eq!(
+            resolve_one("DERIVED_URL"),
+            Some(("value://localhost:80".to_string(), VarSource::Derived))
+        );
+
+        env::set_var("TYPED_PORT", "8080");
+        assert_eq!(
+            registered("DERIVED_URL").get().as_deref(),
+            Some("value://localhost:8080")
+        );
+        env::remove_var("TYPED_PORT");
+
+        // A derived variable is only missing when one of its inputs is
+        let vars = [RequiredVar::new("DERIVED_MISSING").with_derivation(Derivation {
+            from: &["DERIVED_UNSET_INPUT"],
+            compute: join_url,
+        })];
+        match validate_vars(&vars) {
+            Err(EnvInventoryError::MissingEnvVars(missing)) => {
+                assert_eq!(missing::rendered(&missing), vec!["DERIVED_MISSING=(missing)"]);
+            }
+            other => panic!("expected missing vars, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_get_raw_and_expanded() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let file_path = dir.path().join("settings.conf");
+        fs::write(
+            &file_path,
+            format!(
+                "[env]\nTEST_ENV_VAR = \"test_value\"\nTYPED_TAGS = \"${{TYPED_HOST}}:80\"\n{}",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
+
+        // Before loading, the template is still in place. Expansion only
+        // sees the environment.
+        env::set_var("TYPED_HOST", "localhost");
+        env::set_var("TYPED_TAGS", "${TYPED_HOST}/tags");
+        assert_eq!(
+            get_raw_and_expanded("TYPED_TAGS"),
+            Some(("${TYPED_HOST}/tags".to_string(), "localhost/tags".to_string()))
+        );
+        env::remove_var("TYPED_TAGS");
+
+        // After loading, the template is remembered
+        load_and_validate_env_vars(&[&file_path], "env").unwrap();
+        assert_eq!(env::var("TYPED_TAGS").unwrap(), "localhost:80");
+        assert_eq!(
+            get_raw_and_expanded("TYPED_TAGS"),
+            Some(("${TYPED_HOST}:80".to_string(), "localhost:80".to_string()))
+        );
+
+        // Literals are unchanged by expansion
+        assert_eq!(
+            get_raw_and_expanded("TYPED_HOST"),
+            Some(("localhost".to_string(), "localhost".to_string()))
+        );
+        env::remove_var("TYPED_HOST");
+        env::remove_var("TYPED_TAGS");
+    }
+
+    #[test]
+    fn test_feature_default() {
+        let _guard = env_lock();
+        env::remove_var("FEATURE_DEFAULT");
+        let expected = if cfg!(feature = "json") { "with-json" } else { "base" };
+        assert_eq!(
+            resolve_one("FEATURE_DEFAULT").map(|(value, _)| value).as_deref(),
+            Some(expected)
+        );
+    }
+
+    #[test]
+    fn test_assertion() {
+        fn unprivileged_port(value: &str) -> bool {
+            value.parse::<u16>().map(|port| port > 1024).unwrap_or(false)
+        }
+        let port = |default| {
+            [RequiredVar::new("ASSERTED_PORT")
+                .with_default(default)
+                .with_assertion(Assertion {
+                    check: unprivileged_port,
+                    message: "port must be > 1024",
+                })]
+        };
+
+        assert!(validate_vars(&port("8080")).is_ok());
+        match validate_vars(&port("80")) {
+            Err(EnvInventoryError::AssertionFailed { name, message }) => {
+                assert_eq!(name, "ASSERTED_PORT");
+                assert_eq!(message, "port must be > 1024");
+            }
+            other => panic!("expected a failed assertion, got {:?}", other),
+        }
+        assert!(validate_vars(&port("http")).is_err());
+
+        // The macro form registers the same kind of check
+        let workers = registered("ASSERTED_WORKERS");
+        assert_eq!(workers.assertion.map(|a| a.message), Some("workers must be positive"));
+        assert!(check_value(workers).is_ok());
+    }
+
+    #[test]
+    fn test_command_scope() {
+        assert_eq!(registered("SCOPED_VAR").commands(), &["server", "migrate"]);
+        assert_eq!(registered("TEST_ENV_VAR").commands(), &[] as &[&str]);
+
+        let vars = [
+            RequiredVar::new("SCOPED_SERVER_VAR").with_commands(&["server"]),
+            RequiredVar::new("SCOPED_ANY_VAR").with_default("set"),
+        ];
+        let validate = |command| validate_vars(vars.iter().filter(|var| var.applies_to(command)));
+
+        assert!(validate("migrate").is_ok());
+        match validate("server") {
+            Err(EnvInventoryError::MissingEnvVars(missing)) => {
+                assert_eq!(missing::rendered(&missing), vec!["SCOPED_SERVER_VAR=(missing)"]);
+            }
+            other => panic!("expected a missing variable, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_sorted_values() {
+        let _guard = env_lock();
+        let values = sorted_values();
+        let names: Vec<&str> = values.iter().map(|(name, _)| name.as_str()).collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+
+        assert_eq!(names, sorted);
+        assert!(values.contains(&("GETTER_VAR".to_string(), "value".to_string())));
+        assert!(values.contains(&("API_KEY".to_string(), "****".to_string())));
+        assert_eq!(values, sorted_values());
+    }
+
+    #[test]
+    fn test_effective_map() {
+        let _guard = env_lock();
+        for name in ["GETTER_VAR", "COND_LOG_LEVEL", "TYPED_PORT", "DERIVED_URL"] {
+            env::remove_var(name);
+        }
+        env::set_var("COND_ENVIRONMENT", "staging");
+        env::set_var("TYPED_HOST", "example.com");
+
+        let values = effective_map();
+        assert_eq!(values["GETTER_VAR"], "value");
+        assert_eq!(values["COND_LOG_LEVEL"], "info");
+        assert_eq!(values["TYPED_HOST"], "example.com");
+        assert_eq!(values["DERIVED_URL"], "value://example.com:80");
+        assert_eq!(env::var("COND_LOG_LEVEL").ok(), None);
+        env::remove_var("COND_ENVIRONMENT");
+        env::remove_var("TYPED_HOST");
+    }
+
+    #[test]
+    fn test_expected_type() {
+        let _guard = env_lock();
+        let timeout = registered("TYPED_TIMEOUT_MS");
+        assert_eq!(timeout.expected_type(), Some(ExpectedType::Int));
+        assert_eq!(timeout.default(), Some("250"));
+        assert_eq!(registered("TYPED_RETRIES").expected_type(), Some(ExpectedType::UInt));
+        assert_eq!(registered("TYPED_RETRIES").priority, Priority::Binary);
+        assert_eq!(registered("TYPED_HOST").expected_type(), None);
+
+        env::remove_var("TYPED_TIMEOUT_MS");
+        assert!(check_value(timeout).is_ok());
+
+        env::set_var("TYPED_TIMEOUT_MS", "abc");
+        match check_value(timeout) {
+            Err(EnvInventoryError::InvalidType { name, value, expected }) => {
+                assert_eq!(name, "TYPED_TIMEOUT_MS");
+                assert_eq!(value, "abc");
+                assert_eq!(expected, ExpectedType::Int);
+            }
+            other => panic!("expected an invalid type, got {:?}", other),
+        }
+
+        // The value of a secret isn't shown
+        match check_value(&timeout.clone().with_secret()) {
+            Err(EnvInventoryError::InvalidType { value, .. }) => assert_eq!(value, MASK),
+            other => panic!("expected an invalid type, got {:?}", other),
+        }
+        env::remove_var("TYPED_TIMEOUT_MS");
 
-pub fn load_and_validate_env_vars<P: AsRef<Path>>(
-    config_paths: &[P],
-    section: &str,
-) -> Result<(), EnvInventoryError> {
-    let mut merged_settings = HashMap::new();
+        assert!(ExpectedType::UInt.accepts("8080"));
+        assert!(!ExpectedType::UInt.accepts("-1"));
+        assert!(ExpectedType::Float.accepts("0.5"));
+        assert!(ExpectedType::Bool.accepts("false"));
+        assert!(!ExpectedType::Bool.accepts("yes"));
+        assert!(ExpectedType::String.accepts("anything"));
+    }
 
-    for (index, path) in config_paths.iter().enumerate() {
-        let settings = load_toml_settings(path.as_ref(), section);
+    #[test]
+    fn test_allowed_values() {
+        let _guard = env_lock();
+        let mode = registered("ALLOWED_MODE");
+        assert_eq!(mode.allowed_values(), &["fast", "slow", "balanced"]);
+        assert_eq!(mode.default(), Some("fast"));
+        assert_eq!(registered("ALLOWED_REGION").allowed_values(), &["eu", "us"]);
+        assert_eq!(registered("ALLOWED_REGION").priority(), Priority::Binary);
 
-        match settings {
-            Ok(current_settings) => {
-                // Merge settings with nth file being most significant
-                for (key, value) in current_settings.iter() {
-                    merged_settings.insert(key.clone(), value.clone());
-                }
+        env::remove_var("ALLOWED_MODE");
+        assert!(check_value(mode).is_ok());
+        env::set_var("ALLOWED_MODE", "slow");
+        assert!(check_value(mode).is_ok());
+
+        env::set_var("ALLOWED_MODE", "turbo");
+        match check_value(mode) {
+            Err(EnvInventoryError::InvalidValue { name, value, reason }) => {
+                assert_eq!(name, "ALLOWED_MODE");
+                assert_eq!(value, "turbo");
+                assert_eq!(reason, "expected one of [\"fast\", \"slow\", \"balanced\"]");
             }
-            Err(e) => {
-                if index == 0 {
-                    // The first file is mandatory
-                    return Err(e);
-                } else {
-                    // Subsequent files are optional, but let's warn for transparency
-                    eprintln!(
-                        "Warning: Could not load settings from {:?}. Reason: {}",
-                        path.as_ref(),
-                        e
-                    );
-                }
+            other => panic!("expected a value not allowed, got {:?}", other),
+        }
+
+        // The value of a secret isn't shown
+        match check_value(&mode.clone().with_secret()) {
+            Err(EnvInventoryError::InvalidValue { value, .. }) => assert_eq!(value, MASK),
+            other => panic!("expected a value not allowed, got {:?}", other),
+        }
+        env::remove_var("ALLOWED_MODE");
+
+        assert!(__contains_str(&["a", "bc"], "bc"));
+        assert!(!__contains_str(&["a", "bc"], "b"));
+        assert!(!__contains_str(&[], ""));
+    }
+
+    #[test]
+    fn test_absolute_path() {
+        let _guard = env_lock();
+        let data_dir = registered("ABSOLUTE_DATA_DIR");
+        assert!(data_dir.is_path());
+        assert!(data_dir.requires_absolute());
+        assert!(!registered("DATABASE_URL").is_path());
+
+        env::remove_var("ABSOLUTE_DATA_DIR");
+        assert!(check_value(data_dir).is_ok());
+        env::set_var("ABSOLUTE_DATA_DIR", "~/data");
+        assert!(check_value(data_dir).is_ok());
+
+        env::set_var("ABSOLUTE_DATA_DIR", "data/app");
+        match check_value(data_dir) {
+            Err(EnvInventoryError::PathNotAbsolute { name, path }) => {
+                assert_eq!(name, "ABSOLUTE_DATA_DIR");
+                assert_eq!(path, "data/app");
             }
+            other => panic!("expected a relative path, got {:?}", other),
         }
+        env::remove_var("ABSOLUTE_DATA_DIR");
     }
 
-    // let mut missing_vars = Vec::new();
+    #[test]
+    fn test_non_empty() {
+        let _guard = env_lock();
+        let token = registered("NON_EMPTY_TOKEN");
+        assert!(token.requires_non_empty());
+        assert!(!registered("DATABASE_URL").requires_non_empty());
 
-    for var in inventory::iter::<RequiredVar>() {
-        // 1) Check if set in env
-        if env::var(var.name).is_ok() {
-            continue;
+        for value in ["", "  \t"] {
+            env::set_var("NON_EMPTY_TOKEN", value);
+            match check_value(token) {
+                Err(EnvInventoryError::EmptyValue(name)) => assert_eq!(name, "NON_EMPTY_TOKEN"),
+                other => panic!("expected an empty value, got {:?}", other),
+            }
         }
+        env::set_var("NON_EMPTY_TOKEN", "t0k3n");
+        assert!(check_value(token).is_ok());
+        env::remove_var("NON_EMPTY_TOKEN");
 
-        // 2) Check if set in config files
-        if let Some(value) = merged_settings.get(var.name) {
-            env::set_var(var.name, value);
-            continue;
+        // Without the flag, an empty value is a value
+        env::set_var("DATABASE_URL", "");
+        assert!(check_value(registered("DATABASE_URL")).is_ok());
+        env::remove_var("DATABASE_URL");
+    }
+
+    #[test]
+    fn test_type_range() {
+        let _guard = env_lock();
+        let port = registered("TYPED_LISTEN_PORT");
+        let offset = registered("TYPED_OFFSET");
+
+        env::set_var("TYPED_LISTEN_PORT", "65535");
+        assert!(check_value(port).is_ok());
+
+        env::set_var("TYPED_LISTEN_PORT", "70000");
+        match check_value(port) {
+            Err(EnvInventoryError::InvalidValue { name, value, reason }) => {
+                assert_eq!(name, "TYPED_LISTEN_PORT");
+                assert_eq!(value, "70000");
+                assert_eq!(reason, "out of range for u16 (0..=65535)");
+            }
+            other => panic!("expected a value out of range, got {:?}", other),
         }
 
-        // 3) Check if set by binary
-        let binary_default = inventory::iter::<RequiredVar>()
-            .filter(|v| v.name == var.name && v.priority == Priority::Binary)
-            .last() // Get the most significant binary default
-            .and_then(|v| v.default);
+        // Unsigned types reject negative values as the wrong type
+        env::set_var("TYPED_LISTEN_PORT", "-1");
+        assert!(matches!(
+            check_value(port),
+            Err(EnvInventoryError::InvalidType { .. })
+        ));
+        env::remove_var("TYPED_LISTEN_PORT");
 
-        if let Some(default_value) = binary_default {
-            env::set_var(var.name, default_value);
-            continue;
+        env::set_var("TYPED_OFFSET", "-128");
+        assert!(check_value(offset).is_ok());
+        env::set_var("TYPED_OFFSET", "-129");
+        assert!(matches!(
+            check_value(offset),
+            Err(EnvInventoryError::InvalidValue { .. })
+        ));
+        env::remove_var("TYPED_OFFSET");
+
+        let range = IntRange { min: 0, max: u128::MAX, type_name: "u128" };
+        assert!(range.check("340282366920938463463374607431768211455").is_ok());
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum TestLogLevel {
+        Debug,
+        Info,
+    }
+
+    impl std::str::FromStr for TestLogLevel {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            match s.to_ascii_lowercase().as_str() {
+                "debug" => Ok(TestLogLevel::Debug),
+                "info" => Ok(TestLogLevel::Info),
+                _ => Err(format!("unknown log level {}", s)),
+            }
         }
+    }
 
-        // 4) Check if set by library (with nth library being the most significant)
-        let library_default = inventory::iter::<RequiredVar>()
-            .filter(|v| v.name == var.name && v.priority == Priority::Library)
-            .last() // Get the most significant library default
-            .and_then(|v| v.default);
+    register_enum!(TestLogLevel, "ENUM_LOG_LEVEL", default = Info);
+    register!(ENUM_SECRET_LEVEL = "info"; secret);
 
-        if let Some(default_value) = library_default {
-            env::set_var(var.name, default_value);
-            continue;
+    #[test]
+    fn test_register_enum() {
+        let _guard = env_lock();
+        let var = inventory::iter::<RequiredVar>()
+            .find(|var| var.name == "ENUM_LOG_LEVEL")
+            .unwrap();
+
+        // The default
+        env::remove_var("ENUM_LOG_LEVEL");
+        assert_eq!(var.default(), Some("Info"));
+        assert_eq!(get_enum::<TestLogLevel>("ENUM_LOG_LEVEL").unwrap(), Some(TestLogLevel::Info));
+        assert!(check_value(var).is_ok());
+
+        // A valid variant
+        env::set_var("ENUM_LOG_LEVEL", "debug");
+        assert_eq!(get_enum::<TestLogLevel>("ENUM_LOG_LEVEL").unwrap(), Some(TestLogLevel::Debug));
+        assert!(check_value(var).is_ok());
+
+        // An invalid string
+        env::set_var("ENUM_LOG_LEVEL", "verbose");
+        match check_value(var) {
+            Err(EnvInventoryError::ParseValueError { name, value, type_name }) => {
+                assert_eq!(name, "ENUM_LOG_LEVEL");
+                assert_eq!(value, "verbose");
+                assert_eq!(type_name, std::any::type_name::<TestLogLevel>());
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        assert!(matches!(
+            get_enum::<TestLogLevel>("ENUM_LOG_LEVEL"),
+            Err(EnvInventoryError::ParseValueError { .. })
+        ));
+        env::remove_var("ENUM_LOG_LEVEL");
+
+        // The value of a secret isn't shown
+        env::set_var("ENUM_SECRET_LEVEL", "verbose");
+        match get_enum::<TestLogLevel>("ENUM_SECRET_LEVEL") {
+            Err(EnvInventoryError::ParseValueError { value, .. }) => assert_eq!(value, MASK),
+            other => panic!("expected a parse error, got {:?}", other),
         }
+        env::remove_var("ENUM_SECRET_LEVEL");
 
-        // 5) If not set, add to missing
-        // missing_vars.push(var.name.to_string());
+        assert_eq!(get_enum::<TestLogLevel>("NOT_REGISTERED_VAR").unwrap(), None);
     }
 
-    expanded_map()?;
-    validate_env_vars()
+    #[test]
+    fn test_get_as() {
+        let _guard = env_lock();
+        let var = RequiredVar::new("GET_AS_VAR");
+        env::set_var("GET_AS_VAR", "42");
+        assert_eq!(var.get_as::<u32>().unwrap(), Some(42));
 
-    // if missing_vars.is_empty() {
-    //     Ok(())
-    // } else {
-    //     tracing::warn!("Missing required environment variables: {:?}", missing_vars);
-    //     Err(EnvInventoryError::MissingEnvVars(missing_vars))
-    // }
-}
+        env::set_var("GET_AS_VAR", "true");
+        assert_eq!(var.get_as::<bool>().unwrap(), Some(true));
 
-#[doc(hidden)]
-pub fn __old_load_and_validate_env_vars<P: AsRef<Path>>(
-    config_paths: &[P],
-    section: &str,
-) -> Result<(), EnvInventoryError> {
-    let mut merged_settings = HashMap::new();
+        match var.get_as::<u32>() {
+            Err(EnvInventoryError::ParseValueError { name, value, type_name }) => {
+                assert_eq!(name, "GET_AS_VAR");
+                assert_eq!(value, "true");
+                assert_eq!(type_name, "u32");
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
 
-    for (index, path) in config_paths.iter().enumerate() {
-        let settings = load_toml_settings(path.as_ref(), section);
+        // The value of a secret isn't shown
+        match var.clone().with_secret().get_as::<u32>() {
+            Err(EnvInventoryError::ParseValueError { value, .. }) => assert_eq!(value, MASK),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
 
-        match settings {
-            Ok(current_settings) => {
-                // Merge settings
-                for (key, value) in current_settings.iter() {
-                    if !merged_settings.contains_key(key) {
-                        merged_settings.insert(key.clone(), value.clone());
-                    }
-                }
-            }
-            Err(e) => {
-                if index == 0 {
-                    // The first file is mandatory
-                    return Err(e);
-                } else {
-                    // Subsequent files are optional, but let's warn for transparency
-                    eprintln!(
-                        "Warning: Could not load settings from {:?}. Reason: {}",
-                        path.as_ref(),
-                        e
-                    );
-                }
+        env::remove_var("GET_AS_VAR");
+        assert_eq!(var.get_as::<u32>().unwrap(), None);
+    }
+
+    #[test]
+    fn test_file_kinds() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("does_not_exist.conf");
+        let present = dir.path().join("settings.conf");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(
+            &present,
+            format!("[env]\nTEST_ENV_VAR = \"from_file\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+
+        // A missing optional file is fine, even in first position
+        load_and_validate_files(
+            &[(&missing, FileKind::Optional), (&present, FileKind::Mandatory)],
+            "env",
+        )
+        .unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "from_file");
+
+        // A missing mandatory file fails, even in last position
+        let result = load_and_validate_files(
+            &[(&present, FileKind::Optional), (&missing, FileKind::Mandatory)],
+            "env",
+        );
+        assert!(matches!(result, Err(EnvInventoryError::ReadFileError(_))));
+        env::remove_var("TEST_ENV_VAR");
+    }
+
+    #[test]
+    fn test_detect_config_conflicts() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("first.conf");
+        let second = dir.path().join("second.conf");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(
+            &first,
+            format!("[env]\nTEST_ENV_VAR = \"one\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+        fs::write(
+            &second,
+            format!("[env]\nTEST_ENV_VAR = \"two\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+        let files = [(&first, FileKind::Mandatory), (&second, FileKind::Mandatory)];
+
+        match load_and_validate_files_with(&files, "env", true) {
+            Err(EnvInventoryError::ConfigValueConflict(conflicts)) => {
+                assert_eq!(
+                    conflicts,
+                    vec![ConfigConflict {
+                        name: "TEST_ENV_VAR".to_string(),
+                        values: vec![
+                            (first.display().to_string(), "one".to_string()),
+                            (second.display().to_string(), "two".to_string()),
+                        ],
+                    }]
+                );
             }
+            other => panic!("expected a conflict, got {:?}", other),
         }
+        assert!(env::var("TEST_ENV_VAR").is_err());
+
+        // Without detection the later file wins as before
+        load_and_validate_files_with(&files, "env", false).unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "two");
+        env::remove_var("TEST_ENV_VAR");
     }
 
-    // Override the environment variables with our merged settings if they aren't
-    // already set
-    for (key, value) in merged_settings.iter() {
-        if env::var(key).is_err() {
-            env::set_var(key, value);
+    #[test]
+    fn test_load_first_existing() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let missing = dir.path().join("missing.toml");
+        let first = dir.path().join("first.toml");
+        let second = dir.path().join("second.toml");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(
+            &first,
+            format!("[env]\nTEST_ENV_VAR = \"first\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+        fs::write(
+            &second,
+            format!("[env]\nTEST_ENV_VAR = \"second\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+
+        // Only the first existing candidate is loaded
+        load_first_existing(&[&missing, &first, &second], "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "first");
+        env::remove_var("TEST_ENV_VAR");
+
+        match load_first_existing(&[&missing, &dir.path().join("also_missing.toml")], "env") {
+            Err(EnvInventoryError::NoConfigFile(tried)) => assert_eq!(tried.len(), 2),
+            other => panic!("expected no config file, got {:?}", other),
         }
-        let value = env::var(key).unwrap();
-        tracing::info!("{} = {}", key, value);
     }
-    return validate_env_vars();
 
-}
+    #[test]
+    fn test_load_dotenv_alongside_toml() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let toml = dir.path().join("app.toml");
+        let dotenv = dir.path().join(".env");
+        env::remove_var("TEST_ENV_VAR");
+        env::remove_var("GETTER_VAR");
+        fs::write(
+            &toml,
+            format!("[env]\nTEST_ENV_VAR = \"from toml\"\nGETTER_VAR = \"from toml\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+        fs::write(&dotenv, "# overrides\r\nexport GETTER_VAR=\"from dotenv\"\r\n").unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::env;
-    use std::fs;
-    use tempfile::tempdir;
+        load_and_validate_env_vars(&[&toml, &dotenv], "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "from toml");
+        assert_eq!(env::var("GETTER_VAR").unwrap(), "from dotenv");
+        env::remove_var("TEST_ENV_VAR");
+        env::remove_var("GETTER_VAR");
+    }
+
+    #[test]
+    fn test_load_from_path_list_env() {
+        let _guard = env_lock();
+        let dir = tempdir().unwrap();
+        let first = dir.path().join("app.toml");
+        let second = dir.path().join("local.toml");
+        env::remove_var("TEST_ENV_VAR");
+        env::remove_var("GETTER_VAR");
+        fs::write(
+            &first,
+            format!("[env]\nTEST_ENV_VAR = \"first\"\nGETTER_VAR = \"first\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
+        fs::write(&second, "[env]\nGETTER_VAR = \"second\"\n").unwrap();
+        env::set_var("TEST_CONFIG_PATH", env::join_paths([&first, &second]).unwrap());
+
+        load_from_path_list_env("TEST_CONFIG_PATH", "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "first");
+        assert_eq!(env::var("GETTER_VAR").unwrap(), "second");
+        env::remove_var("TEST_ENV_VAR");
+        env::remove_var("GETTER_VAR");
 
-    register!("TEST_ENV_VAR");
+        env::remove_var("TEST_CONFIG_PATH");
+        assert!(matches!(
+            load_from_path_list_env("TEST_CONFIG_PATH", "env"),
+            Err(EnvInventoryError::MissingEnvVar(_))
+        ));
+    }
 
     #[test]
-    fn test_load_single_toml() {
+    fn test_load_confd() {
+        let _guard = env_lock();
         let dir = tempdir().unwrap();
-        let file_path = dir.path().join("settings.conf");
+        let base = dir.path().join("app.toml");
+        let confd = dir.path().join("conf.d");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(
+            &base,
+            format!("[env]\nTEST_ENV_VAR = \"base\"\n{}", REQUIRED_SETTINGS),
+        )
+        .unwrap();
 
-        fs::write(&file_path, "[env]\nTEST_ENV_VAR = \"test_value\"").unwrap();
+        // Without a drop-in directory only the base file applies
+        load_confd(&base, &confd, "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "base");
+        env::remove_var("TEST_ENV_VAR");
 
-        load_and_validate_env_vars(&[file_path], "env").unwrap();
-        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "test_value");
+        fs::create_dir(&confd).unwrap();
+        fs::write(confd.join("20-override.conf"), "[env]\nTEST_ENV_VAR = \"twenty\"\n").unwrap();
+        fs::write(confd.join("10-override.toml"), "[env]\nTEST_ENV_VAR = \"ten\"\n").unwrap();
+        fs::write(confd.join("30-ignored.bak"), "[env]\nTEST_ENV_VAR = \"backup\"\n").unwrap();
+
+        load_confd(&base, &confd, "env").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "twenty");
+        env::remove_var("TEST_ENV_VAR");
     }
 
     #[test]
-    fn test_merge_priority() {
+    fn test_load_from_dir() {
+        let _guard = env_lock();
+        satisfy_required_vars();
         let dir = tempdir().unwrap();
-        let file_path1 = dir.path().join("settings1.conf");
-        let file_path2 = dir.path().join("settings2.conf");
-        fs::write(&file_path1, "[env]\nTEST_ENV_VAR = \"value1\"").unwrap();
-        fs::write(&file_path2, "[env]\nTEST_ENV_VAR = \"value2\"").unwrap();
+        env::remove_var("DATABASE_URL");
+        env::remove_var("API_KEY");
+        fs::write(dir.path().join("DATABASE_URL"), "postgres://db.internal/app\n").unwrap();
+        fs::write(dir.path().join("API_KEY"), "  s3cr3t  ").unwrap();
+        fs::write(dir.path().join(".hidden"), "ignored").unwrap();
+        fs::create_dir(dir.path().join("..data")).unwrap();
 
-        load_and_validate_env_vars(&[file_path2, file_path1], "env").unwrap();
-        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "value2");
+        load_from_dir(dir.path()).unwrap();
+        assert_eq!(env::var("DATABASE_URL").unwrap(), "postgres://db.internal/app");
+        assert_eq!(env::var("API_KEY").unwrap(), "s3cr3t");
+        env::remove_var("DATABASE_URL");
+        env::remove_var("API_KEY");
+
+        assert!(matches!(
+            load_from_dir(&dir.path().join("missing")),
+            Err(EnvInventoryError::ReadFileError(_))
+        ));
     }
 
     #[test]
-    fn test_missing_mandatory_config() {
+    fn test_secret_redaction() {
+        let _guard = env_lock();
+        let secret = registered("API_KEY");
+        env::set_var("API_KEY", "s3cr3t");
+        assert_eq!(secret.to_string(), "API_KEY=****");
+        assert_eq!(Unredacted(secret).to_string(), "API_KEY=s3cr3t");
+
+        let debug = format!("{:?}", secret);
+        assert!(debug.contains("default: Some(\"****\")"));
+        assert!(!debug.contains("\"none\""));
+        assert!(format!("{:?}", Unredacted(secret)).contains("default: Some(\"none\")"));
+
+        assert!(list_all_vars_redacted().contains(&"API_KEY=****".to_string()));
+        assert!(list_all_vars_unredacted().contains(&"API_KEY=s3cr3t".to_string()));
+        env::remove_var("API_KEY");
+
+        // Other variables are shown as they are
+        env::set_var("TYPED_OFFSET", "-1");
+        assert_eq!(registered("TYPED_OFFSET").to_string(), "TYPED_OFFSET=-1");
+        assert!(list_all_vars_redacted().contains(&"TYPED_OFFSET=-1".to_string()));
+        assert!(format!("{:?}", registered("TYPED_OFFSET")).contains("default: Some(\"0\")"));
+        env::remove_var("TYPED_OFFSET");
+    }
+
+    #[test]
+    fn test_dump_all_vars_to() {
+        let _guard = env_lock();
+        let mut out = Vec::new();
+        dump_all_vars_to(&mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let getter = format!("{:#?}\n", registered("GETTER_VAR"));
+        assert!(out.contains(&getter));
+        assert!(out.contains("\"****\""));
+        assert!(out.find("name: \"API_KEY\"") < out.find("name: \"TEST_ENV_VAR\""));
+        assert_eq!(
+            out.matches("RequiredVar {").count(),
+            inventory::iter::<RequiredVar>().count()
+        );
+    }
+
+    #[test]
+    fn test_generation() {
+        let _guard = env_lock();
+        satisfy_required_vars();
         let dir = tempdir().unwrap();
-        let file_path1 = dir.path().join("does_not_exist.conf");
-        let file_path2 = dir.path().join("settings.conf");
-        fs::write(&file_path2, "[env]\nTEST_ENV_VAR = \"test_value\"").unwrap();
+        let path = dir.path().join("app.toml");
+        fs::write(&path, "[env]\nGETTER_VAR = \"from file\"\n").unwrap();
+        env::remove_var("GETTER_VAR");
 
-        assert!(load_and_validate_env_vars(&[file_path1, file_path2], "env").is_err());
+        load_and_validate_env_vars(&[&path], "env").unwrap();
+        let loaded = generation();
+        assert!(loaded > 0);
+
+        // Reloading the same config keeps the generation
+        load_and_validate_env_vars(&[&path], "env").unwrap();
+        assert_eq!(generation(), loaded);
+
+        // Loads that leave the environment untouched don't count
+        env::set_var("GETTER_VAR", "elsewhere");
+        LoaderConfig::new().mutate_env(false).load(&[&path], "env").unwrap();
+        Loader::new().add_env().load().unwrap();
+        assert_eq!(generation(), loaded);
+
+        env::remove_var("GETTER_VAR");
+        fs::write(&path, "[env]\nGETTER_VAR = \"changed\"\n").unwrap();
+        load_and_validate_env_vars(&[&path], "env").unwrap();
+        let reloaded = generation();
+        assert!(reloaded > loaded);
+
+        // A failed load doesn't count
+        fs::write(&path, "[env]\nGETTER_VAR = \"changed\"\nASSERTED_WORKERS = \"0\"\n").unwrap();
+        env::remove_var("GETTER_VAR");
+        env::remove_var("ASSERTED_WORKERS");
+        assert!(load_and_validate_env_vars(&[&path], "env").is_err());
+        assert_eq!(generation(), reloaded);
+        env::remove_var("GETTER_VAR");
+        env::remove_var("ASSERTED_WORKERS");
     }
 
     #[test]
-    fn test_missing_env_vars() {
+    fn test_optional_vars() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        env::remove_var("OPTIONAL_PROXY_URL");
+        env::remove_var("OPTIONAL_SIGNING_KEY");
+        let proxy = registered("OPTIONAL_PROXY_URL");
+        assert!(proxy.is_optional());
+        assert!(registered("OPTIONAL_SIGNING_KEY").is_optional());
+        assert!(registered("OPTIONAL_SIGNING_KEY").is_secret());
+        assert!(!registered("GETTER_VAR").is_optional());
+
+        // Never missing, but still listed and readable
+        validate_env_vars().unwrap();
+        assert!(list_all_vars().iter().any(|var| var.contains("OPTIONAL_PROXY_URL")));
+        assert_eq!(proxy.get(), None);
+        env::set_var("OPTIONAL_PROXY_URL", "http://proxy:3128");
+        assert_eq!(proxy.get().unwrap(), "http://proxy:3128");
+        env::remove_var("OPTIONAL_PROXY_URL");
+
+        let loaded = LoaderConfig::new().mutate_env(false).load_files::<&Path, _>(&[], "env").unwrap();
+        assert!(!loaded.contains_key("OPTIONAL_PROXY_URL"));
+    }
+
+    #[test]
+    fn test_sensitivity_levels() {
+        let _guard = env_lock();
+        let internal = registered("INTERNAL_HOST");
+        assert_eq!(internal.sensitivity(), Sensitivity::Internal);
+        assert!(!internal.is_secret());
+        assert_eq!(registered("API_KEY").sensitivity(), Sensitivity::Secret);
+        assert_eq!(registered("TYPED_OFFSET").sensitivity(), Sensitivity::Public);
+
+        // Only secrets are redacted by default
+        assert_eq!(redaction_level(), Sensitivity::Secret);
+        env::set_var("API_KEY", "s3cr3t");
+        let vars = list_all_vars_redacted();
+        assert!(vars.contains(&"INTERNAL_HOST=billing.internal".to_string()));
+        assert!(vars.contains(&"API_KEY=****".to_string()));
+        let toml = dump_effective_config_toml();
+        assert!(toml.contains("INTERNAL_HOST = \"billing.internal\""));
+        assert!(!toml.contains("s3cr3t"));
+
+        set_redaction_level(Sensitivity::Internal);
+        assert_eq!(internal.to_string(), "INTERNAL_HOST=****");
+        assert!(!dump_effective_config_toml().contains("billing.internal"));
+        set_redaction_level(Sensitivity::Secret);
+        env::remove_var("API_KEY");
+    }
+
+    #[test]
+    fn test_resolve_one() {
+        let _guard = env_lock();
+        env::remove_var("TYPED_HOST");
+        env::remove_var("GETTER_VAR");
+
+        assert_eq!(
+            resolve_one("TYPED_HOST"),
+            Some(("localhost".to_string(), VarSource::LibraryDefault))
+        );
+        assert_eq!(
+            resolve_one("GETTER_VAR"),
+            Some(("value".to_string(), VarSource::BinaryDefault))
+        );
+
+        env::set_var("TYPED_HOST", "example.com");
+        assert_eq!(
+            resolve_one("TYPED_HOST"),
+            Some(("example.com".to_string(), VarSource::Environment))
+        );
+        env::remove_var("TYPED_HOST");
+
+        assert_eq!(resolve_one("NOT_REGISTERED_VAR"), None);
+    }
+
+    #[test]
+    fn test_register_prefixed() {
+        let _guard = env_lock();
+        let names = |prefix: &str| -> Vec<&str> {
+            vars_with_prefix(prefix).iter().map(|var| var.name).collect()
+        };
+        assert_eq!(names("PREFIXED"), vec!["PREFIXED_DATABASE_URL", "PREFIXED_REDIS_URL"]);
+        assert_eq!(names("PREFIXED_"), names("PREFIXED"));
+        assert!(names("PREFIX").is_empty());
+
+        // The full name is the one looked up
+        env::remove_var("PREFIXED_REDIS_URL");
+        assert_eq!(
+            resolve_one("PREFIXED_REDIS_URL"),
+            Some(("redis://localhost".to_string(), VarSource::LibraryDefault))
+        );
+        assert_eq!(registered("PREFIXED_DATABASE_URL").default(), Some("postgres://localhost/app"));
+    }
+
+    #[test]
+    fn test_get_first_set() {
+        let _guard = env_lock();
+        let chain = ["FALLBACK_PRIMARY_URL", "FALLBACK_URL", "FALLBACK_LEGACY_URL"];
+        for name in chain {
+            env::remove_var(name);
+        }
+        assert_eq!(get_first_set(&chain).unwrap(), None);
+
+        env::set_var("FALLBACK_URL", "postgres://fallback/app");
+        assert_eq!(
+            get_first_set(&chain).unwrap(),
+            Some(("FALLBACK_URL".to_string(), "postgres://fallback/app".to_string()))
+        );
+
+        // Earlier names win
+        env::set_var("FALLBACK_PRIMARY_URL", "postgres://primary/app");
+        env::set_var("FALLBACK_LEGACY_URL", "postgres://legacy/app");
+        assert_eq!(get_first_set(&chain).unwrap().unwrap().0, "FALLBACK_PRIMARY_URL");
+
+        // Defaults don't count as set
+        env::remove_var("DATABASE_URL");
+        let first = get_first_set(&["DATABASE_URL", "FALLBACK_LEGACY_URL"]).unwrap();
+        assert_eq!(first.unwrap().0, "FALLBACK_LEGACY_URL");
+
+        // Matches ignoring case are ambiguous rather than skipped
+        env::set_var("fallback_primary_url", "postgres://lower/app");
+        env::set_var("Fallback_Primary_Url", "postgres://mixed/app");
+        env::remove_var("FALLBACK_PRIMARY_URL");
+        crate::set_case_insensitive(true);
+        assert!(matches!(get_first_set(&chain), Err(EnvInventoryError::AmbiguousVar { .. })));
+        crate::set_case_insensitive(false);
+        env::remove_var("fallback_primary_url");
+        env::remove_var("Fallback_Primary_Url");
+
+        for name in chain {
+            env::remove_var(name);
+        }
+    }
+
+    #[test]
+    fn test_load_without_section() {
+        let _guard = env_lock();
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("settings.conf");
+        env::remove_var("TEST_ENV_VAR");
+        fs::write(
+            &file_path,
+            format!(
+                "TEST_ENV_VAR = \"top_level\"\n{}\n[other]\nTYPED_HOST = \"ignored\"\n",
+                REQUIRED_SETTINGS
+            ),
+        )
+        .unwrap();
 
-        // Write a file without MISSING_VAR
-        fs::write(&file_path, "[env]\nSOME_OTHER_VAR = \"some_value\"").unwrap();
+        let settings = load_toml_settings(&file_path, "").unwrap();
+        assert_eq!(settings.get("TEST_ENV_VAR").unwrap(), "top_level");
+        assert!(!settings.contains_key("TYPED_HOST"));
+        assert!(!settings.contains_key("other"));
 
-        // Ensure the environment variable isn't set before the test
-        env::remove_var("MISSING_VAR");
+        load_and_validate_env_vars(&[file_path], "").unwrap();
+        assert_eq!(env::var("TEST_ENV_VAR").unwrap(), "top_level");
+        env::remove_var("TEST_ENV_VAR");
+    }
 
-        // Register MISSING_VAR as a required environment variable
-        register!("MISSING_VAR");
+    #[test]
+    fn test_check_schema_version() {
+        let matching: Value = "schema_version = 2\n[env]\n".parse().unwrap();
+        let older: Value = "schema_version = 1\n[env]\n".parse().unwrap();
+        let undeclared: Value = "[env]\n".parse().unwrap();
 
-        // Since MISSING_VAR isn't in the environment and also isn't in the TOML files,
-        // the function should return an error.
-        assert!(load_and_validate_env_vars(&[file_path], "env").is_err());
+        assert!(check_schema_version(None, &older).is_ok());
+        assert!(check_schema_version(Some(2), &matching).is_ok());
+        assert!(matches!(
+            check_schema_version(Some(2), &older),
+            Err(EnvInventoryError::SchemaVersionMismatch { expected: 2, found: Some(1) })
+        ));
+        assert!(matches!(
+            check_schema_version(Some(2), &undeclared),
+            Err(EnvInventoryError::SchemaVersionMismatch { expected: 2, found: None })
+        ));
     }
 
+    #[cfg(unix)]
     #[test]
-    fn test_present_env_vars() {
+    fn test_apply_to_command() {
+        let _guard = env_lock();
+        env::remove_var("TYPED_HOST");
+        let echo = |cmd: &mut std::process::Command| {
+            let output = cmd.output().unwrap();
+            String::from_utf8(output.stdout).unwrap()
+        };
+
+        let mut cmd = std::process::Command::new("sh");
+        cmd.args(["-c", "printf '%s' \"$TYPED_HOST\""]).env_remove("TYPED_HOST");
+        assert_eq!(echo(apply_to_command(&mut cmd)), "localhost");
+
+        let mut cmd = std::process::Command::new("sh");
+        cmd.args(["-c", "printf '%s' \"$TYPED_HOST\""]).env_remove("TYPED_HOST");
+        apply_to_command_filtered(&mut cmd, |name| name.starts_with("GETTER_"));
+        assert_eq!(echo(&mut cmd), "");
+    }
+
+    #[test]
+    fn test_drift_since_init() {
+        let _guard = env_lock();
         let dir = tempdir().unwrap();
         let file_path = dir.path().join("settings.conf");
-
-        // Write a file with PRESENT_VAR
+        env::remove_var("TYPED_HOST");
         fs::write(
             &file_path,
-            r#"
-        [env]
-        PRESENT_VAR = "present_value"
-        MISSING_VAR = "missing_value"
-        TEST_ENV_VAR = "test_value"
-        "#,
+            format!("[env]\nTEST_ENV_VAR = \"test_value\"\n{}", REQUIRED_SETTINGS),
         )
         .unwrap();
 
-        // Ensure the environment variable isn't set before the test
-        env::remove_var("PRESENT_VAR");
+        init_config(&[file_path], "env").unwrap();
+        assert!(drift_since_init().iter().all(|change| change.name != "TYPED_HOST"));
 
-        // Register PRESENT_VAR as a required environment variable
-        register!("PRESENT_VAR");
+        env::set_var("TYPED_HOST", "changed.example.com");
+        let drift = drift_since_init();
+        let change = drift.iter().find(|change| change.name == "TYPED_HOST").unwrap();
+        assert_eq!(change.before.as_deref(), Some("localhost"));
+        assert_eq!(change.after.as_deref(), Some("changed.example.com"));
+        env::remove_var("TYPED_HOST");
 
-        // Since PRESENT_VAR is in the TOML file, the function should run without errors
-        load_and_validate_env_vars(&[file_path], "env").unwrap();
+        // A changed secret is reported without its values
+        env::set_var("API_KEY", "rotated");
+        let drift = drift_since_init();
+        let change = drift.iter().find(|change| change.name == "API_KEY").unwrap();
+        assert_eq!(change.before.as_deref(), Some(MASK));
+        assert_eq!(change.after.as_deref(), Some(MASK));
+        env::remove_var("API_KEY");
     }
 }
@@ -0,0 +1,133 @@
+//! Parsing of values holding lists.
+
+use crate::access;
+
+/// Gets the value of a registered variable as a comma-separated list, with
+/// each item trimmed of surrounding whitespace.
+///
+/// This is how lists are read by [`init_typed`](crate::init_typed). A blank
+/// value is an empty list. Use [`get_list_with`] for other delimiters, or to
+/// drop empty items.
+///
+/// Returns `None` if `name` isn't registered or has no value.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{get_list, register};
+/// register!(ALLOWED_ORIGINS = "https://a.example, https://b.example");
+///
+/// assert_eq!(
+///     get_list("ALLOWED_ORIGINS").unwrap(),
+///     vec!["https://a.example", "https://b.example"]
+/// );
+/// ```
+pub fn get_list(name: &str) -> Option<Vec<String>> {
+    get_list_with(name, &[','], true, false)
+}
+
+/// Gets the value of a registered variable as a list split on any of
+/// `delimiters`.
+///
+/// With `trim`, surrounding whitespace is removed from each item. With
+/// `skip_empty`, empty items, such as the one after a trailing delimiter in
+/// `"a,b,"`, are dropped. A value that is empty, or blank with `trim`, is an
+/// empty list either way.
+///
+/// Returns `None` if `name` isn't registered or has no value.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{get_list_with, register};
+/// register!(SEARCH_PATHS = "/usr/lib; /opt/lib,/srv/lib;");
+///
+/// assert_eq!(
+///     get_list_with("SEARCH_PATHS", &[',', ';'], true, true).unwrap(),
+///     vec!["/usr/lib", "/opt/lib", "/srv/lib"]
+/// );
+/// ```
+pub fn get_list_with(
+    name: &str,
+    delimiters: &[char],
+    trim: bool,
+    skip_empty: bool,
+) -> Option<Vec<String>> {
+    access::read(name).map(|(value, _)| split_list(&value, delimiters, trim, skip_empty))
+}
+
+pub(crate) fn split_list(
+    value: &str,
+    delimiters: &[char],
+    trim: bool,
+    skip_empty: bool,
+) -> Vec<String> {
+    let blank = if trim {
+        value.trim().is_empty()
+    } else {
+        value.is_empty()
+    };
+    if blank {
+        return Vec::new();
+    }
+
+    value
+        .split(|c| delimiters.contains(&c))
+        .map(|item| if trim { item.trim() } else { item })
+        .filter(|item| !(skip_empty && item.is_empty()))
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    crate::register!(LIST_VAR = "a, b");
+
+    #[test]
+    fn test_get_list() {
+        let _guard = crate::env_lock();
+        env::remove_var("LIST_VAR");
+        assert_eq!(get_list("LIST_VAR").unwrap(), vec!["a", "b"]);
+
+        env::set_var("LIST_VAR", "a;b, c;");
+        assert_eq!(
+            get_list_with("LIST_VAR", &[',', ';'], true, true).unwrap(),
+            vec!["a", "b", "c"]
+        );
+        env::remove_var("LIST_VAR");
+
+        assert_eq!(get_list("NOT_REGISTERED_VAR"), None);
+    }
+
+    #[test]
+    fn test_split_list() {
+        // Trailing delimiters
+        assert_eq!(split_list("a,b,", &[','], true, false), vec!["a", "b", ""]);
+        assert_eq!(split_list("a,b,", &[','], true, true), vec!["a", "b"]);
+
+        // Mixed separators
+        assert_eq!(
+            split_list("a b;c,d", &[',', ' ', ';'], false, false),
+            vec!["a", "b", "c", "d"]
+        );
+
+        // Empty elements, and whitespace kept without trimming
+        assert_eq!(
+            split_list(" a,, b ,", &[','], false, false),
+            vec![" a", "", " b ", ""]
+        );
+        assert_eq!(
+            split_list(" a,, b ,", &[','], false, true),
+            vec![" a", " b "]
+        );
+        assert_eq!(split_list("a,  ,b", &[','], true, true), vec!["a", "b"]);
+
+        // Blank values
+        assert!(split_list("", &[','], false, false).is_empty());
+        assert!(split_list("  ", &[','], true, false).is_empty());
+        assert_eq!(split_list("  ", &[','], false, false), vec!["  "]);
+    }
+}
@@ -0,0 +1,811 @@
+//! Configurable loading of config files.
+
+use crate::diagnostic::{self, Diagnostic};
+use crate::{
+    apply_settings, case, effective_map, load_file_sections, merge_settings, positional_file_kinds,
+    resolve, EnvInventoryError, FileKind, RequiredVar, Sections,
+};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How a loader tells the format of a config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ConfigFormat {
+    /// Files named `.env` or `.env.*`, or with the `env` extension, are
+    /// `.env` files; files with the `json`, or `yaml` or `yml`, extension are
+    /// JSON or YAML when the matching feature is enabled; anything else is
+    /// TOML. This is the default.
+    ByExtension,
+    /// Like [`ConfigFormat::ByExtension`] for files with one of those
+    /// extensions or `toml`, as long as they parse. Other files, and files
+    /// that don't parse in the format of their extension, are tried as TOML,
+    /// then JSON and YAML if enabled, and read in the first format that
+    /// provides settings from the section, or else that parses.
+    Auto,
+    /// TOML, whatever the name of the file.
+    Toml,
+    /// A `.env` file, whatever its name.
+    Dotenv,
+    /// JSON, whatever the name of the file.
+    #[cfg(feature = "json")]
+    Json,
+    /// YAML, whatever the name of the file.
+    #[cfg(feature = "yaml")]
+    Yaml,
+}
+
+/// Options for loading config files, for when the defaults of
+/// [`load_and_validate_env_vars`](crate::load_and_validate_env_vars) and
+/// [`load_and_validate_files`](crate::load_and_validate_files) don't fit.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::LoaderConfig;
+/// LoaderConfig::new()
+///     .detect_config_conflicts(true)
+///     .max_file_bytes(64 * 1024)
+///     .load(&["/etc/app/app.toml", "/etc/app/local.toml"], "env")
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LoaderConfig {
+    pub(crate) format: ConfigFormat,
+    pub(crate) detect_config_conflicts: bool,
+    pub(crate) max_file_bytes: Option<u64>,
+    pub(crate) forbid_secrets_in_files: bool,
+    pub(crate) mutate_env: bool,
+    pub(crate) treat_blank_as_unset: bool,
+    /// A section that TOML files may only have as a table, set by
+    /// [`load_with_profile`](crate::load_with_profile).
+    pub(crate) profile_section: Option<String>,
+}
+
+impl Default for LoaderConfig {
+    fn default() -> Self {
+        Self {
+            format: ConfigFormat::ByExtension,
+            detect_config_conflicts: false,
+            max_file_bytes: None,
+            forbid_secrets_in_files: false,
+            mutate_env: true,
+            treat_blank_as_unset: false,
+            profile_section: None,
+        }
+    }
+}
+
+impl LoaderConfig {
+    /// Creates a config with the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// How to tell the format of each config file; see [`ConfigFormat`].
+    pub fn format(mut self, format: ConfigFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Fails with `ConfigValueConflict` when two files set the same variable
+    /// to different values, rather than letting the later file silently win.
+    pub fn detect_config_conflicts(mut self, detect: bool) -> Self {
+        self.detect_config_conflicts = detect;
+        self
+    }
+
+    /// Fails with `FileTooLarge` before reading a config file larger than
+    /// `limit` bytes, guarding against loading a misrouted log or dump.
+    pub fn max_file_bytes(mut self, limit: u64) -> Self {
+        self.max_file_bytes = Some(limit);
+        self
+    }
+
+    /// Fails with `SecretInConfigFile` when a variable registered with the
+    /// `secret` option would take its value from a config file, enforcing
+    /// that secrets come from the environment or a vault instead. Otherwise
+    /// each such secret is only reported as a [`Diagnostic`].
+    pub fn forbid_secrets_in_files(mut self, forbid: bool) -> Self {
+        self.forbid_secrets_in_files = forbid;
+        self
+    }
+
+    /// Whether loading stores the resolved values in the process environment,
+    /// which it does by default.
+    ///
+    /// With `false`, the values are resolved and validated the same way but
+    /// only returned, leaving the environment untouched. This suits
+    /// libraries, which shouldn't mutate the environment of the process
+    /// embedding them. Nothing is recorded for
+    /// [`dump_effective_config_toml`](crate::dump_effective_config_toml) or
+    /// [`drift_since_init`](crate::drift_since_init) either, and the
+    /// [`generation`](crate::generation) doesn't change.
+    pub fn mutate_env(mut self, mutate: bool) -> Self {
+        self.mutate_env = mutate;
+        self
+    }
+
+    /// Treats values that are empty or only whitespace, in the environment
+    /// or the config files, as unset, so the variable falls through to the
+    /// next source or its default, or is reported as missing.
+    ///
+    /// When the environment is mutated, blank values of registered variables
+    /// are removed from it before the others are resolved.
+    pub fn treat_blank_as_unset(mut self, treat: bool) -> Self {
+        self.treat_blank_as_unset = treat;
+        self
+    }
+
+    /// Loads `config_paths` like
+    /// [`load_and_validate_env_vars`](crate::load_and_validate_env_vars): the
+    /// first file is mandatory and the rest optional.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error [`LoaderConfig::load_files`] can return.
+    pub fn load<P: AsRef<Path>, S: Sections + ?Sized>(
+        &self,
+        config_paths: &[P],
+        section: &S,
+    ) -> Result<HashMap<String, String>, EnvInventoryError> {
+        self.load_files(&positional_file_kinds(config_paths), section)
+    }
+
+    /// Loads `files` like [`load_and_validate_files`](crate::load_and_validate_files),
+    /// returning the resolved value of every registered variable that has
+    /// one.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error `load_and_validate_files` can return, and:
+    ///
+    /// * `ConfigValueConflict`: If conflict detection is enabled and files
+    ///   disagree on a value.
+    /// * `FileTooLarge`: If a file exceeds the size limit, even an optional
+    ///   one.
+    /// * `SecretInConfigFile`: If secrets are forbidden in files and a secret
+    ///   would be set from one. Nothing is set in that case.
+    /// * `UnrecognizedConfigFormat`: If the format is
+    ///   [`ConfigFormat::Auto`] and the first file doesn't parse in any
+    ///   format tried.
+    pub fn load_files<P: AsRef<Path>, S: Sections + ?Sized>(
+        &self,
+        files: &[(P, FileKind)],
+        section: &S,
+    ) -> Result<HashMap<String, String>, EnvInventoryError> {
+        let (mut merged_settings, mut origins) = merge_settings(files, &section.names(), self)?;
+        if self.treat_blank_as_unset {
+            merged_settings.retain(|_, value| !is_blank(value));
+            origins.retain(|name, _| merged_settings.contains_key(name));
+        }
+        // Secrets set in the environment aren't read from the files
+        let mut file_origins = origins.clone();
+        file_origins.retain(|name, _| !self.is_set_in_env(name));
+        self.check_secrets(&file_origins)?;
+        if !self.mutate_env {
+            return resolve(&merged_settings, self.treat_blank_as_unset);
+        }
+        let _lock = crate::sync::lock_env();
+        if self.treat_blank_as_unset {
+            for var in inventory::iter::<RequiredVar>() {
+                if env::var(var.name).map_or(false, |value| is_blank(&value)) {
+                    crate::sync::remove_var(var.name);
+                }
+            }
+        }
+        apply_settings(&merged_settings, &origins)?;
+        Ok(effective_map())
+    }
+
+    /// Checks if `name` is set in the environment, looked up as the
+    /// resolution does, so the value from the files isn't used.
+    fn is_set_in_env(&self, name: &str) -> bool {
+        match case::lookup(name) {
+            Ok(value) => value.map_or(false, |value| {
+                !(self.treat_blank_as_unset && is_blank(&value))
+            }),
+            // The resolution fails on the ambiguous name itself
+            Err(_) => true,
+        }
+    }
+
+    /// Fails, or reports a [`Diagnostic`] unless secrets in files are
+    /// forbidden, for every secret that would be set from one of `origins`,
+    /// a map of setting name to the file it came from.
+    fn check_secrets(&self, origins: &HashMap<String, String>) -> Result<(), EnvInventoryError> {
+        if self.forbid_secrets_in_files {
+            check_secrets(origins)
+        } else {
+            warn_secrets(origins);
+            Ok(())
+        }
+    }
+}
+
+/// A source of settings layered by a [`Loader`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Layer {
+    /// A config file, read in the given format.
+    File(PathBuf, ConfigFormat),
+    /// The registered variables set in the environment.
+    Env,
+}
+
+/// Loads config from sources layered in an explicit order, for when the
+/// fixed precedence of
+/// [`load_and_validate_env_vars`](crate::load_and_validate_env_vars), where
+/// the environment wins over every file, doesn't fit.
+///
+/// Each `add_*` method pushes a source, and [`Loader::load`] merges them in
+/// the order they were added, later sources overriding earlier ones.
+/// Registered variables no source sets fall back to their defaults. Unlike
+/// the other loaders, the environment only takes part where
+/// [`Loader::add_env`] puts it, apart from variable references in values,
+/// which are still looked up there, and nothing is set in it.
+///
+/// [`load_and_validate_env_vars`](crate::load_and_validate_env_vars) isn't
+/// built on a `Loader`: it sets the values in the environment and records
+/// where each came from, and the files after its first are optional, while a
+/// `Loader` only returns the values and requires every file.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::Loader;
+/// // Shipped defaults, overridden by the environment, overridden in turn by
+/// // the operator's file
+/// let values = Loader::new()
+///     .section("env")
+///     .add_toml("/usr/share/app/app.toml")
+///     .add_env()
+///     .add_dotenv("/etc/app/override.env")
+///     .load()
+///     .unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Loader {
+    layers: Vec<Layer>,
+    sections: Vec<String>,
+    config: LoaderConfig,
+}
+
+impl Loader {
+    /// Creates a loader without any source.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The section, or sections, of the config files holding the variables;
+    /// see [`Sections`]. Files are read from the top level by default.
+    pub fn section<S: Sections + ?Sized>(mut self, section: &S) -> Self {
+        self.sections = section.names().into_iter().map(str::to_string).collect();
+        self
+    }
+
+    /// Fails with `FileTooLarge` before reading a config file larger than
+    /// `limit` bytes; see [`LoaderConfig::max_file_bytes`].
+    pub fn max_file_bytes(mut self, limit: u64) -> Self {
+        self.config = self.config.max_file_bytes(limit);
+        self
+    }
+
+    /// Fails with `SecretInConfigFile` when a secret would take its value
+    /// from a config file, rather than only reporting a [`Diagnostic`]; see
+    /// [`LoaderConfig::forbid_secrets_in_files`].
+    pub fn forbid_secrets_in_files(mut self, forbid: bool) -> Self {
+        self.config = self.config.forbid_secrets_in_files(forbid);
+        self
+    }
+
+    /// Adds a config file read in `format`.
+    pub fn add_file<P: AsRef<Path>>(mut self, path: P, format: ConfigFormat) -> Self {
+        self.layers
+            .push(Layer::File(path.as_ref().to_path_buf(), format));
+        self
+    }
+
+    /// Adds a TOML config file.
+    pub fn add_toml<P: AsRef<Path>>(self, path: P) -> Self {
+        self.add_file(path, ConfigFormat::Toml)
+    }
+
+    /// Adds a JSON config file.
+    #[cfg(feature = "json")]
+    pub fn add_json<P: AsRef<Path>>(self, path: P) -> Self {
+        self.add_file(path, ConfigFormat::Json)
+    }
+
+    /// Adds a YAML config file.
+    #[cfg(feature = "yaml")]
+    pub fn add_yaml<P: AsRef<Path>>(self, path: P) -> Self {
+        self.add_file(path, ConfigFormat::Yaml)
+    }
+
+    /// Adds a `.env` file.
+    pub fn add_dotenv<P: AsRef<Path>>(self, path: P) -> Self {
+        self.add_file(path, ConfigFormat::Dotenv)
+    }
+
+    /// Adds the registered variables set in the environment.
+    pub fn add_env(mut self) -> Self {
+        self.layers.push(Layer::Env);
+        self
+    }
+
+    /// Merges the sources, then resolves and validates the registered
+    /// variables, returning the resolved value of every one that has a
+    /// value.
+    ///
+    /// # Errors
+    ///
+    /// * `ReadFileError`: If a file can't be read. Every file is mandatory.
+    /// * `FileTooLarge`: If a file exceeds the size limit.
+    /// * `ParseFileError`: If a file can't be parsed in its format.
+    /// * `SecretInConfigFile`: If secrets are forbidden in files and a secret
+    ///   would be set from one.
+    /// * `MissingEnvVars`: If one or more registered variables have no value.
+    /// * `AmbiguousVar`: If the environment is added and a variable matches
+    ///   several environment variables ignoring case.
+    ///
+    /// And any error [`validate_env_vars`](crate::validate_env_vars) returns
+    /// for an invalid value.
+    pub fn load(&self) -> Result<HashMap<String, String>, EnvInventoryError> {
+        let sections: Vec<&str> = self.sections.iter().map(String::as_str).collect();
+        let mut merged = HashMap::new();
+        let mut origins = HashMap::new();
+        for layer in &self.layers {
+            match layer {
+                Layer::File(path, format) => {
+                    check_file_size(path, self.config.max_file_bytes)?;
+                    let settings = load_file_sections(path, &sections, *format, None)?;
+                    for name in settings.keys() {
+                        origins.insert(name.clone(), path.display().to_string());
+                    }
+                    merged.extend(settings);
+                }
+                Layer::Env => {
+                    for var in inventory::iter::<RequiredVar>() {
+                        if let Some(value) = case::lookup(var.name)? {
+                            origins.remove(var.name);
+                            merged.insert(var.name.to_string(), value);
+                        }
+                    }
+                }
+            }
+        }
+        self.config.check_secrets(&origins)?;
+        resolve_with(|name| Ok(merged.get(name).cloned()))
+    }
+}
+
+fn is_blank(value: &str) -> bool {
+    value.trim().is_empty()
+}
+
+/// Resolves and validates the registered variables like [`apply_settings`],
+/// without setting anything in the environment, ignoring blank environment
+/// values if `skip_blank`.
+fn resolve(
+    merged_settings: &HashMap<String, String>,
+    skip_blank: bool,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    resolve_with(|name| {
+        let value = case::lookup(name)?.filter(|value| !(skip_blank && is_blank(value)));
+        Ok(value.or_else(|| merged_settings.get(name).cloned()))
+    })
+}
+
+/// Resolves and validates the registered variables, taking the values set
+/// explicitly from `lookup`.
+fn resolve_with<L>(lookup: L) -> Result<HashMap<String, String>, EnvInventoryError>
+where
+    L: Fn(&str) -> Result<Option<String>, EnvInventoryError>,
+{
+    let resolution = resolve::resolve(lookup)?;
+    resolution.validate()?;
+    Ok(resolution.values)
+}
+
+/// Lists the secrets that would be set from one of `origins`, a map of
+/// setting name to the file it came from, with that file.
+fn secrets_in_files(origins: &HashMap<String, String>) -> Vec<(&'static str, &String)> {
+    let mut offending: Vec<(&str, &String)> = inventory::iter::<RequiredVar>()
+        .filter(|var| var.is_secret())
+        .filter_map(|var| Some((var.name, origins.get(var.name)?)))
+        .collect();
+    offending.sort();
+    offending.dedup();
+    offending
+}
+
+/// Fails if a secret would be set from one of `origins`, as listed by
+/// [`secrets_in_files`].
+fn check_secrets(origins: &HashMap<String, String>) -> Result<(), EnvInventoryError> {
+    match secrets_in_files(origins).first() {
+        Some((name, path)) => Err(EnvInventoryError::SecretInConfigFile {
+            name: name.to_string(),
+            path: path.to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Reports a [`Diagnostic`] for every secret that would be set from one of
+/// `origins`, as listed by [`secrets_in_files`].
+fn warn_secrets(origins: &HashMap<String, String>) {
+    for (name, path) in secrets_in_files(origins) {
+        diagnostic::emit(Diagnostic::SecretInConfigFile {
+            name: name.to_string(),
+            path: path.to_string(),
+        });
+    }
+}
+
+/// Fails if the file at `path` is larger than `limit` bytes.
+pub(crate) fn check_file_size(path: &Path, limit: Option<u64>) -> Result<(), EnvInventoryError> {
+    let limit = match limit {
+        Some(limit) => limit,
+        None => return Ok(()),
+    };
+    let size = fs::metadata(path)
+        .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?
+        .len();
+    if size > limit {
+        return Err(EnvInventoryError::FileTooLarge {
+            path: path.display().to_string(),
+            size,
+            limit,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env_lock, satisfy_required_vars};
+    use std::env;
+    use tempfile::tempdir;
+
+    crate::register!(LOADER_LIMITED_VAR = "default");
+    crate::register!(LOADER_SECRET = "placeholder"; secret);
+    crate::register!(LOADER_SNIFFED_VAR = "default");
+    crate::register!(LOADER_PUBLIC_TOKEN = "placeholder");
+
+    #[test]
+    fn test_auto_format() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app");
+        let auto = LoaderConfig::new().format(ConfigFormat::Auto).mutate_env(false);
+
+        fs::write(&path, "[env]\nLOADER_SNIFFED_VAR = \"from toml\"\n").unwrap();
+        env::remove_var("LOADER_SNIFFED_VAR");
+        let loaded = auto.load(&[&path], "env").unwrap();
+        assert_eq!(loaded["LOADER_SNIFFED_VAR"], "from toml");
+
+        fs::write(&path, "not = [valid").unwrap();
+        match auto.load(&[&path], "env") {
+            Err(EnvInventoryError::UnrecognizedConfigFormat { path: found, tried }) => {
+                assert_eq!(found, path.display().to_string());
+                assert_eq!(tried[0], "TOML");
+            }
+            other => panic!("expected an unrecognized format, got {:?}", other),
+        }
+        // Without sniffing, the file is TOML
+        assert!(matches!(
+            LoaderConfig::new().load(&[&path], "env"),
+            Err(EnvInventoryError::ParseFileError(_))
+        ));
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn test_auto_format_json() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let auto = LoaderConfig::new().format(ConfigFormat::Auto).mutate_env(false);
+        env::remove_var("LOADER_SNIFFED_VAR");
+
+        let path = dir.path().join("app");
+        fs::write(&path, r#"{"env": {"LOADER_SNIFFED_VAR": "from json"}}"#).unwrap();
+        let loaded = auto.load(&[&path], "env").unwrap();
+        assert_eq!(loaded["LOADER_SNIFFED_VAR"], "from json");
+
+        // A wrong extension falls back to sniffing
+        let path = dir.path().join("app.toml");
+        fs::write(&path, r#"{"env": {"LOADER_SNIFFED_VAR": "from json"}}"#).unwrap();
+        let loaded = auto.load(&[&path], "env").unwrap();
+        assert_eq!(loaded["LOADER_SNIFFED_VAR"], "from json");
+    }
+
+    #[test]
+    fn test_max_file_bytes() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        env::remove_var("LOADER_LIMITED_VAR");
+        fs::write(&path, "[env]\nLOADER_LIMITED_VAR = \"from file\"\n").unwrap();
+        let size = fs::metadata(&path).unwrap().len();
+
+        match LoaderConfig::new().max_file_bytes(8).load(&[&path], "env") {
+            Err(EnvInventoryError::FileTooLarge {
+                size: found, limit, ..
+            }) => {
+                assert_eq!((found, limit), (size, 8));
+            }
+            other => panic!("expected the file to be too large, got {:?}", other),
+        }
+        assert!(env::var("LOADER_LIMITED_VAR").is_err());
+
+        // The limit applies to optional files too
+        let result = LoaderConfig::new()
+            .max_file_bytes(8)
+            .load_files(&[(&path, FileKind::Optional)], "env");
+        assert!(matches!(
+            result,
+            Err(EnvInventoryError::FileTooLarge { .. })
+        ));
+
+        LoaderConfig::new()
+            .max_file_bytes(size)
+            .load(&[&path], "env")
+            .unwrap();
+        assert_eq!(env::var("LOADER_LIMITED_VAR").unwrap(), "from file");
+        env::remove_var("LOADER_LIMITED_VAR");
+    }
+
+    #[test]
+    fn test_mutate_env() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        env::remove_var("LOADER_LIMITED_VAR");
+        env::remove_var("LOADER_SECRET");
+        fs::write(&path, "[env]\nLOADER_LIMITED_VAR = \"from file\"\n").unwrap();
+
+        let resolved = LoaderConfig::new()
+            .mutate_env(false)
+            .load(&[&path], "env")
+            .unwrap();
+        assert_eq!(resolved["LOADER_LIMITED_VAR"], "from file");
+        assert_eq!(resolved["LOADER_SECRET"], "placeholder");
+        assert_eq!(resolved["TEST_ENV_VAR"], env::var("TEST_ENV_VAR").unwrap());
+        assert!(env::var("LOADER_LIMITED_VAR").is_err());
+        assert!(env::var("LOADER_SECRET").is_err());
+
+        // The mutating loader resolves the same values
+        let applied = LoaderConfig::new().load(&[&path], "env").unwrap();
+        assert_eq!(resolved, applied);
+        assert_eq!(env::var("LOADER_LIMITED_VAR").unwrap(), "from file");
+        env::remove_var("LOADER_LIMITED_VAR");
+        env::remove_var("LOADER_SECRET");
+    }
+
+    #[test]
+    fn test_treat_blank_as_unset() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        fs::write(&path, "[env]\nLOADER_SNIFFED_VAR = \" \"\n").unwrap();
+
+        // By default, a blank value is a value
+        env::set_var("LOADER_LIMITED_VAR", "   ");
+        env::remove_var("LOADER_SNIFFED_VAR");
+        let resolved = LoaderConfig::new().load(&[&path], "env").unwrap();
+        assert_eq!(resolved["LOADER_LIMITED_VAR"], "   ");
+        assert_eq!(resolved["LOADER_SNIFFED_VAR"], " ");
+
+        // Otherwise blank values fall through to the defaults
+        for mutate in [false, true] {
+            env::set_var("LOADER_LIMITED_VAR", "   ");
+            env::remove_var("LOADER_SNIFFED_VAR");
+            let resolved = LoaderConfig::new()
+                .treat_blank_as_unset(true)
+                .mutate_env(mutate)
+                .load(&[&path], "env")
+                .unwrap();
+            assert_eq!(resolved["LOADER_LIMITED_VAR"], "default");
+            assert_eq!(resolved["LOADER_SNIFFED_VAR"], "default");
+        }
+        assert_eq!(env::var("LOADER_LIMITED_VAR").unwrap(), "default");
+
+        // or are missing
+        env::set_var("MISSING_VAR", "   ");
+        match LoaderConfig::new()
+            .treat_blank_as_unset(true)
+            .load(&[&path], "env")
+        {
+            Err(EnvInventoryError::MissingEnvVars(missing)) => {
+                assert!(missing.iter().any(|var| var.name == "MISSING_VAR"));
+            }
+            other => panic!("expected a missing variable, got {:?}", other),
+        }
+
+        env::remove_var("LOADER_LIMITED_VAR");
+        env::remove_var("LOADER_SNIFFED_VAR");
+        satisfy_required_vars();
+    }
+
+    #[test]
+    fn test_forbid_secrets_in_files() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        env::remove_var("LOADER_SECRET");
+        fs::write(&path, "[env]\nLOADER_SECRET = \"plaintext\"\n").unwrap();
+        let loader = LoaderConfig::new().forbid_secrets_in_files(true);
+
+        match loader.load(&[&path], "env") {
+            Err(EnvInventoryError::SecretInConfigFile { name, path: found }) => {
+                assert_eq!(name, "LOADER_SECRET");
+                assert_eq!(found, path.display().to_string());
+            }
+            other => panic!("expected a secret in a config file, got {:?}", other),
+        }
+        assert!(env::var("LOADER_SECRET").is_err());
+
+        // A secret from the environment wins over the file, so it's fine
+        env::set_var("LOADER_SECRET", "from env");
+        loader.load(&[&path], "env").unwrap();
+        assert_eq!(env::var("LOADER_SECRET").unwrap(), "from env");
+
+        // Unless it's blank and blank values are unset
+        env::set_var("LOADER_SECRET", " ");
+        assert!(matches!(
+            loader.clone().treat_blank_as_unset(true).load(&[&path], "env"),
+            Err(EnvInventoryError::SecretInConfigFile { .. })
+        ));
+        env::remove_var("LOADER_SECRET");
+
+        // It also wins when set in another case and the lookup ignores case
+        env::set_var("loader_secret", "from env");
+        crate::set_case_insensitive(true);
+        let result = loader.clone().mutate_env(false).load(&[&path], "env");
+        crate::set_case_insensitive(false);
+        env::remove_var("loader_secret");
+        assert_eq!(result.unwrap()["LOADER_SECRET"], "from env");
+    }
+
+    #[test]
+    fn test_warn_secrets_in_files() {
+        use std::sync::{Arc, Mutex};
+
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let reported = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&reported);
+        crate::set_diagnostic_hook(move |diagnostic| {
+            sink.lock().unwrap().push(diagnostic.clone());
+        });
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        env::remove_var("LOADER_SECRET");
+        env::remove_var("LOADER_PUBLIC_TOKEN");
+        fs::write(
+            &path,
+            "[env]\nLOADER_SECRET = \"plaintext\"\nLOADER_PUBLIC_TOKEN = \"plaintext\"\n",
+        )
+        .unwrap();
+
+        // The load still succeeds
+        let loaded = LoaderConfig::new()
+            .mutate_env(false)
+            .load(&[&path], "env")
+            .unwrap();
+        assert_eq!(loaded["LOADER_SECRET"], "plaintext");
+        assert_eq!(
+            *reported.lock().unwrap(),
+            vec![Diagnostic::SecretInConfigFile {
+                name: "LOADER_SECRET".to_string(),
+                path: path.display().to_string(),
+            }]
+        );
+
+        // A secret from the environment isn't read from the file
+        reported.lock().unwrap().clear();
+        env::set_var("LOADER_SECRET", "from env");
+        LoaderConfig::new()
+            .mutate_env(false)
+            .load(&[&path], "env")
+            .unwrap();
+        assert!(reported.lock().unwrap().is_empty());
+        env::remove_var("LOADER_SECRET");
+    }
+
+    #[test]
+    fn test_layered_loader() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let toml = dir.path().join("app.toml");
+        let dotenv = dir.path().join("override.env");
+        fs::write(
+            &toml,
+            "[env]\nLOADER_LIMITED_VAR = \"from toml\"\nLOADER_SNIFFED_VAR = \"from toml\"\n",
+        )
+        .unwrap();
+        fs::write(&dotenv, "LOADER_SNIFFED_VAR=from dotenv\n").unwrap();
+        env::set_var("LOADER_LIMITED_VAR", "from env");
+        env::remove_var("LOADER_SNIFFED_VAR");
+
+        // Later sources win, whatever their kind
+        let loaded = Loader::new()
+            .section("env")
+            .add_toml(&toml)
+            .add_env()
+            .add_dotenv(&dotenv)
+            .load()
+            .unwrap();
+        assert_eq!(loaded["LOADER_LIMITED_VAR"], "from env");
+        assert_eq!(loaded["LOADER_SNIFFED_VAR"], "from dotenv");
+
+        let loaded = Loader::new()
+            .section("env")
+            .add_env()
+            .add_toml(&toml)
+            .load()
+            .unwrap();
+        assert_eq!(loaded["LOADER_LIMITED_VAR"], "from toml");
+        assert_eq!(loaded["LOADER_SNIFFED_VAR"], "from toml");
+
+        // Without a file, defaults apply; nothing was set in the environment
+        let loaded = Loader::new().add_env().load().unwrap();
+        assert_eq!(loaded["LOADER_SNIFFED_VAR"], "default");
+        assert!(env::var("LOADER_SNIFFED_VAR").is_err());
+        assert_eq!(env::var("LOADER_LIMITED_VAR").unwrap(), "from env");
+
+        // The environment only counts where it's added
+        assert!(matches!(
+            Loader::new().add_toml(&toml).load(),
+            Err(EnvInventoryError::MissingEnvVars(_))
+        ));
+        assert!(matches!(
+            Loader::new()
+                .add_env()
+                .add_toml(dir.path().join("missing.toml"))
+                .load(),
+            Err(EnvInventoryError::ReadFileError(_))
+        ));
+        env::remove_var("LOADER_LIMITED_VAR");
+    }
+
+    #[test]
+    fn test_layered_loader_checks() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        fs::write(&path, "[env]\nLOADER_SECRET = \"plaintext\"\n").unwrap();
+        env::set_var("LOADER_SECRET", "from env");
+
+        let loader = Loader::new().section("env").max_file_bytes(8).add_env();
+        assert!(matches!(
+            loader.add_toml(&path).load(),
+            Err(EnvInventoryError::FileTooLarge { .. })
+        ));
+
+        // Forbidden where the file wins, fine where the environment does
+        let loader = Loader::new().section("env").forbid_secrets_in_files(true);
+        match loader.clone().add_env().add_toml(&path).load() {
+            Err(EnvInventoryError::SecretInConfigFile { name, path: found }) => {
+                assert_eq!(name, "LOADER_SECRET");
+                assert_eq!(found, path.display().to_string());
+            }
+            other => panic!("expected a secret in a config file, got {:?}", other),
+        }
+        let loaded = loader.add_toml(&path).add_env().load().unwrap();
+        assert_eq!(loaded["LOADER_SECRET"], "from env");
+        env::remove_var("LOADER_SECRET");
+    }
+}
@@ -0,0 +1,223 @@
+//! Rendering of missing variables in `MissingEnvVars` errors.
+
+use crate::RequiredVar;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+type Formatter = Arc<dyn Fn(&RequiredVar) -> String + Send + Sync>;
+
+static FORMATTER: RwLock<Option<Formatter>> = RwLock::new(None);
+
+/// Installs a function rendering each missing variable in
+/// `EnvInventoryError::MissingEnvVars`, replacing any previous formatter.
+///
+/// Use this to add links to documentation or remediation hints. The entries
+/// are sorted after formatting. The formatter may itself install another
+/// formatter.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{default_missing_format, set_missing_formatter};
+/// set_missing_formatter(|var| {
+///     format!("{} (see https://docs.example.com/config#{})", default_missing_format(var), var.name())
+/// });
+/// ```
+pub fn set_missing_formatter<F>(formatter: F)
+where
+    F: Fn(&RequiredVar) -> String + Send + Sync + 'static,
+{
+    let mut current = FORMATTER.write().unwrap_or_else(|e| e.into_inner());
+    *current = Some(Arc::new(formatter));
+}
+
+/// The default rendering of a missing variable: `NAME=(missing)`, or
+/// `NAME=message` with its custom `error` message, as chosen by
+/// [`error_message_for`].
+///
+/// Install it with [`set_missing_formatter`] to restore the default.
+pub fn default_missing_format(var: &RequiredVar) -> String {
+    let error = error_message_for(var.name).or(var.error);
+    format!("{}={}", var.name, error.unwrap_or("(missing)"))
+}
+
+/// Gets the custom `error` message shown when `name` is missing.
+///
+/// When the variable is registered several times with different messages,
+/// the message registered with the highest [`Priority`](crate::Priority) wins, and among
+/// those the most significant registration, as for defaults.
+///
+/// Returns `None` if `name` isn't registered with an `error` message.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{error_message_for, register};
+/// register!(SMTP_HOST; error = "set SMTP_HOST to the mail relay");
+/// register!(SMTP_HOST; error = "see the deployment guide"; Binary);
+///
+/// assert_eq!(error_message_for("SMTP_HOST"), Some("see the deployment guide"));
+/// # std::env::set_var("SMTP_HOST", "localhost");
+/// ```
+pub fn error_message_for(name: &str) -> Option<&'static str> {
+    inventory::iter::<RequiredVar>()
+        .filter(|var| var.name == name && var.error.is_some())
+        .enumerate()
+        .max_by_key(|(index, var)| (var.priority, *index))
+        .and_then(|(_, var)| var.error)
+}
+
+/// A variable reported by `EnvInventoryError::MissingEnvVars`.
+///
+/// Its `Display` is the rendering of the installed formatter, by default
+/// `NAME=(missing)` or `NAME=message`, as shown in the error message.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MissingVar {
+    /// The name of the variable.
+    pub name: String,
+    /// The file the variable was registered in, or `<none>` if it isn't
+    /// registered.
+    pub source: String,
+    /// The custom `error` message registered for the variable, as chosen by
+    /// [`error_message_for`], if any.
+    pub custom_error: Option<String>,
+    pub(crate) rendered: String,
+}
+
+impl MissingVar {
+    /// Describes the registered `var` as missing.
+    pub(crate) fn new(var: &RequiredVar) -> Self {
+        Self {
+            name: var.name.to_string(),
+            source: var.source.to_string(),
+            custom_error: error_message_for(var.name)
+                .or(var.error)
+                .map(str::to_string),
+            rendered: format(var),
+        }
+    }
+
+    /// Describes `name`, which isn't registered, as missing.
+    pub(crate) fn unregistered(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            source: "<none>".to_string(),
+            custom_error: None,
+            rendered: format!("{}=(missing)", name),
+        }
+    }
+}
+
+impl fmt::Display for MissingVar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.rendered)
+    }
+}
+
+/// Renders `missing` as listed in the message of `MissingEnvVars`.
+pub(crate) fn rendered(missing: &[MissingVar]) -> Vec<&str> {
+    missing.iter().map(|var| var.rendered.as_str()).collect()
+}
+
+/// Renders `var` with the installed formatter.
+pub(crate) fn format(var: &RequiredVar) -> String {
+    // Called outside the lock, so the formatter may replace itself
+    let formatter = FORMATTER.read().unwrap_or_else(|e| e.into_inner()).clone();
+    match formatter {
+        Some(formatter) => formatter(var),
+        None => default_missing_format(var),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::missing_vars;
+
+    #[test]
+    fn test_missing_formatter() {
+        // Other tests rely on the default format, so only customize our vars
+        set_missing_formatter(|var| {
+            if var.name().starts_with("BRANDED_") {
+                format!("{}: see https://docs.example.com/{}", var.name(), var.name())
+            } else {
+                default_missing_format(var)
+            }
+        });
+
+        let vars = [
+            RequiredVar::new("BRANDED_TOKEN"),
+            RequiredVar::new("UNBRANDED_TOKEN").with_error("ask ops"),
+        ];
+        let missing = missing_vars(&vars);
+        assert_eq!(
+            rendered(&missing),
+            vec![
+                "BRANDED_TOKEN: see https://docs.example.com/BRANDED_TOKEN",
+                "UNBRANDED_TOKEN=ask ops",
+            ]
+        );
+        assert_eq!(missing[1].name, "UNBRANDED_TOKEN");
+        assert_eq!(missing[1].custom_error.as_deref(), Some("ask ops"));
+
+        // A formatter may install another one
+        set_missing_formatter(|var| {
+            if !var.name().starts_with("BRANDED_") {
+                return default_missing_format(var);
+            }
+            set_missing_formatter(default_missing_format);
+            format!("{}: replaced", var.name())
+        });
+        assert_eq!(
+            format(&RequiredVar::new("BRANDED_TOKEN")),
+            "BRANDED_TOKEN: replaced"
+        );
+        assert_eq!(
+            format(&RequiredVar::new("BRANDED_TOKEN")),
+            "BRANDED_TOKEN=(missing)"
+        );
+    }
+
+    crate::register!(ERROR_PRIORITY_VAR = "set"; error = "library message");
+    crate::register!(ERROR_PRIORITY_VAR = "set"; error = "binary message"; Binary);
+    crate::register!(ERROR_PRIORITY_VAR = "set"; error = "another library message");
+
+    #[test]
+    fn test_error_message_for() {
+        assert_eq!(
+            error_message_for("ERROR_PRIORITY_VAR"),
+            Some("binary message")
+        );
+        assert_eq!(
+            default_missing_format(&RequiredVar::new("ERROR_PRIORITY_VAR")),
+            "ERROR_PRIORITY_VAR=binary message"
+        );
+        assert_eq!(
+            error_message_for("GETTER_VAR"),
+            Some("set GETTER_VAR to the service URL")
+        );
+        assert_eq!(error_message_for("TEST_ENV_VAR"), None);
+        assert_eq!(error_message_for("NOT_REGISTERED_VAR"), None);
+    }
+
+    crate::register!(ERROR_TIE_VAR = "first"; error = "first message");
+    crate::register!(ERROR_TIE_VAR = "second"; error = "second message");
+
+    #[test]
+    fn test_error_message_for_same_priority() {
+        // Then source order: the registration whose default wins
+        let default =
+            crate::registered_default("ERROR_TIE_VAR", crate::Priority::Library, |_| None);
+        let expected = match default {
+            Some("first") => "first message",
+            Some("second") => "second message",
+            other => panic!("unexpected default {:?}", other),
+        };
+        assert_eq!(error_message_for("ERROR_TIE_VAR"), Some(expected));
+        let last = inventory::iter::<RequiredVar>()
+            .filter(|var| var.name == "ERROR_TIE_VAR")
+            .last()
+            .unwrap();
+        assert_eq!(error_message_for("ERROR_TIE_VAR"), last.error);
+    }
+}
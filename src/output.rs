@@ -0,0 +1,370 @@
+//! Rendering of the effective config for logs and files.
+
+use crate::expand::expand;
+use crate::{registered_default, sorted_unredacted_values, Priority, RequiredVar};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::env;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// The format written by [`write_effective_config`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// A TOML table of `NAME = "value"` pairs.
+    Toml,
+    /// A JSON object mapping names to values.
+    Json,
+    /// `.env` lines of `NAME="value"`, with `\`, `"`, `$` and newlines
+    /// escaped.
+    Env,
+}
+
+/// The placeholder written for the values of `secret` variables.
+const REDACTED: &str = "<redacted>";
+
+/// Writes the resolved value of every registered variable to `w` in the
+/// given format, sorted by name.
+///
+/// Values of variables registered with the `secret` option are replaced by
+/// `<redacted>`, so the output is safe to log. Variables without a value are
+/// omitted.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, write_effective_config, OutputFormat};
+/// register!(LOG_FORMAT = "json");
+///
+/// let mut out = Vec::new();
+/// write_effective_config(&mut out, OutputFormat::Env).unwrap();
+/// assert!(String::from_utf8(out).unwrap().contains("LOG_FORMAT=\"json\"\n"));
+/// ```
+///
+/// # Errors
+///
+/// Returns any error from writing to `w`.
+pub fn write_effective_config<W: Write>(w: &mut W, format: OutputFormat) -> io::Result<()> {
+    let mut secrets: BTreeMap<&'static str, bool> = BTreeMap::new();
+    for var in inventory::iter::<RequiredVar>() {
+        *secrets.entry(var.name).or_default() |= var.is_redacted();
+    }
+
+    if format == OutputFormat::Json {
+        write!(w, "{{")?;
+    }
+    let mut written = 0;
+    for (name, secret) in secrets {
+        // Each value is resolved as it is written, as for `effective_map`
+        let value = match inventory::iter::<RequiredVar>()
+            .filter(|var| var.name == name)
+            .find_map(RequiredVar::value)
+        {
+            Some(_) if secret => REDACTED.to_string(),
+            Some(value) => value,
+            None => continue,
+        };
+        match format {
+            OutputFormat::Toml => writeln!(w, "{} = {}", name, toml::Value::String(value))?,
+            OutputFormat::Json => {
+                let separator = if written == 0 { "" } else { "," };
+                write!(w, "{}\n  ", separator)?;
+                write_json_string(w, name)?;
+                write!(w, ": ")?;
+                write_json_string(w, &value)?;
+            }
+            OutputFormat::Env => {
+                write!(w, "{}=", name)?;
+                write_env_value(w, &value)?;
+                writeln!(w)?;
+            }
+        }
+        written += 1;
+    }
+    if format == OutputFormat::Json {
+        let closing = if written == 0 { "" } else { "\n" };
+        writeln!(w, "{}}}", closing)?;
+    }
+    Ok(())
+}
+
+/// Renders the variables set to something other than their default as a TOML
+/// document of `NAME = "value"` pairs, sorted by name.
+///
+/// A variable is included if the environment, where loaded config files put
+/// their values, holds a value other than the one it would have without it:
+/// its platform value, its derived value or its registered default, before
+/// or after expansion. Loading the output reproduces the current config, so
+/// it can be committed as a record of what the operator changed. `secret`
+/// variables are left out; supply them from the environment.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{export_overrides_toml, register};
+/// register!(WORKER_COUNT = "4", QUEUE_NAME = "jobs");
+///
+/// std::env::set_var("WORKER_COUNT", "16");
+/// let toml = export_overrides_toml();
+/// assert!(toml.contains("WORKER_COUNT = \"16\"\n"));
+/// assert!(!toml.contains("QUEUE_NAME"));
+/// ```
+pub fn export_overrides_toml() -> String {
+    let mut names = BTreeSet::new();
+    let mut secrets = HashSet::new();
+    for var in inventory::iter::<RequiredVar>() {
+        names.insert(var.name);
+        if var.is_redacted() {
+            secrets.insert(var.name);
+        }
+    }
+
+    let mut out = String::new();
+    for name in names {
+        if secrets.contains(name) {
+            continue;
+        }
+        let value = match env::var(name) {
+            Ok(value) => value,
+            Err(_) => continue,
+        };
+        let is_baseline = match baseline_value(name) {
+            Some(baseline) => {
+                value == baseline || expand(&baseline).map_or(false, |expanded| value == expanded)
+            }
+            None => false,
+        };
+        if !is_baseline {
+            let _ = writeln!(out, "{} = {}", name, toml::Value::String(value));
+        }
+    }
+    out
+}
+
+/// Renders the resolved value of every registered variable, expanded, as a
+/// shell script of `export NAME="value"` lines, sorted by name.
+///
+/// Sourcing the script reproduces the fully resolved config, after config
+/// files are merged and references expanded, in a subprocess or a debugging
+/// shell. Values are double-quoted with `"`, `\`, `$` and `` ` `` escaped, so
+/// the shell takes them literally; newlines are kept as they are, which
+/// POSIX shells accept inside double quotes. A value whose expansion fails is
+/// exported unexpanded.
+///
+/// Unlike the other renderings, the script includes the values of `secret`
+/// variables, since it is meant to be executed: don't log it or write it
+/// where others can read it.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{export_shell_script, register};
+/// register!(GREETING = "say \"hi\"");
+///
+/// let script = export_shell_script();
+/// assert!(script.contains("export GREETING=\"say \\\"hi\\\"\"\n"));
+/// ```
+pub fn export_shell_script() -> String {
+    let mut out = String::new();
+    for (name, value) in sorted_unredacted_values() {
+        let value = expand(&value).unwrap_or(value);
+        let _ = writeln!(out, "export {}={}", name, shell_quote(&value));
+    }
+    out
+}
+
+/// Double-quotes `value` for POSIX shells.
+fn shell_quote(value: &str) -> String {
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Gets the value `name` would have without the environment.
+fn baseline_value(name: &str) -> Option<String> {
+    let lookup = |name: &str| env::var(name).ok();
+    let vars = || inventory::iter::<RequiredVar>().filter(move |var| var.name == name);
+    vars()
+        .find_map(RequiredVar::platform_value)
+        .or_else(|| vars().find_map(RequiredVar::derived_value))
+        .or_else(|| {
+            registered_default(name, Priority::Binary, lookup)
+                .or_else(|| registered_default(name, Priority::Library, lookup))
+                .map(str::to_string)
+        })
+}
+
+/// Returns the resolved values sorted by name, with `None` in place of the
+/// values of variables at or above the
+/// [`redaction_level`](crate::redaction_level).
+#[cfg(feature = "test-util")]
+pub(crate) fn redacted_values() -> Vec<(String, Option<String>)> {
+    let secrets: HashSet<&str> = inventory::iter::<RequiredVar>()
+        .filter(|var| var.is_redacted())
+        .map(|var| var.name)
+        .collect();
+
+    sorted_unredacted_values()
+        .into_iter()
+        .map(|(name, value)| {
+            if secrets.contains(name.as_str()) {
+                (name, None)
+            } else {
+                (name, Some(value))
+            }
+        })
+        .collect()
+}
+
+#[cfg(feature = "json")]
+fn write_json_string<W: Write>(w: &mut W, value: &str) -> io::Result<()> {
+    serde_json::to_writer(w, value).map_err(io::Error::from)
+}
+
+#[cfg(not(feature = "json"))]
+fn write_json_string<W: Write>(w: &mut W, value: &str) -> io::Result<()> {
+    write!(w, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' => write!(w, "\\\"")?,
+            '\\' => write!(w, "\\\\")?,
+            '\n' => write!(w, "\\n")?,
+            '\r' => write!(w, "\\r")?,
+            '\t' => write!(w, "\\t")?,
+            c if (c as u32) < 0x20 => write!(w, "\\u{:04x}", c as u32)?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    write!(w, "\"")
+}
+
+fn write_env_value<W: Write>(w: &mut W, value: &str) -> io::Result<()> {
+    write!(w, "\"")?;
+    for c in value.chars() {
+        match c {
+            '"' | '\\' | '$' => write!(w, "\\{}", c)?,
+            '\n' => write!(w, "\\n")?,
+            c => write!(w, "{}", c)?,
+        }
+    }
+    write!(w, "\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    crate::register!(OUTPUT_QUOTED = "say \"hi\"");
+    crate::register!(OUTPUT_SECRET = "hunter2"; secret);
+    crate::register!(OUTPUT_DEFAULTED = "unchanged");
+
+    fn render(format: OutputFormat) -> String {
+        let mut out = Vec::new();
+        write_effective_config(&mut out, format).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_write_effective_config() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        env::remove_var("OUTPUT_QUOTED");
+        env::remove_var("OUTPUT_SECRET");
+
+        let toml = render(OutputFormat::Toml);
+        assert!(toml.contains("OUTPUT_QUOTED = 'say \"hi\"'\n"));
+        assert!(toml.contains("OUTPUT_SECRET = \"<redacted>\"\n"));
+        let parsed: toml::Value = toml.parse().unwrap();
+        assert_eq!(parsed["OUTPUT_QUOTED"].as_str(), Some("say \"hi\""));
+
+        let json = render(OutputFormat::Json);
+        assert!(json.starts_with("{\n  \""));
+        assert!(json.ends_with("\n}\n"));
+        assert!(json.contains("  \"OUTPUT_QUOTED\": \"say \\\"hi\\\"\""));
+        assert!(json.contains("  \"OUTPUT_SECRET\": \"<redacted>\""));
+        #[cfg(feature = "json")]
+        {
+            let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed["OUTPUT_QUOTED"], "say \"hi\"");
+        }
+
+        let dotenv = render(OutputFormat::Env);
+        assert!(dotenv.contains("OUTPUT_QUOTED=\"say \\\"hi\\\"\"\n"));
+        assert!(dotenv.contains("OUTPUT_SECRET=\"<redacted>\"\n"));
+
+        for output in [toml, json, dotenv] {
+            assert!(!output.contains("hunter2"));
+        }
+    }
+
+    #[test]
+    fn test_export_overrides_toml() {
+        let _guard = crate::env_lock();
+        env::set_var("OUTPUT_QUOTED", "say \"bye\"");
+        env::set_var("OUTPUT_SECRET", "hunter3");
+        env::set_var("OUTPUT_DEFAULTED", "unchanged");
+
+        let toml = export_overrides_toml();
+        assert!(toml.contains("OUTPUT_QUOTED = 'say \"bye\"'\n"));
+        assert!(!toml.contains("OUTPUT_DEFAULTED"));
+        assert!(!toml.contains("OUTPUT_SECRET"));
+        assert!(!toml.contains("hunter3"));
+        let parsed: toml::Value = toml.parse().unwrap();
+        assert_eq!(parsed["OUTPUT_QUOTED"].as_str(), Some("say \"bye\""));
+
+        env::remove_var("OUTPUT_QUOTED");
+        env::remove_var("OUTPUT_SECRET");
+        env::remove_var("OUTPUT_DEFAULTED");
+        assert!(!export_overrides_toml().contains("OUTPUT_QUOTED"));
+    }
+
+    #[test]
+    fn test_export_shell_script() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        env::set_var("OUTPUT_QUOTED", "say \"hi\"\nand `bye` for $5 \\o/");
+        env::set_var("OUTPUT_SECRET", "hunter2");
+
+        let script = export_shell_script();
+        assert!(script
+            .contains("export OUTPUT_QUOTED=\"say \\\"hi\\\"\nand \\`bye\\` for \\$5 \\\\o/\"\n"));
+        // Secrets are included, as the script is meant to be executed
+        assert!(script.contains("export OUTPUT_SECRET=\"hunter2\"\n"));
+        assert!(script.contains("export OUTPUT_DEFAULTED=\"unchanged\"\n"));
+
+        // The shell reads back the exact values
+        #[cfg(unix)]
+        {
+            let output = std::process::Command::new("sh")
+                .arg("-c")
+                .arg(format!("{}printf %s \"$OUTPUT_QUOTED\"", script))
+                .output()
+                .unwrap();
+            assert_eq!(
+                String::from_utf8(output.stdout).unwrap(),
+                "say \"hi\"\nand `bye` for $5 \\o/"
+            );
+        }
+
+        env::remove_var("OUTPUT_QUOTED");
+        env::remove_var("OUTPUT_SECRET");
+    }
+
+    #[test]
+    fn test_escaping() {
+        let mut out = Vec::new();
+        write_json_string(&mut out, "a\"b\\c\nd\u{1}").unwrap();
+        assert_eq!(out, b"\"a\\\"b\\\\c\\nd\\u0001\"");
+
+        let mut out = Vec::new();
+        write_env_value(&mut out, "$HOME \"x\"\n").unwrap();
+        assert_eq!(out, b"\"\\$HOME \\\"x\\\"\\n\"");
+    }
+}
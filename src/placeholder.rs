@@ -0,0 +1,110 @@
+//! Values left over from templates, which pass presence checks but were
+//! never filled in.
+
+use std::sync::Mutex;
+
+/// The placeholders rejected by the `reject_placeholders` option of
+/// [`register!`](crate::register), compared ignoring case.
+const PLACEHOLDERS: &[&str] = &[
+    "changeme",
+    "change-me",
+    "change_me",
+    "todo",
+    "tbd",
+    "fixme",
+    "xxx",
+    "placeholder",
+    "set-me",
+    "<set-me>",
+    "replace-me",
+    "<replace-me>",
+];
+
+static EXTRA_PLACEHOLDERS: Mutex<Option<Vec<String>>> = Mutex::new(None);
+
+/// Adds `value` to the placeholders rejected by the `reject_placeholders`
+/// option of [`register!`](crate::register), on top of the built-in ones
+/// such as `changeme`, `TODO` and `<set-me>`.
+///
+/// Placeholders are compared ignoring case and surrounding whitespace.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, register_placeholder, validate_env_vars};
+/// register!(API_KEY = "dev-key"; reject_placeholders);
+///
+/// register_placeholder("your-api-key-here");
+/// std::env::set_var("API_KEY", "YOUR-API-KEY-HERE");
+/// assert!(validate_env_vars().is_err());
+/// ```
+pub fn register_placeholder(value: &str) {
+    let mut extra = EXTRA_PLACEHOLDERS.lock().unwrap_or_else(|e| e.into_inner());
+    extra
+        .get_or_insert_with(Vec::new)
+        .push(value.trim().to_string());
+}
+
+/// Checks if `value` is a placeholder, built-in or registered.
+pub(crate) fn is_placeholder(value: &str) -> bool {
+    let value = value.trim();
+    if PLACEHOLDERS
+        .iter()
+        .any(|placeholder| placeholder.eq_ignore_ascii_case(value))
+    {
+        return true;
+    }
+    let extra = EXTRA_PLACEHOLDERS.lock().unwrap_or_else(|e| e.into_inner());
+    extra
+        .iter()
+        .flatten()
+        .any(|placeholder| placeholder.eq_ignore_ascii_case(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{check_value, env_lock, EnvInventoryError, RequiredVar};
+    use std::env;
+
+    crate::register!(PLACEHOLDER_API_KEY = "dev-key"; reject_placeholders);
+
+    #[test]
+    fn test_is_placeholder() {
+        assert!(is_placeholder("changeme"));
+        assert!(is_placeholder("  TODO "));
+        assert!(is_placeholder("<SET-ME>"));
+        assert!(!is_placeholder("changeme-later"));
+        assert!(!is_placeholder(""));
+
+        assert!(!is_placeholder("fill-in"));
+        register_placeholder("Fill-In");
+        assert!(is_placeholder("fill-in"));
+    }
+
+    #[test]
+    fn test_reject_placeholders() {
+        let _guard = env_lock();
+        let var = inventory::iter::<RequiredVar>()
+            .find(|var| var.name == "PLACEHOLDER_API_KEY")
+            .unwrap();
+        assert!(var.rejects_placeholders());
+
+        env::set_var("PLACEHOLDER_API_KEY", "ChangeMe");
+        match check_value(var) {
+            Err(EnvInventoryError::PlaceholderValue { name, value }) => {
+                assert_eq!(name, "PLACEHOLDER_API_KEY");
+                assert_eq!(value, "ChangeMe");
+            }
+            other => panic!("expected a placeholder, got {:?}", other),
+        }
+
+        env::set_var("PLACEHOLDER_API_KEY", "sk-live-4f2a");
+        assert!(check_value(var).is_ok());
+
+        // Other variables take placeholders as they are
+        let plain = RequiredVar::new("PLACEHOLDER_PLAIN").with_default("TODO");
+        assert!(check_value(&plain).is_ok());
+        env::remove_var("PLACEHOLDER_API_KEY");
+    }
+}
@@ -0,0 +1,137 @@
+//! OS-native sources of values for variables that are otherwise unset.
+//!
+//! Some values live outside the environment in a place specific to the
+//! platform: the registry on Windows, or well-known files on Unix. Variables
+//! registered with the `platform` option consult the installed
+//! [`PlatformSource`] when they aren't set in the environment or the config
+//! files, before falling back to their defaults.
+
+use std::sync::{Arc, RwLock};
+
+/// A source of values native to the operating system.
+///
+/// Implement this to pull values from the Windows registry, `/etc` files, a
+/// keychain, or any other backend, and install it with
+/// [`set_platform_source`].
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, set_platform_source, PlatformSource};
+/// struct HostnameFile;
+///
+/// impl PlatformSource for HostnameFile {
+///     fn lookup(&self, name: &str) -> Option<String> {
+///         match name {
+///             "HOSTNAME" => std::fs::read_to_string("/etc/hostname")
+///                 .ok()
+///                 .map(|s| s.trim().to_string()),
+///             _ => None,
+///         }
+///     }
+/// }
+///
+/// register!(HOSTNAME; platform);
+/// set_platform_source(HostnameFile);
+/// ```
+pub trait PlatformSource: Send + Sync {
+    /// Looks up the value of `name`, returning `None` if the source doesn't
+    /// provide one.
+    fn lookup(&self, name: &str) -> Option<String>;
+}
+
+/// The default platform source, which never provides a value.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoPlatformSource;
+
+impl PlatformSource for NoPlatformSource {
+    fn lookup(&self, _name: &str) -> Option<String> {
+        None
+    }
+}
+
+static PLATFORM_SOURCE: RwLock<Option<Arc<dyn PlatformSource>>> = RwLock::new(None);
+
+/// Installs the platform source consulted by variables registered with the
+/// `platform` option, replacing any previously installed source.
+///
+/// Install [`NoPlatformSource`] to go back to the default behavior. A source
+/// may itself install another source.
+pub fn set_platform_source<S: PlatformSource + 'static>(source: S) {
+    let mut current = PLATFORM_SOURCE.write().unwrap_or_else(|e| e.into_inner());
+    *current = Some(Arc::new(source));
+}
+
+/// Looks up `name` in the installed platform source.
+pub(crate) fn lookup(name: &str) -> Option<String> {
+    // Called outside the lock, so the source may replace itself
+    let source = PLATFORM_SOURCE
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone();
+    source.and_then(|source| source.lookup(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RequiredVar;
+    use std::env;
+
+    crate::register!(PLATFORM_TOKEN = "fallback"; platform);
+    crate::register!(PLATFORM_IGNORED = "fallback");
+
+    struct MockSource;
+
+    impl PlatformSource for MockSource {
+        fn lookup(&self, name: &str) -> Option<String> {
+            name.starts_with("PLATFORM_").then(|| "from-platform".to_string())
+        }
+    }
+
+    /// Hands over to [`NoPlatformSource`] after its first lookup.
+    struct OneShotSource;
+
+    impl PlatformSource for OneShotSource {
+        fn lookup(&self, _name: &str) -> Option<String> {
+            set_platform_source(NoPlatformSource);
+            Some("once".to_string())
+        }
+    }
+
+    fn registered(name: &str) -> &'static RequiredVar {
+        inventory::iter::<RequiredVar>()
+            .find(|var| var.name == name)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_platform_source() {
+        let _guard = crate::env_lock();
+        let token = registered("PLATFORM_TOKEN");
+        let ignored = registered("PLATFORM_IGNORED");
+        env::remove_var("PLATFORM_TOKEN");
+        env::remove_var("PLATFORM_IGNORED");
+
+        assert!(token.platform);
+        assert_eq!(token.get().as_deref(), Some("fallback"));
+
+        set_platform_source(MockSource);
+        assert_eq!(token.get().as_deref(), Some("from-platform"));
+        // Only variables registered with `platform` consult the source
+        assert_eq!(ignored.get().as_deref(), Some("fallback"));
+
+        // The environment still takes precedence
+        env::set_var("PLATFORM_TOKEN", "from-env");
+        assert_eq!(token.get().as_deref(), Some("from-env"));
+        env::remove_var("PLATFORM_TOKEN");
+
+        set_platform_source(NoPlatformSource);
+        assert_eq!(token.get().as_deref(), Some("fallback"));
+
+        // A source may replace itself
+        set_platform_source(OneShotSource);
+        assert_eq!(lookup("PLATFORM_TOKEN").as_deref(), Some("once"));
+        assert_eq!(lookup("PLATFORM_TOKEN"), None);
+    }
+}
@@ -0,0 +1,282 @@
+//! Tracking of where resolved values came from.
+//!
+//! The loaders copy config file values and defaults into the environment, so
+//! once loaded they are indistinguishable from values set by the caller. The
+//! loaders therefore record where each value they set came from.
+
+use crate::{registered_default, resolve_one, Priority, RequiredVar, VarSource};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+use std::fmt::Write;
+use std::sync::RwLock;
+
+/// Where a loader took a value from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Origin {
+    /// A config file, or another settings source such as `$APP_CONFIG_JSON`.
+    File(String),
+    /// The platform source.
+    Platform,
+    /// Computed from other variables.
+    Derived,
+    /// A registered default.
+    Default,
+}
+
+/// Maps each variable set by the last load to its origin and the value it
+/// was set to.
+static ORIGINS: RwLock<Option<HashMap<String, (Origin, String)>>> = RwLock::new(None);
+
+/// Replaces the recorded origins with `origins`, a map of variable name to
+/// where the loader took its value from. Must run after the values are set.
+pub(crate) fn record(origins: HashMap<String, Origin>) {
+    let origins = origins
+        .into_iter()
+        .filter_map(|(name, origin)| {
+            let value = env::var(&name).ok()?;
+            Some((name, (origin, value)))
+        })
+        .collect();
+    *ORIGINS.write().unwrap_or_else(|e| e.into_inner()) = Some(origins);
+}
+
+/// Maps each variable expanded by the last load to its value before
+/// expansion and the expanded value.
+static RAW_VALUES: RwLock<Option<HashMap<String, (String, String)>>> = RwLock::new(None);
+
+/// Replaces the recorded pre-expansion values with `raw`. Must run after the
+/// values are expanded.
+pub(crate) fn record_raw(raw: HashMap<String, String>) {
+    let raw = raw
+        .into_iter()
+        .filter_map(|(name, raw)| {
+            let expanded = env::var(&name).ok()?;
+            Some((name, (raw, expanded)))
+        })
+        .collect();
+    *RAW_VALUES.write().unwrap_or_else(|e| e.into_inner()) = Some(raw);
+}
+
+/// The value of `name` before the last load expanded it, if the current value
+/// is still the expansion.
+pub(crate) fn raw_value(name: &str) -> Option<String> {
+    let raw_values = RAW_VALUES.read().unwrap_or_else(|e| e.into_inner());
+    let (raw, expanded) = raw_values.as_ref()?.get(name)?;
+    match env::var(name) {
+        Ok(current) if current == *expanded => Some(raw.clone()),
+        _ => None,
+    }
+}
+
+/// Where the loader took the current value of `name` from, if it set it.
+///
+/// A value changed in the environment since the load is not attributed to
+/// the loader.
+pub(crate) fn origin(name: &str) -> Option<Origin> {
+    let origins = ORIGINS.read().unwrap_or_else(|e| e.into_inner());
+    let (origin, value) = origins.as_ref()?.get(name)?;
+    match env::var(name) {
+        Ok(current) if current == *value => Some(origin.clone()),
+        _ => None,
+    }
+}
+
+/// Returns the effective value of every registered variable that has one,
+/// and where it came from, keyed by name.
+///
+/// Loaders copy the values they resolve into the environment, and record
+/// where each came from; a value set by a loader is attributed to its
+/// original source as long as it is unchanged, and to
+/// [`VarSource::Environment`] once overridden.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, resolved_with_source, VarSource};
+/// register!(QUEUE_NAME = "jobs");
+///
+/// let resolved = resolved_with_source();
+/// assert_eq!(
+///     resolved["QUEUE_NAME"],
+///     ("jobs".to_string(), VarSource::LibraryDefault)
+/// );
+/// ```
+pub fn resolved_with_source() -> HashMap<String, (String, VarSource)> {
+    let names: HashSet<&'static str> = inventory::iter::<RequiredVar>()
+        .map(|var| var.name)
+        .collect();
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let (value, source) = resolve_one(name)?;
+            let source = match source {
+                VarSource::Environment => match origin(name) {
+                    Some(Origin::File(_)) => VarSource::ConfigFile,
+                    Some(Origin::Platform) => VarSource::Platform,
+                    Some(Origin::Derived) => VarSource::Derived,
+                    Some(Origin::Default) => default_source(name),
+                    None => VarSource::Environment,
+                },
+                source => source,
+            };
+            Some((name.to_string(), (value, source)))
+        })
+        .collect()
+}
+
+/// Tells which default a loader set `name` from.
+fn default_source(name: &str) -> VarSource {
+    let lookup = |name: &str| env::var(name).ok();
+    if registered_default(name, Priority::Binary, lookup).is_some() {
+        VarSource::BinaryDefault
+    } else {
+        VarSource::LibraryDefault
+    }
+}
+
+/// Renders the resolved value of every registered variable as a TOML
+/// document, with a comment above each value noting where it came from.
+///
+/// Values are attributed to `env`, the config file they were loaded from,
+/// the `platform` source, a derivation, or a `default`. The values of variables registered
+/// with the `secret` option are replaced by `"<redacted>"`. Variables without
+/// a value are omitted. The output is sorted by name, so it can be committed
+/// or diffed as a record of exactly what the process runs with.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{dump_effective_config_toml, register};
+/// register!(LISTEN_ADDR = "0.0.0.0:8080");
+///
+/// let toml = dump_effective_config_toml();
+/// assert!(toml.contains("# default\nLISTEN_ADDR = \"0.0.0.0:8080\"\n"));
+/// ```
+pub fn dump_effective_config_toml() -> String {
+    let mut vars: BTreeMap<&'static str, bool> = BTreeMap::new();
+    for var in inventory::iter::<RequiredVar>() {
+        *vars.entry(var.name).or_default() |= var.is_redacted();
+    }
+
+    let mut out = String::new();
+    for (name, secret) in vars {
+        let (value, source) = match resolve_one(name) {
+            Some(resolved) => resolved,
+            None => continue,
+        };
+        let origin = match source {
+            VarSource::Environment | VarSource::ConfigFile => origin(name),
+            VarSource::Platform => Some(Origin::Platform),
+            VarSource::Derived => Some(Origin::Derived),
+            VarSource::BinaryDefault | VarSource::LibraryDefault => Some(Origin::Default),
+        };
+        let origin = match origin {
+            Some(Origin::File(path)) => format!("from {}", path),
+            Some(Origin::Platform) => "from platform".to_string(),
+            Some(Origin::Derived) => "derived".to_string(),
+            Some(Origin::Default) => "default".to_string(),
+            None => "from env".to_string(),
+        };
+        let value = if secret { "<redacted>".to_string() } else { value };
+
+        if secret {
+            let _ = writeln!(out, "# {} (redacted)", origin);
+        } else {
+            let _ = writeln!(out, "# {}", origin);
+        }
+        let _ = writeln!(out, "{} = {}", name, toml::Value::String(value));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env_lock, load_and_validate_env_vars, satisfy_required_vars};
+    use std::fs;
+    use tempfile::tempdir;
+
+    crate::register!(PROVENANCE_FILE_VAR = "default");
+    crate::register!(PROVENANCE_ENV_VAR = "default");
+    crate::register!(PROVENANCE_DEFAULT_VAR = "fallback");
+    crate::register!(PROVENANCE_SECRET = "hunter2"; secret);
+    crate::register!(PROVENANCE_BINARY_VAR = "library");
+    crate::register!(PROVENANCE_BINARY_VAR = "binary"; Binary);
+
+    #[test]
+    fn test_dump_effective_config_toml() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("app.toml");
+        for name in [
+            "PROVENANCE_FILE_VAR",
+            "PROVENANCE_DEFAULT_VAR",
+            "PROVENANCE_SECRET",
+            "PROVENANCE_BINARY_VAR",
+        ] {
+            env::remove_var(name);
+        }
+        env::set_var("PROVENANCE_ENV_VAR", "from the shell");
+        fs::write(
+            &path,
+            "[env]\nPROVENANCE_FILE_VAR = \"from \\\"file\\\"\"\nPROVENANCE_SECRET = \"s3cr3t\"\n",
+        )
+        .unwrap();
+        load_and_validate_env_vars(&[&path], "env").unwrap();
+
+        let dump = dump_effective_config_toml();
+        let parsed: toml::Value = dump.parse().unwrap();
+        assert_eq!(parsed["PROVENANCE_FILE_VAR"].as_str(), Some("from \"file\""));
+        assert_eq!(parsed["PROVENANCE_SECRET"].as_str(), Some("<redacted>"));
+        assert!(!dump.contains("s3cr3t"));
+
+        let from_file = format!("# from {}\nPROVENANCE_FILE_VAR = ", path.display());
+        assert!(dump.contains(&from_file));
+        assert!(dump.contains("# from env\nPROVENANCE_ENV_VAR = \"from the shell\"\n"));
+        assert!(dump.contains("# default\nPROVENANCE_DEFAULT_VAR = \"fallback\"\n"));
+        let secret_from_file = format!("# from {} (redacted)\nPROVENANCE_SECRET = ", path.display());
+        assert!(dump.contains(&secret_from_file));
+
+        let resolved = resolved_with_source();
+        assert_eq!(
+            resolved["PROVENANCE_FILE_VAR"],
+            ("from \"file\"".to_string(), VarSource::ConfigFile)
+        );
+        assert_eq!(
+            resolved["PROVENANCE_ENV_VAR"],
+            ("from the shell".to_string(), VarSource::Environment)
+        );
+        assert_eq!(
+            resolved["PROVENANCE_DEFAULT_VAR"],
+            ("fallback".to_string(), VarSource::LibraryDefault)
+        );
+        assert_eq!(
+            resolved["PROVENANCE_BINARY_VAR"],
+            ("binary".to_string(), VarSource::BinaryDefault)
+        );
+        assert_eq!(
+            crate::effective_map()["PROVENANCE_FILE_VAR"],
+            "from \"file\""
+        );
+
+        // Overriding a loaded value moves its attribution to the environment
+        env::set_var("PROVENANCE_FILE_VAR", "overridden");
+        assert!(dump_effective_config_toml()
+            .contains("# from env\nPROVENANCE_FILE_VAR = \"overridden\"\n"));
+        assert_eq!(
+            resolved_with_source()["PROVENANCE_FILE_VAR"].1,
+            VarSource::Environment
+        );
+
+        for name in [
+            "PROVENANCE_FILE_VAR",
+            "PROVENANCE_ENV_VAR",
+            "PROVENANCE_DEFAULT_VAR",
+            "PROVENANCE_SECRET",
+            "PROVENANCE_BINARY_VAR",
+        ] {
+            env::remove_var(name);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! A summary of the registered variables against the actual environment,
+//! for diagnostics.
+
+use crate::audit::is_similar;
+use crate::{case, RequiredVar};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+
+/// How the registered variables compare with the environment, as returned by
+/// [`env_report`]. Each list is sorted by name.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvReport {
+    /// Registered variables with a value that isn't a default: set in the
+    /// environment, by the platform source, or derived.
+    pub set: Vec<String>,
+    /// Registered variables without a value, other than `optional` ones.
+    pub missing: Vec<String>,
+    /// Variables set in the environment but not registered, whose names are
+    /// within two single-character edits of a registered name, such as
+    /// `APP_PROT` next to a registered `APP_PORT`; these are possible typos.
+    pub unregistered: Vec<String>,
+    /// Registered variables that take their default.
+    pub defaulted: Vec<String>,
+}
+
+/// Summarizes the registered variables against the environment, answering
+/// most "why is my config wrong?" questions in one report.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{env_report, register};
+/// register!(APP_PORT = "8080", APP_HOST = "localhost");
+///
+/// std::env::set_var("APP_HOST", "0.0.0.0");
+/// std::env::set_var("APP_PROT", "9090");
+///
+/// let report = env_report();
+/// assert_eq!(report.set, vec!["APP_HOST"]);
+/// assert_eq!(report.defaulted, vec!["APP_PORT"]);
+/// assert_eq!(report.unregistered, vec!["APP_PROT"]);
+/// ```
+pub fn env_report() -> EnvReport {
+    let env_names = env::vars_os().filter_map(|(name, _)| name.into_string().ok());
+    report(inventory::iter::<RequiredVar>(), env_names)
+}
+
+/// How many single-character edits apart an unregistered name may be from a
+/// registered one to be reported as a possible typo.
+const TYPO_DISTANCE: usize = 2;
+
+/// Where a variable gets its value, from the most to the least telling.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Status {
+    Set,
+    Defaulted,
+    Missing,
+    /// An `optional` variable without a value, which isn't reported.
+    Unset,
+}
+
+/// A variable whose name is ambiguous ignoring case has no value, as for
+/// [`RequiredVar::get`].
+fn status(var: &RequiredVar) -> Status {
+    let from_env = match case::lookup(var.name) {
+        Ok(value) => value.is_some(),
+        Err(_) => return Status::Missing,
+    };
+    if from_env || var.platform_value().is_some() || var.derived_value().is_some() {
+        Status::Set
+    } else if var.default_with(case::var).is_some() {
+        Status::Defaulted
+    } else if var.optional {
+        Status::Unset
+    } else {
+        Status::Missing
+    }
+}
+
+pub(crate) fn report<'a, I, E>(vars: I, env_names: E) -> EnvReport
+where
+    I: IntoIterator<Item = &'a RequiredVar>,
+    E: IntoIterator<Item = String>,
+{
+    // A variable registered several times has the best status of them
+    let mut statuses: BTreeMap<&str, Status> = BTreeMap::new();
+    for var in vars {
+        let status = status(var);
+        statuses
+            .entry(var.name)
+            .and_modify(|best| *best = (*best).min(status))
+            .or_insert(status);
+    }
+
+    let mut report = EnvReport::default();
+    for (name, status) in &statuses {
+        let bucket = match status {
+            Status::Set => &mut report.set,
+            Status::Defaulted => &mut report.defaulted,
+            Status::Missing => &mut report.missing,
+            Status::Unset => continue,
+        };
+        bucket.push(name.to_string());
+    }
+
+    let unregistered: BTreeSet<String> = env_names
+        .into_iter()
+        .filter(|name| !statuses.contains_key(name.as_str()))
+        .filter(|name| {
+            statuses
+                .keys()
+                .any(|registered| is_similar(name, registered, TYPO_DISTANCE))
+        })
+        .collect();
+    report.unregistered = unregistered.into_iter().collect();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+
+    #[test]
+    fn test_report() {
+        let _guard = env_lock();
+        let vars = [
+            RequiredVar::new("REPORT_HOST").with_default("localhost"),
+            RequiredVar::new("REPORT_PORT").with_default("8080"),
+            RequiredVar::new("REPORT_TOKEN"),
+            // Registered again with a default
+            RequiredVar::new("REPORT_REGION"),
+            RequiredVar::new("REPORT_REGION").with_default("eu"),
+            RequiredVar::new("REPORT_PROXY").with_optional(),
+        ];
+        env::set_var("REPORT_HOST", "0.0.0.0");
+        env::remove_var("REPORT_PORT");
+        env::remove_var("REPORT_TOKEN");
+        env::remove_var("REPORT_REGION");
+        env::remove_var("REPORT_PROXY");
+
+        let env_names = [
+            "REPORT_HOST",
+            "REPORT_PROT",
+            "OTHER_PORT",
+            "REPORTING",
+            "RPORT_TOKEN",
+        ];
+        let report = report(&vars, env_names.iter().map(|name| name.to_string()));
+        assert_eq!(
+            report,
+            EnvReport {
+                set: vec!["REPORT_HOST".to_string()],
+                missing: vec!["REPORT_TOKEN".to_string()],
+                unregistered: vec!["REPORT_PROT".to_string(), "RPORT_TOKEN".to_string()],
+                defaulted: vec!["REPORT_PORT".to_string(), "REPORT_REGION".to_string()],
+            }
+        );
+        env::remove_var("REPORT_HOST");
+    }
+}
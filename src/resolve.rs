@@ -0,0 +1,216 @@
+//! Resolution of the registered variables from one source of explicit
+//! values, shared by the loaders whether or not they mutate the environment.
+
+use crate::expand::expand_with;
+use crate::graph::{references, resolution_order};
+use crate::invariant::{self, Invariant, ResolvedValues};
+use crate::{
+    case, check_resolved_value, format_missing, registered_default, EnvInventoryError, Group,
+    Priority, RequiredVar,
+};
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+
+/// How the value of a variable was resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// Taken from the lookup, the environment or the config files.
+    Lookup,
+    /// Taken from the platform source.
+    Platform,
+    /// Computed from other variables.
+    Derived,
+    /// A registered default.
+    Default,
+}
+
+/// The registered variables resolved by [`resolve`].
+#[derive(Debug, Default)]
+pub(crate) struct Resolution {
+    /// The expanded value of every registered variable that has one.
+    pub(crate) values: HashMap<String, String>,
+    /// The value of each of `values` before expansion.
+    pub(crate) raw: HashMap<String, String>,
+    /// How each of `values` was resolved.
+    pub(crate) steps: HashMap<String, Step>,
+}
+
+/// Resolves and expands the registered variables, taking the values set
+/// explicitly from `lookup`.
+///
+/// A value from `lookup` wins, then the platform source, then derived
+/// values, then binary defaults, then library defaults. Variables are
+/// resolved after those they depend on, so derived values and conditional
+/// defaults see the expanded values of their inputs. Variable references
+/// that aren't registered are looked up in the environment.
+///
+/// # Errors
+///
+/// * `CyclicReference`: If variables depend on each other.
+/// * `MissingEnvVar`: If a reference can't be expanded.
+///
+/// And any error `lookup` returns, such as `AmbiguousVar`.
+pub(crate) fn resolve<L>(lookup: L) -> Result<Resolution, EnvInventoryError>
+where
+    L: Fn(&str) -> Result<Option<String>, EnvInventoryError>,
+{
+    let mut explicit: BTreeMap<&'static str, (String, Step)> = BTreeMap::new();
+    let mut dependencies: BTreeMap<&'static str, Vec<String>> = BTreeMap::new();
+    for var in inventory::iter::<RequiredVar>() {
+        if dependencies.contains_key(var.name) {
+            continue;
+        }
+        let value = match lookup(var.name)? {
+            Some(value) => Some((value, Step::Lookup)),
+            None => platform_value(var.name).map(|value| (value, Step::Platform)),
+        };
+        match value {
+            Some((value, step)) => {
+                dependencies.insert(var.name, references(&value));
+                explicit.insert(var.name, (value, step));
+            }
+            None => {
+                dependencies.insert(var.name, registered_dependencies(var.name));
+            }
+        }
+    }
+
+    let mut resolution = Resolution::default();
+    for name in resolution_order(&dependencies)? {
+        let (raw, step) = match explicit.remove(name) {
+            Some(value) => value,
+            None => match resolve_implicit(name, &resolution.values, &lookup)? {
+                Some(value) => value,
+                None => continue,
+            },
+        };
+        let values = &resolution.values;
+        let context = |name: &str| match values.get(name) {
+            Some(value) => Ok(Some(value.clone())),
+            None => env::var(name).map(Some),
+        };
+        let value = expand_with(&raw, context)?;
+        resolution.values.insert(name.to_string(), value);
+        resolution.raw.insert(name.to_string(), raw);
+        resolution.steps.insert(name.to_string(), step);
+    }
+    Ok(resolution)
+}
+
+impl Resolution {
+    /// Validates the resolved values: every registered variable that isn't
+    /// optional must have one, every value must be well-formed, and the
+    /// registered groups and invariants must hold.
+    ///
+    /// # Errors
+    ///
+    /// * `MissingEnvVars`: If one or more registered variables have no value.
+    ///
+    /// And any error [`validate_env_vars`](crate::validate_env_vars) returns
+    /// for an invalid value.
+    pub(crate) fn validate(&self) -> Result<(), EnvInventoryError> {
+        let missing = format_missing(
+            inventory::iter::<RequiredVar>()
+                .filter(|var| !var.optional && !self.values.contains_key(var.name)),
+        );
+        if !missing.is_empty() {
+            #[cfg(feature = "tracing")]
+            tracing::warn!(
+                "Missing required environment variables: {:?}",
+                crate::missing::rendered(&missing)
+            );
+            return Err(EnvInventoryError::MissingEnvVars(missing));
+        }
+        for var in inventory::iter::<RequiredVar>() {
+            if let Some(value) = self.values.get(var.name) {
+                check_resolved_value(var, value.clone())?;
+            }
+        }
+        let is_present = |name: &str| {
+            self.values.contains_key(name) || (!is_registered(name) && env::var(name).is_ok())
+        };
+        let is_explicit = |name: &str| match self.steps.get(name) {
+            Some(step) => matches!(step, Step::Lookup | Step::Platform),
+            None => !is_registered(name) && env::var(name).is_ok(),
+        };
+        inventory::iter::<Group>()
+            .try_for_each(|group| group.check_with(is_present, is_explicit))?;
+        let values: ResolvedValues = self.values.clone().into_iter().collect();
+        invariant::check_invariants(inventory::iter::<Invariant>(), &values)
+    }
+}
+
+/// Lists the variables that the derivation, conditional defaults and
+/// defaults registered for `name` may depend on.
+fn registered_dependencies(name: &str) -> Vec<String> {
+    let mut names = Vec::new();
+    for var in inventory::iter::<RequiredVar>().filter(|var| var.name == name) {
+        if let Some(derivation) = var.derivation {
+            names.extend(derivation.from.iter().map(|name| name.to_string()));
+        }
+        if let Some(condition) = &var.condition {
+            names.push(condition.based_on.to_string());
+            for (_, value) in condition.cases {
+                names.extend(references(value));
+            }
+        }
+        if let Some(default) = var.default {
+            names.extend(references(default));
+        }
+    }
+    names
+}
+
+/// Resolves `name`, which `lookup` and the platform source don't set, from
+/// its derivation or defaults, given the expanded `values` of the variables
+/// it depends on. Inputs that aren't registered are looked up with `lookup`,
+/// then in the environment.
+fn resolve_implicit<L>(
+    name: &str,
+    values: &HashMap<String, String>,
+    lookup: &L,
+) -> Result<Option<(String, Step)>, EnvInventoryError>
+where
+    L: Fn(&str) -> Result<Option<String>, EnvInventoryError>,
+{
+    let value_of = |name: &str| -> Result<Option<String>, EnvInventoryError> {
+        if let Some(value) = values.get(name) {
+            return Ok(Some(value.clone()));
+        }
+        match lookup(name)? {
+            Some(value) => Ok(Some(value)),
+            None => case::lookup(name),
+        }
+    };
+    let registrations = || inventory::iter::<RequiredVar>().filter(|var| var.name == name);
+    if let Some(derivation) = registrations().find_map(|var| var.derivation) {
+        let mut inputs = Vec::new();
+        for input in derivation.from {
+            match value_of(input)? {
+                Some(value) => inputs.push(value),
+                None => return Ok(None),
+            }
+        }
+        let inputs: Vec<&str> = inputs.iter().map(String::as_str).collect();
+        return Ok(Some(((derivation.compute)(&inputs), Step::Derived)));
+    }
+
+    let mut based_on = HashMap::new();
+    for condition in registrations().filter_map(|var| var.condition.as_ref()) {
+        based_on.insert(condition.based_on, value_of(condition.based_on)?);
+    }
+    let based_on = |name: &str| based_on.get(name).cloned().flatten();
+    Ok(registered_default(name, Priority::Binary, based_on)
+        .or_else(|| registered_default(name, Priority::Library, based_on))
+        .map(|value| (value.to_string(), Step::Default)))
+}
+
+fn platform_value(name: &str) -> Option<String> {
+    inventory::iter::<RequiredVar>()
+        .filter(|var| var.name == name)
+        .find_map(RequiredVar::platform_value)
+}
+
+fn is_registered(name: &str) -> bool {
+    inventory::iter::<RequiredVar>().any(|var| var.name == name)
+}
@@ -0,0 +1,293 @@
+//! Validation of the resolved config against a JSON Schema served over HTTP.
+//!
+//! Only the subset of JSON Schema that makes sense for a flat map of strings is
+//! supported: `required` and `additionalProperties: false` at the top level,
+//! and `type`, `enum`, `minimum`, `maximum`, `minLength` and `maxLength` per
+//! property. Every value is a string, so `type` checks that the value parses
+//! as the given type rather than its JSON representation.
+
+use crate::{map, EnvInventoryError, RequiredVar, MASK};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Fetches a JSON Schema from `url` and validates the resolved values of all
+/// registered variables against it.
+///
+/// Failing to reach the schema endpoint is an error; use
+/// [`validate_against_schema_url_with`] to skip validation instead.
+///
+/// # Errors
+///
+/// * `SchemaFetchError`: If the schema can't be fetched.
+/// * `ParseFileError`: If the fetched schema isn't valid JSON.
+/// * `SchemaViolations`: If one or more values violate the schema. The
+///   values of secrets are masked in the violations.
+pub fn validate_against_schema_url(url: &str) -> Result<(), EnvInventoryError> {
+    validate_against_schema_url_with(url, false)
+}
+
+/// Like [`validate_against_schema_url`], but when `skip_if_unreachable` is
+/// `true` a failure to fetch the schema only logs a warning, with the
+/// `tracing` feature, and validation is skipped.
+pub fn validate_against_schema_url_with(
+    url: &str,
+    skip_if_unreachable: bool,
+) -> Result<(), EnvInventoryError> {
+    let schema = match fetch_schema(url) {
+        Ok(schema) => schema,
+        Err(EnvInventoryError::SchemaFetchError(_reason)) if skip_if_unreachable => {
+            #[cfg(feature = "tracing")]
+            tracing::warn!("Skipping schema validation against {}: {}", url, _reason);
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    let violations = schema_violations(&schema, &map());
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(EnvInventoryError::SchemaViolations(violations))
+    }
+}
+
+fn fetch_schema(url: &str) -> Result<Value, EnvInventoryError> {
+    let body = reqwest::blocking::get(url)
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| EnvInventoryError::SchemaFetchError(e.to_string()))?;
+
+    serde_json::from_str(&body).map_err(|_| EnvInventoryError::ParseFileError(url.to_string()))
+}
+
+/// Lists every way `values` violates `schema`, sorted for stable output.
+pub(crate) fn schema_violations<K: AsRef<str>>(
+    schema: &Value,
+    values: &HashMap<K, String>,
+) -> Vec<String> {
+    let values: HashMap<&str, &str> = values
+        .iter()
+        .map(|(name, value)| (name.as_ref(), value.as_str()))
+        .collect();
+    let properties = schema.get("properties").and_then(Value::as_object);
+    let mut violations = Vec::new();
+
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        for name in required.iter().filter_map(Value::as_str) {
+            if !values.contains_key(name) {
+                violations.push(format!("{}: required but not set", name));
+            }
+        }
+    }
+
+    for (name, value) in values.iter() {
+        match properties.and_then(|properties| properties.get(*name)) {
+            Some(property) => {
+                // The values of secrets aren't shown in the violations
+                let secret = inventory::iter::<RequiredVar>()
+                    .any(|var| var.name == *name && var.is_redacted());
+                let shown = if secret { MASK } else { value };
+                check_value(name, value, shown, property, &mut violations)
+            }
+            None => {
+                if schema.get("additionalProperties") == Some(&Value::Bool(false)) {
+                    violations.push(format!("{}: not allowed by the schema", name));
+                }
+            }
+        }
+    }
+
+    violations.sort();
+    violations
+}
+
+/// Checks `value` against `property`, describing violations with `shown` in
+/// place of the value.
+fn check_value(
+    name: &str,
+    value: &str,
+    shown: &str,
+    property: &Value,
+    violations: &mut Vec<String>,
+) {
+    let types: Vec<&str> = match property.get("type") {
+        Some(Value::String(ty)) => vec![ty.as_str()],
+        Some(Value::Array(tys)) => tys.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new(),
+    };
+    if !types.is_empty() && !types.iter().any(|ty| matches_type(value, ty)) {
+        violations.push(format!(
+            "{}: expected {}, found {:?}",
+            name,
+            types.join(" or "),
+            shown
+        ));
+    }
+
+    if let Some(allowed) = property.get("enum").and_then(Value::as_array) {
+        let found = allowed.iter().any(|option| match option {
+            Value::String(option) => option == value,
+            option => serde_json::from_str::<Value>(value).ok().as_ref() == Some(option),
+        });
+        if !found {
+            violations.push(format!(
+                "{}: {:?} is not one of {}",
+                name,
+                shown,
+                Value::from(allowed.clone())
+            ));
+        }
+    }
+
+    if let Ok(number) = value.trim().parse::<f64>() {
+        if let Some(minimum) = property.get("minimum").and_then(Value::as_f64) {
+            if number < minimum {
+                violations.push(format!(
+                    "{}: {} is less than the minimum {}",
+                    name, shown, minimum
+                ));
+            }
+        }
+        if let Some(maximum) = property.get("maximum").and_then(Value::as_f64) {
+            if number > maximum {
+                violations.push(format!(
+                    "{}: {} is greater than the maximum {}",
+                    name, shown, maximum
+                ));
+            }
+        }
+    }
+
+    let length = value.chars().count() as u64;
+    if let Some(min_length) = property.get("minLength").and_then(Value::as_u64) {
+        if length < min_length {
+            violations.push(format!("{}: shorter than {} characters", name, min_length));
+        }
+    }
+    if let Some(max_length) = property.get("maxLength").and_then(Value::as_u64) {
+        if length > max_length {
+            violations.push(format!("{}: longer than {} characters", name, max_length));
+        }
+    }
+}
+
+fn matches_type(value: &str, ty: &str) -> bool {
+    match ty {
+        "integer" => value.trim().parse::<i64>().is_ok(),
+        "number" => value.trim().parse::<f64>().is_ok(),
+        "boolean" => matches!(value.trim(), "true" | "false"),
+        "null" => value.is_empty(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    crate::register!(SCHEMA_PORT = "8080");
+    crate::register!(SCHEMA_TOKEN = "0"; secret);
+
+    /// Serves `body` as JSON to a single HTTP request and returns the URL.
+    fn serve_once(body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/schema.json", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+                let n = stream.read(&mut buf).unwrap();
+                if n == 0 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..n]);
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+        url
+    }
+
+    #[test]
+    fn test_schema_violation_from_url() {
+        let url =
+            serve_once(r#"{"properties": {"SCHEMA_PORT": {"type": "integer", "maximum": 65535}}}"#);
+        let _guard = crate::env_lock();
+        env::set_var("SCHEMA_PORT", "not-a-port");
+
+        match validate_against_schema_url(&url) {
+            Err(EnvInventoryError::SchemaViolations(violations)) => {
+                assert_eq!(
+                    violations,
+                    vec!["SCHEMA_PORT: expected integer, found \"not-a-port\""]
+                );
+            }
+            other => panic!("expected schema violations, got {:?}", other),
+        }
+        env::remove_var("SCHEMA_PORT");
+    }
+
+    #[test]
+    fn test_unreachable_schema() {
+        let url = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/schema.json", listener.local_addr().unwrap())
+        };
+
+        assert!(matches!(
+            validate_against_schema_url(&url),
+            Err(EnvInventoryError::SchemaFetchError(_))
+        ));
+        assert!(validate_against_schema_url_with(&url, true).is_ok());
+    }
+
+    #[test]
+    fn test_schema_keywords() {
+        let schema: Value = serde_json::from_str(
+            r#"{
+                "required": ["HOST"],
+                "additionalProperties": false,
+                "properties": {
+                    "MODE": {"enum": ["fast", "slow"]},
+                    "WORKERS": {"type": "integer", "minimum": 1},
+                    "NAME": {"type": "string", "maxLength": 3},
+                    "SCHEMA_TOKEN": {"type": "integer", "enum": ["1", "2"]}
+                }
+            }"#,
+        )
+        .unwrap();
+        let values: HashMap<&str, String> = [
+            ("MODE", "medium"),
+            ("WORKERS", "0"),
+            ("NAME", "toolong"),
+            ("EXTRA", "1"),
+            ("SCHEMA_TOKEN", "hunter2"),
+        ]
+        .iter()
+        .map(|(k, v)| (*k, v.to_string()))
+        .collect();
+
+        assert_eq!(
+            schema_violations(&schema, &values),
+            vec![
+                "EXTRA: not allowed by the schema",
+                "HOST: required but not set",
+                "MODE: \"medium\" is not one of [\"fast\",\"slow\"]",
+                "NAME: longer than 3 characters",
+                "SCHEMA_TOKEN: \"****\" is not one of [\"1\",\"2\"]",
+                "SCHEMA_TOKEN: expected integer, found \"****\"",
+                "WORKERS: 0 is less than the minimum 1",
+            ]
+        );
+    }
+}
@@ -0,0 +1,286 @@
+//! Validation deferred over the staged initialization of an application, or
+//! scoped to an operation.
+
+use crate::{check_value, format_missing, resolve_one, EnvInventoryError, MissingVar, RequiredVar};
+use std::collections::BTreeSet;
+use std::env;
+
+/// Collects the variables each stage of an application requires, and
+/// validates them all at once when the last stage is done.
+///
+/// Applications that initialize their modules or plugins in stages only know
+/// which variables they need once every stage has run. Each stage adds its
+/// variables to the scope, and [`ValidationScope::finish`] reports every
+/// missing and invalid one together instead of stopping at the first.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, ValidationScope};
+/// register!(CACHE_TTL: u32 = "60");
+///
+/// let mut scope = ValidationScope::new();
+/// // Core modules
+/// scope.require("CACHE_TTL");
+/// // Plugins, once they are loaded
+/// scope.require_source_prefix("plugins/");
+///
+/// scope.finish().unwrap();
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ValidationScope {
+    names: BTreeSet<String>,
+    prefixes: BTreeSet<String>,
+}
+
+impl ValidationScope {
+    /// Creates an empty scope.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the variable `name` to the scope.
+    ///
+    /// The variable is required even if it is registered as `optional`. A
+    /// name that isn't registered only has to be set in the environment.
+    pub fn require(&mut self, name: &str) -> &mut Self {
+        self.names.insert(name.to_string());
+        self
+    }
+
+    /// Adds the variables registered in files whose path starts with `prefix`
+    /// to the scope, as for
+    /// [`validate_from_source_prefix`](crate::validate_from_source_prefix).
+    /// Those registered as `optional` may remain unset.
+    pub fn require_source_prefix(&mut self, prefix: &str) -> &mut Self {
+        self.prefixes.insert(prefix.to_string());
+        self
+    }
+
+    /// Validates every variable in the scope.
+    ///
+    /// # Errors
+    ///
+    /// * `ValidationErrors`: If any variable in the scope is missing or
+    ///   invalid. It holds one `MissingEnvVars` listing every missing
+    ///   variable, followed by the error for each invalid value, in name
+    ///   order.
+    pub fn finish(self) -> Result<(), EnvInventoryError> {
+        let mut vars: Vec<&RequiredVar> = inventory::iter::<RequiredVar>()
+            .filter(|var| {
+                self.names.contains(var.name)
+                    || self
+                        .prefixes
+                        .iter()
+                        .any(|prefix| var.source.starts_with(prefix.as_str()))
+            })
+            .collect();
+        vars.sort_by_key(|var| var.name);
+
+        // Naming a variable explicitly requires it, even if it is optional
+        let mut missing = format_missing(
+            vars.iter()
+                .copied()
+                .filter(|var| (!var.optional || self.names.contains(var.name)) && !var.is_set()),
+        );
+        missing.extend(
+            self.names
+                .iter()
+                .filter(|name| !vars.iter().any(|var| var.name == name.as_str()))
+                .filter(|name| env::var(name).is_err())
+                .map(|name| MissingVar::unregistered(name)),
+        );
+        missing.sort_by(|a, b| a.rendered.cmp(&b.rendered));
+
+        let mut errors = Vec::new();
+        if !missing.is_empty() {
+            errors.push(EnvInventoryError::MissingEnvVars(missing));
+        }
+        let mut checked = BTreeSet::new();
+        for var in vars {
+            if checked.insert(var.name) {
+                if let Err(e) = check_value(var) {
+                    errors.push(e);
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(EnvInventoryError::ValidationErrors(errors))
+        }
+    }
+}
+
+/// Runs `f` only if the variables `names` are set, treating them as required
+/// for the duration of an operation even if they are normally optional.
+///
+/// A registered name is set if it resolves to a value, as for
+/// [`resolve_one`](crate::resolve_one), and its value must also pass
+/// validation; a name that isn't registered only has to be set in the
+/// environment.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, require_during};
+/// register!(BACKUP_BUCKET = "");
+///
+/// std::env::set_var("BACKUP_BUCKET", "s3://backups");
+/// let bucket = require_during(&["BACKUP_BUCKET"], || {
+///     Ok(std::env::var("BACKUP_BUCKET").unwrap())
+/// })
+/// .unwrap();
+/// assert_eq!(bucket, "s3://backups");
+/// ```
+///
+/// # Errors
+///
+/// * `MissingEnvVars`: If any of `names` isn't set; `f` isn't run.
+/// * Any error validating the value of a registered variable; `f` isn't run.
+/// * Any error returned by `f`.
+pub fn require_during<R, F>(names: &[&str], f: F) -> Result<R, EnvInventoryError>
+where
+    F: FnOnce() -> Result<R, EnvInventoryError>,
+{
+    let mut missing = Vec::new();
+    for name in names {
+        match inventory::iter::<RequiredVar>().find(|var| var.name == *name) {
+            Some(var) => match resolve_one(name) {
+                Some(_) => check_value(var)?,
+                None => missing.push(MissingVar::new(var)),
+            },
+            None if env::var(name).is_err() => missing.push(MissingVar::unregistered(name)),
+            None => {}
+        }
+    }
+    if !missing.is_empty() {
+        missing.sort_by(|a, b| a.rendered.cmp(&b.rendered));
+        return Err(EnvInventoryError::MissingEnvVars(missing));
+    }
+    f()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::env_lock;
+
+    crate::register!(SCOPE_WORKERS: u8 = "4");
+    crate::register!(SCOPE_PLUGIN_PORT: u16 = "9000");
+    crate::register!(SCOPE_PROXY?);
+
+    #[test]
+    fn test_require_during() {
+        let _guard = env_lock();
+        env::set_var("SCOPE_ARCHIVE_DIR", "/srv/archive");
+        let result = require_during(&["SCOPE_ARCHIVE_DIR", "SCOPE_WORKERS"], || Ok(42));
+        assert_eq!(result.unwrap(), 42);
+
+        env::remove_var("SCOPE_ARCHIVE_DIR");
+        let mut ran = false;
+        let result = require_during(&["SCOPE_ARCHIVE_DIR"], || {
+            ran = true;
+            Ok(())
+        });
+        match result {
+            Err(EnvInventoryError::MissingEnvVars(missing)) => {
+                assert_eq!(missing.len(), 1);
+                assert_eq!(missing[0].name, "SCOPE_ARCHIVE_DIR");
+            }
+            other => panic!("expected a missing variable, got {:?}", other),
+        }
+        assert!(!ran);
+
+        // Registered variables must also hold a valid value
+        env::set_var("SCOPE_WORKERS", "many");
+        assert!(matches!(
+            require_during(&["SCOPE_WORKERS"], || Ok(())),
+            Err(EnvInventoryError::InvalidType { .. })
+        ));
+        env::remove_var("SCOPE_WORKERS");
+
+        // Errors from the block are passed through
+        assert!(matches!(
+            require_during(&[], || Err::<(), _>(EnvInventoryError::MissingEnvVar(
+                "X".into()
+            ))),
+            Err(EnvInventoryError::MissingEnvVar(_))
+        ));
+    }
+
+    #[test]
+    fn test_finish_collects_every_stage() {
+        let _guard = env_lock();
+        env::remove_var("SCOPE_PLUGIN_TOKEN");
+        env::set_var("SCOPE_WORKERS", "many");
+        env::set_var("SCOPE_PLUGIN_PORT", "70000");
+
+        let mut scope = ValidationScope::new();
+        scope.require("SCOPE_WORKERS");
+        scope
+            .require("SCOPE_PLUGIN_TOKEN")
+            .require_source_prefix(file!());
+
+        match scope.finish() {
+            Err(EnvInventoryError::ValidationErrors(errors)) => {
+                assert_eq!(errors.len(), 3);
+                assert!(matches!(
+                    &errors[0],
+                    EnvInventoryError::MissingEnvVars(missing)
+                        if missing.len() == 1
+                            && missing[0].to_string() == "SCOPE_PLUGIN_TOKEN=(missing)"
+                            && missing[0].source == "<none>"
+                ));
+                assert!(matches!(
+                    &errors[1],
+                    EnvInventoryError::InvalidValue { name, .. } if name == "SCOPE_PLUGIN_PORT"
+                ));
+                assert!(matches!(
+                    &errors[2],
+                    EnvInventoryError::InvalidType { name, .. } if name == "SCOPE_WORKERS"
+                ));
+            }
+            other => panic!("expected ValidationErrors, got {:?}", other),
+        }
+
+        env::set_var("SCOPE_PLUGIN_TOKEN", "secret");
+        env::remove_var("SCOPE_WORKERS");
+        env::remove_var("SCOPE_PLUGIN_PORT");
+        let mut scope = ValidationScope::new();
+        scope
+            .require("SCOPE_PLUGIN_TOKEN")
+            .require_source_prefix(file!());
+        scope.finish().unwrap();
+        env::remove_var("SCOPE_PLUGIN_TOKEN");
+    }
+
+    #[test]
+    fn test_require_overrides_optional() {
+        let _guard = env_lock();
+        env::remove_var("SCOPE_PROXY");
+
+        // Optional variables matched by their source may remain unset
+        let mut scope = ValidationScope::new();
+        scope.require_source_prefix(file!());
+        scope.finish().unwrap();
+
+        let mut scope = ValidationScope::new();
+        scope.require("SCOPE_PROXY");
+        match scope.finish() {
+            Err(EnvInventoryError::ValidationErrors(errors)) => assert!(matches!(
+                &errors[..],
+                [EnvInventoryError::MissingEnvVars(missing)]
+                    if missing.len() == 1 && missing[0].name == "SCOPE_PROXY"
+            )),
+            other => panic!("expected ValidationErrors, got {:?}", other),
+        }
+
+        env::set_var("SCOPE_PROXY", "http://proxy:3128");
+        let mut scope = ValidationScope::new();
+        scope.require("SCOPE_PROXY");
+        scope.finish().unwrap();
+        env::remove_var("SCOPE_PROXY");
+    }
+}
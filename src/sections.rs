@@ -0,0 +1,78 @@
+//! The sections of config files the loaders read variables from.
+
+/// The section, or sections, of config files holding the variables.
+///
+/// A loader given several sections reads each of them in order, with later
+/// sections winning, so shared values in `[env]` can be overridden by
+/// `[env.production]`. Dotted names such as `env.production` refer to nested
+/// tables, unless the file has a section named with the dots, such as
+/// `["env.production"]` in TOML. A section missing from a file provides no
+/// settings. An empty name refers to the top level of the file.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::load_and_validate_env_vars;
+/// // One section
+/// load_and_validate_env_vars(&["app.toml"], "env").unwrap();
+/// // Overrides layered over shared values
+/// load_and_validate_env_vars(&["app.toml"], &["env", "env.production"]).unwrap();
+/// ```
+pub trait Sections {
+    /// The names of the sections, least significant first.
+    fn names(&self) -> Vec<&str>;
+}
+
+impl<T: Sections + ?Sized> Sections for &T {
+    fn names(&self) -> Vec<&str> {
+        (**self).names()
+    }
+}
+
+impl Sections for str {
+    fn names(&self) -> Vec<&str> {
+        vec![self]
+    }
+}
+
+impl Sections for String {
+    fn names(&self) -> Vec<&str> {
+        vec![self]
+    }
+}
+
+impl Sections for [&str] {
+    fn names(&self) -> Vec<&str> {
+        self.to_vec()
+    }
+}
+
+impl<const N: usize> Sections for [&str; N] {
+    fn names(&self) -> Vec<&str> {
+        self.to_vec()
+    }
+}
+
+impl Sections for Vec<&str> {
+    fn names(&self) -> Vec<&str> {
+        self.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names() {
+        assert_eq!("env".names(), vec!["env"]);
+        assert_eq!(String::from("env").names(), vec!["env"]);
+        assert_eq!(
+            ["env", "env.production"].names(),
+            vec!["env", "env.production"]
+        );
+        assert_eq!(vec!["a", "b"].names(), vec!["a", "b"]);
+        assert_eq!((&"env").names(), vec!["env"]);
+        assert!(<[&str]>::names(&[]).is_empty());
+    }
+}
@@ -0,0 +1,51 @@
+//! Sensitivity levels of variables, and which of them are redacted from
+//! dumps and logs.
+
+use std::sync::RwLock;
+
+/// How sensitive the value of a variable is, from least to most.
+///
+/// Variables are [`Public`](Sensitivity::Public) unless registered with the
+/// `internal` or `secret` option. Values at or above the
+/// [redaction level](set_redaction_level) are masked in dumps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Sensitivity {
+    /// The value can be shown anywhere.
+    #[default]
+    Public,
+    /// The value can be shown within the organization, as in internal logs.
+    Internal,
+    /// The value must never be shown.
+    Secret,
+}
+
+static REDACTION_LEVEL: RwLock<Option<Sensitivity>> = RwLock::new(None);
+
+/// Sets the lowest sensitivity whose values are redacted from the `Debug` and
+/// `Display` forms of variables and from dumps such as
+/// [`list_all_vars_redacted`](crate::list_all_vars_redacted) and
+/// [`dump_effective_config_toml`](crate::dump_effective_config_toml).
+///
+/// Without a call, only [`Sensitivity::Secret`] values are redacted.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{list_all_vars_redacted, register, set_redaction_level, Sensitivity};
+/// register!(BILLING_HOST = "billing.internal"; internal);
+///
+/// // Logs leave the organization, so hide internal values too
+/// set_redaction_level(Sensitivity::Internal);
+/// assert!(list_all_vars_redacted().contains(&"BILLING_HOST=****".to_string()));
+/// ```
+pub fn set_redaction_level(level: Sensitivity) {
+    *REDACTION_LEVEL.write().unwrap_or_else(|e| e.into_inner()) = Some(level);
+}
+
+/// The lowest sensitivity currently redacted; see [`set_redaction_level`].
+pub fn redaction_level() -> Sensitivity {
+    REDACTION_LEVEL
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .unwrap_or(Sensitivity::Secret)
+}
@@ -0,0 +1,188 @@
+//! Serialization of the crate's writes to the process environment.
+//!
+//! The environment is process-global: `env::set_var` races with concurrent
+//! reads through the C library, which is why newer Rust editions make it
+//! `unsafe`. The loaders and [`expanded_map`](crate::expanded_map) take
+//! [`ENV_LOCK`] for the whole of their writes, so concurrent loads don't
+//! interleave and each sees the environment the previous one left.
+//!
+//! The writes go through [`set_var`] and [`remove_var`], which remember what
+//! each variable held before the crate first wrote it, so
+//! [`clear_loaded_vars`] can undo them.
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::sync::{Mutex, MutexGuard};
+
+/// Held while the crate writes to the environment.
+static ENV_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    /// How many [`EnvWriteGuard`]s the current thread holds, so the loaders
+    /// can take the lock and call each other without deadlocking.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Holds [`ENV_LOCK`] until dropped.
+pub(crate) struct EnvWriteGuard {
+    _guard: Option<MutexGuard<'static, ()>>,
+}
+
+impl Drop for EnvWriteGuard {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Takes [`ENV_LOCK`], unless the current thread already holds it.
+///
+/// A panic while the lock is held leaves the environment as it was when the
+/// panic happened, which the next load overrides as usual, so a poisoned lock
+/// is taken anyway.
+pub(crate) fn lock_env() -> EnvWriteGuard {
+    let guard = if DEPTH.with(Cell::get) == 0 {
+        Some(ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner()))
+    } else {
+        None
+    };
+    DEPTH.with(|depth| depth.set(depth.get() + 1));
+    EnvWriteGuard { _guard: guard }
+}
+
+/// A variable written by the crate.
+struct Written {
+    /// The value before the crate first wrote the variable, if it was set.
+    previous: Option<OsString>,
+    /// The value the crate last wrote, or `None` if it removed the variable.
+    current: Option<OsString>,
+}
+
+/// Every variable the crate has written since the last
+/// [`clear_loaded_vars`].
+static WRITTEN: Mutex<Option<HashMap<String, Written>>> = Mutex::new(None);
+
+fn remember(name: &str, current: Option<OsString>) {
+    let mut written = WRITTEN.lock().unwrap_or_else(|e| e.into_inner());
+    written
+        .get_or_insert_with(HashMap::new)
+        .entry(name.to_string())
+        .or_insert_with(|| Written {
+            previous: env::var_os(name),
+            current: None,
+        })
+        .current = current;
+}
+
+/// Sets `name` to `value`, remembering the value it replaces.
+pub(crate) fn set_var<V: AsRef<OsStr>>(name: &str, value: V) {
+    remember(name, Some(value.as_ref().to_os_string()));
+    env::set_var(name, value);
+}
+
+/// Removes `name`, remembering the value it had.
+pub(crate) fn remove_var(name: &str) {
+    remember(name, None);
+    env::remove_var(name);
+}
+
+/// Undoes the changes the loaders made to the environment, leaving it as it
+/// was before the first load or the last call to this function.
+///
+/// Variables the loaders set are removed, and those they overwrote or
+/// removed get their previous values back. A variable changed by someone
+/// else since the crate last wrote it is left alone. This is mostly useful
+/// between tests that load config, so earlier loads don't leak into later
+/// tests.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{clear_loaded_vars, load_dotenv};
+/// # use std::io::Write;
+/// let mut file = tempfile::NamedTempFile::new().unwrap();
+/// writeln!(file, "FEATURE_FLAGS=beta").unwrap();
+///
+/// load_dotenv(file.path()).unwrap();
+/// assert_eq!(std::env::var("FEATURE_FLAGS").unwrap(), "beta");
+///
+/// clear_loaded_vars();
+/// assert!(std::env::var("FEATURE_FLAGS").is_err());
+/// ```
+pub fn clear_loaded_vars() {
+    let _lock = lock_env();
+    let written = WRITTEN
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_default();
+    for (name, written) in written {
+        if env::var_os(&name) != written.current {
+            continue;
+        }
+        match written.previous {
+            Some(previous) => env::set_var(&name, previous),
+            None => env::remove_var(&name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lock_env_is_reentrant() {
+        // Keeps other tests from loading, and taking the lock, meanwhile
+        let _guard = crate::env_lock();
+        let outer = lock_env();
+        let inner = lock_env();
+        assert!(ENV_LOCK.try_lock().is_err());
+        drop(inner);
+        assert!(ENV_LOCK.try_lock().is_err());
+        drop(outer);
+        assert!(ENV_LOCK.try_lock().is_ok());
+    }
+
+    #[test]
+    fn test_clear_loaded_vars() {
+        let _guard = crate::env_lock();
+        env::remove_var("SYNC_NEW");
+        env::set_var("SYNC_OVERWRITTEN", "original");
+        env::set_var("SYNC_REMOVED", "original");
+        env::remove_var("SYNC_CHANGED");
+        env::set_var("SYNC_UNTOUCHED", "original");
+
+        set_var("SYNC_NEW", "loaded");
+        set_var("SYNC_OVERWRITTEN", "loaded");
+        set_var("SYNC_OVERWRITTEN", "loaded again");
+        remove_var("SYNC_REMOVED");
+        set_var("SYNC_CHANGED", "loaded");
+        env::set_var("SYNC_CHANGED", "by the caller");
+
+        clear_loaded_vars();
+        assert!(env::var("SYNC_NEW").is_err());
+        assert_eq!(env::var("SYNC_OVERWRITTEN").unwrap(), "original");
+        assert_eq!(env::var("SYNC_REMOVED").unwrap(), "original");
+        assert_eq!(env::var("SYNC_CHANGED").unwrap(), "by the caller");
+        assert_eq!(env::var("SYNC_UNTOUCHED").unwrap(), "original");
+
+        // Nothing is left to undo
+        set_var("SYNC_NEW", "loaded");
+        clear_loaded_vars();
+        env::set_var("SYNC_NEW", "by the caller");
+        clear_loaded_vars();
+        assert_eq!(env::var("SYNC_NEW").unwrap(), "by the caller");
+
+        for name in [
+            "SYNC_NEW",
+            "SYNC_OVERWRITTEN",
+            "SYNC_REMOVED",
+            "SYNC_CHANGED",
+            "SYNC_UNTOUCHED",
+        ] {
+            env::remove_var(name);
+        }
+    }
+}
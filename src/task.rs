@@ -0,0 +1,83 @@
+//! Config scoped to a tokio task rather than the process environment.
+//!
+//! The environment is shared by the whole process, so tasks that need
+//! different config, such as tenants of a multi-tenant server or tests
+//! running concurrently, can't each load their own into it. Instead, resolve
+//! the config without touching the environment and run each task within its
+//! own with [`with_config`].
+
+use crate::ResolvedValues;
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static CONFIG: Arc<ResolvedValues>;
+}
+
+/// Runs `future` with `config` as the config returned by [`current_config`]
+/// within it, leaving the process environment and other tasks alone.
+///
+/// The config does not follow tasks spawned by `future`; wrap them in their
+/// own `with_config`.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use env_inventory::{current_config, with_config, LoaderConfig, ResolvedValues};
+/// # async fn serve(_database_url: Option<&str>) {}
+/// # async fn run() -> Result<(), env_inventory::EnvInventoryError> {
+/// let values = LoaderConfig::new()
+///     .mutate_env(false)
+///     .load(&["tenant-a.toml"], "app")?;
+/// let config: ResolvedValues = values.into_iter().collect();
+///
+/// with_config(config, async {
+///     let config = current_config().unwrap();
+///     serve(config.get("DATABASE_URL")).await
+/// })
+/// .await;
+/// # Ok(())
+/// # }
+/// ```
+pub async fn with_config<F: Future>(config: ResolvedValues, future: F) -> F::Output {
+    CONFIG.scope(Arc::new(config), future).await
+}
+
+/// The config of the current task, if it runs within [`with_config`].
+pub fn current_config() -> Option<Arc<ResolvedValues>> {
+    CONFIG.try_with(Arc::clone).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tasks_see_their_own_config() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        runtime.block_on(async {
+            assert!(current_config().is_none());
+
+            let tasks: Vec<_> = ["tenant-a", "tenant-b"]
+                .into_iter()
+                .map(|tenant| {
+                    let config: ResolvedValues = [("TASK_TENANT", tenant)].into_iter().collect();
+                    tokio::spawn(with_config(config, async move {
+                        // Let the other task run within its own config
+                        tokio::task::yield_now().await;
+                        let config = current_config().unwrap();
+                        assert_eq!(config.get("TASK_TENANT"), Some(tenant));
+                        tenant
+                    }))
+                })
+                .collect();
+            for (task, tenant) in tasks.into_iter().zip(["tenant-a", "tenant-b"]) {
+                assert_eq!(task.await.unwrap(), tenant);
+            }
+
+            assert!(current_config().is_none());
+        });
+    }
+}
@@ -0,0 +1,113 @@
+//! Config health counters reported through the `metrics` facade.
+//!
+//! After every load, the following counters are incremented by the number of
+//! distinct registered variables in each state:
+//!
+//! * `env_inventory.resolved`: variables with a value.
+//! * `env_inventory.defaulted`: variables the load set from a default.
+//! * `env_inventory.missing`: variables without a value, apart from
+//!   `optional` ones.
+//!
+//! Install any `metrics` recorder, such as a Prometheus exporter, to collect
+//! them.
+
+use crate::provenance::Origin;
+use crate::RequiredVar;
+use std::collections::{HashMap, HashSet};
+
+/// Increments the counters for a load that set the variables in `loaded`.
+pub(crate) fn record_load(loaded: &HashMap<String, Origin>) {
+    let resolved: HashSet<&'static str> = inventory::iter::<RequiredVar>()
+        .filter(|var| var.is_set())
+        .map(|var| var.name)
+        .collect();
+    let missing = inventory::iter::<RequiredVar>()
+        .filter(|var| !var.optional && !resolved.contains(var.name))
+        .map(|var| var.name)
+        .collect::<HashSet<_>>()
+        .len();
+    let defaulted = loaded
+        .values()
+        .filter(|origin| **origin == Origin::Default)
+        .count();
+
+    metrics::counter!("env_inventory.resolved").increment(resolved.len() as u64);
+    metrics::counter!("env_inventory.defaulted").increment(defaulted as u64);
+    metrics::counter!("env_inventory.missing").increment(missing as u64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::{
+        Counter, CounterFn, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit,
+    };
+    use std::sync::{Arc, Mutex};
+
+    /// Records counter values by name.
+    #[derive(Default)]
+    struct TestRecorder(Arc<Mutex<HashMap<String, u64>>>);
+
+    struct TestCounter(String, Arc<Mutex<HashMap<String, u64>>>);
+
+    impl CounterFn for TestCounter {
+        fn increment(&self, value: u64) {
+            *self.1.lock().unwrap().entry(self.0.clone()).or_default() += value;
+        }
+
+        fn absolute(&self, value: u64) {
+            self.1.lock().unwrap().insert(self.0.clone(), value);
+        }
+    }
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(Arc::new(TestCounter(
+                key.name().to_string(),
+                self.0.clone(),
+            )))
+        }
+
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    #[test]
+    fn test_record_load() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        let recorder = TestRecorder::default();
+        let loaded: HashMap<String, Origin> = [
+            ("A".to_string(), Origin::Default),
+            ("B".to_string(), Origin::Default),
+            ("C".to_string(), Origin::File("app.toml".to_string())),
+        ]
+        .into_iter()
+        .collect();
+
+        metrics::with_local_recorder(&recorder, || record_load(&loaded));
+
+        let counters = recorder.0.lock().unwrap();
+        let required = inventory::iter::<RequiredVar>()
+            .filter(|var| !var.optional || var.is_set())
+            .map(|var| var.name)
+            .collect::<HashSet<_>>()
+            .len() as u64;
+        assert_eq!(counters["env_inventory.defaulted"], 2);
+        assert_eq!(
+            counters["env_inventory.resolved"] + counters["env_inventory.missing"],
+            required
+        );
+        // Every variable registered by the tests has a value at this point
+        assert_eq!(counters["env_inventory.missing"], 0);
+    }
+}
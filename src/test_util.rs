@@ -0,0 +1,112 @@
+//! Helpers for testing applications' config.
+
+use crate::output::redacted_values;
+use std::collections::HashSet;
+use std::env;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+/// Asserts that the effective config matches the golden file at
+/// `golden_path`, panicking with a diff otherwise.
+///
+/// The config is rendered as one `NAME="value"` line per variable with a
+/// value, sorted by name, with the values of `secret` variables redacted, so
+/// the golden file can be committed. Run the tests with `UPDATE_GOLDEN` set
+/// to write the current config to the golden file instead.
+///
+/// # Panics
+///
+/// Panics if the config doesn't match, or if the golden file can't be read
+/// or written.
+pub fn assert_config_matches_golden(golden_path: &Path) {
+    let actual = render_config();
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(golden_path, &actual)
+            .unwrap_or_else(|e| panic!("Failed to write {}: {}", golden_path.display(), e));
+        return;
+    }
+
+    let expected = fs::read_to_string(golden_path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read {}: {}; set UPDATE_GOLDEN to create it",
+            golden_path.display(),
+            e
+        )
+    });
+    if actual != expected {
+        panic!(
+            "Config doesn't match {} (set UPDATE_GOLDEN to update it):\n{}",
+            golden_path.display(),
+            diff(&expected, &actual)
+        );
+    }
+}
+
+/// Renders the effective config as sorted `NAME="value"` lines.
+fn render_config() -> String {
+    let mut out = String::new();
+    for (name, value) in redacted_values() {
+        match value {
+            Some(value) => {
+                let _ = writeln!(out, "{}={:?}", name, value);
+            }
+            None => {
+                let _ = writeln!(out, "{}=<redacted>", name);
+            }
+        }
+    }
+    out
+}
+
+/// Lists the lines removed from `expected` with `-` and those added in
+/// `actual` with `+`.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected_lines: HashSet<&str> = expected.lines().collect();
+    let actual_lines: HashSet<&str> = actual.lines().collect();
+
+    let mut out = String::new();
+    for line in expected.lines().filter(|line| !actual_lines.contains(line)) {
+        let _ = writeln!(out, "-{}", line);
+    }
+    for line in actual.lines().filter(|line| !expected_lines.contains(line)) {
+        let _ = writeln!(out, "+{}", line);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+    use tempfile::tempdir;
+
+    crate::register!(GOLDEN_VAR = "golden");
+    crate::register!(GOLDEN_SECRET = "hunter2"; secret);
+
+    #[test]
+    fn test_golden() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        env::remove_var("GOLDEN_VAR");
+        env::remove_var("GOLDEN_SECRET");
+        let dir = tempdir().unwrap();
+        let golden = dir.path().join("config.golden");
+
+        env::set_var("UPDATE_GOLDEN", "1");
+        assert_config_matches_golden(&golden);
+        env::remove_var("UPDATE_GOLDEN");
+
+        let written = fs::read_to_string(&golden).unwrap();
+        assert!(written.contains("GOLDEN_VAR=\"golden\"\n"));
+        assert!(written.contains("GOLDEN_SECRET=<redacted>\n"));
+        assert!(!written.contains("hunter2"));
+        assert_config_matches_golden(&golden);
+
+        env::set_var("GOLDEN_VAR", "drifted");
+        let result = panic::catch_unwind(|| assert_config_matches_golden(&golden));
+        env::remove_var("GOLDEN_VAR");
+        let message = *result.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("-GOLDEN_VAR=\"golden\"\n+GOLDEN_VAR=\"drifted\"\n"));
+    }
+}
@@ -0,0 +1,102 @@
+//! Parsing of values with units, such as byte sizes.
+
+use crate::{access, EnvInventoryError};
+
+/// Byte-size suffixes and their multipliers, matched case-insensitively.
+const BYTE_UNITS: &[(&str, u64)] = &[
+    ("", 1),
+    ("b", 1),
+    ("kb", 1_000),
+    ("mb", 1_000_000),
+    ("gb", 1_000_000_000),
+    ("tb", 1_000_000_000_000),
+    ("kib", 1 << 10),
+    ("mib", 1 << 20),
+    ("gib", 1 << 30),
+    ("tib", 1 << 40),
+];
+
+/// Gets the value of a registered variable as a number of bytes.
+///
+/// The value is a whole number with an optional SI (`KB`, `MB`, `GB`, `TB`)
+/// or binary (`KiB`, `MiB`, `GiB`, `TiB`) suffix, so `"512MB"` is
+/// 512,000,000 bytes and `"2GiB"` is 2,147,483,648 bytes. A bare number is a
+/// count of bytes. Suffixes are case-insensitive and may be separated from
+/// the number by whitespace.
+///
+/// Returns `Ok(None)` if `name` isn't registered or has no value.
+///
+/// # Errors
+///
+/// * `InvalidValue`: If the value isn't a byte size, has an unknown unit, or
+///   doesn't fit in a `u64`.
+pub fn get_bytes(name: &str) -> Result<Option<u64>, EnvInventoryError> {
+    match access::read(name) {
+        Some((value, _)) => parse_bytes(&value)
+            .map(Some)
+            .map_err(|reason| EnvInventoryError::InvalidValue {
+                name: name.to_string(),
+                value,
+                reason,
+            }),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn parse_bytes(value: &str) -> Result<u64, String> {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    if number.is_empty() {
+        return Err("expected a byte size such as 512MB".to_string());
+    }
+
+    let unit = unit.trim_start().to_ascii_lowercase();
+    let multiplier = BYTE_UNITS
+        .iter()
+        .find(|(suffix, _)| *suffix == unit)
+        .map(|(_, multiplier)| *multiplier)
+        .ok_or_else(|| format!("unknown byte unit {:?}", unit))?;
+
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|number| number.checked_mul(multiplier))
+        .ok_or_else(|| "byte size is too large".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    crate::register!(BUFFER_SIZE = "64KiB");
+
+    #[test]
+    fn test_parse_bytes() {
+        assert_eq!(parse_bytes("512MB"), Ok(512_000_000));
+        assert_eq!(parse_bytes("2GiB"), Ok(2 * 1024 * 1024 * 1024));
+        assert_eq!(parse_bytes("4096"), Ok(4096));
+        assert_eq!(parse_bytes("16 kb"), Ok(16_000));
+        assert!(parse_bytes("10XB").is_err());
+        assert!(parse_bytes("MB").is_err());
+        assert!(parse_bytes("99999999999TiB").is_err());
+    }
+
+    #[test]
+    fn test_get_bytes() {
+        let _guard = crate::env_lock();
+        env::remove_var("BUFFER_SIZE");
+        assert_eq!(get_bytes("BUFFER_SIZE").unwrap(), Some(64 * 1024));
+        assert_eq!(get_bytes("NOT_REGISTERED_BYTES").unwrap(), None);
+
+        env::set_var("BUFFER_SIZE", "12 parsecs");
+        assert!(matches!(
+            get_bytes("BUFFER_SIZE"),
+            Err(EnvInventoryError::InvalidValue { .. })
+        ));
+        env::remove_var("BUFFER_SIZE");
+    }
+}
@@ -0,0 +1,100 @@
+//! Validation predicates attached to variables at runtime.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+type Validator = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+static VALIDATORS: Mutex<Option<HashMap<String, Validator>>> = Mutex::new(None);
+
+/// Attaches a validator to the registered variable `name`, replacing any
+/// previous one.
+///
+/// Validation, such as [`validate_env_vars`](crate::validate_env_vars) or a
+/// loader, runs the validator against the resolved value of the variable, and
+/// fails with `ValidationFailed` carrying the message it returns. Variables
+/// without a value aren't checked. The validator may itself register
+/// validators or run validation.
+///
+/// # Examples
+///
+/// ```rust
+/// # use env_inventory::{register, register_validator, validate_env_vars};
+/// register!(LOG_LEVEL = "info");
+///
+/// register_validator("LOG_LEVEL", |value| match value {
+///     "trace" | "debug" | "info" | "warn" | "error" => Ok(()),
+///     _ => Err(format!("unknown log level {:?}", value)),
+/// });
+///
+/// std::env::set_var("LOG_LEVEL", "verbose");
+/// assert!(validate_env_vars().is_err());
+/// ```
+pub fn register_validator<F>(name: &str, validator: F)
+where
+    F: Fn(&str) -> Result<(), String> + Send + Sync + 'static,
+{
+    let mut validators = VALIDATORS.lock().unwrap_or_else(|e| e.into_inner());
+    validators
+        .get_or_insert_with(HashMap::new)
+        .insert(name.to_string(), Arc::new(validator));
+}
+
+/// Runs the validator attached to `name`, if any, against `value`.
+pub(crate) fn run(name: &str, value: &str) -> Result<(), String> {
+    let validator = VALIDATORS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .and_then(|validators| validators.get(name).cloned());
+    // Called outside the lock, so the validator may register validators
+    match validator {
+        Some(validator) => validator(value),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{env_lock, satisfy_required_vars, validate_env_vars, EnvInventoryError};
+    use std::env;
+
+    crate::register!(VALIDATED_LOG_LEVEL = "info");
+
+    #[test]
+    fn test_validator_rejects_value() {
+        let _guard = env_lock();
+        satisfy_required_vars();
+        register_validator("VALIDATED_LOG_LEVEL", |value| match value {
+            "trace" | "debug" | "info" | "warn" | "error" => Ok(()),
+            _ => Err(format!("unknown log level {:?}", value)),
+        });
+
+        env::remove_var("VALIDATED_LOG_LEVEL");
+        validate_env_vars().unwrap();
+
+        env::set_var("VALIDATED_LOG_LEVEL", "verbose");
+        match validate_env_vars() {
+            Err(EnvInventoryError::ValidationFailed { name, message }) => {
+                assert_eq!(name, "VALIDATED_LOG_LEVEL");
+                assert_eq!(message, "unknown log level \"verbose\"");
+            }
+            other => panic!("expected ValidationFailed, got {:?}", other),
+        }
+
+        env::set_var("VALIDATED_LOG_LEVEL", "warn");
+        validate_env_vars().unwrap();
+        env::remove_var("VALIDATED_LOG_LEVEL");
+    }
+
+    #[test]
+    fn test_validator_may_register_validators() {
+        register_validator("VALIDATED_OUTER", |value| {
+            register_validator("VALIDATED_INNER", |_| Err("rejected".to_string()));
+            run("VALIDATED_INNER", value)
+        });
+
+        assert_eq!(run("VALIDATED_OUTER", "any"), Err("rejected".to_string()));
+    }
+}
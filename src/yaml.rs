@@ -0,0 +1,181 @@
+//! YAML config files.
+
+use crate::{check_found_schema_version, expected_schema_version, EnvInventoryError};
+use serde_yaml::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Loads the settings of the `section` mapping of a YAML config file, or of
+/// the top-level mapping if `section` is empty.
+///
+/// Strings, numbers and booleans become their string form, and nulls are
+/// skipped. Nested mappings are flattened by joining keys with `.`, so
+/// `database: {host: x}` provides `database.host`. Sequences are rejected
+/// with `ParseFileError`. A missing section provides no settings, as with
+/// TOML files.
+pub(crate) fn load_yaml_settings<P: AsRef<Path>>(
+    path: P,
+    section: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .map_err(|_| EnvInventoryError::ReadFileError(path.display().to_string()))?;
+    parse_yaml_settings(&content, &path.display().to_string(), section)
+}
+
+/// Parses the settings of YAML `content` read from `source`, as
+/// [`load_yaml_settings`] does for files.
+pub(crate) fn parse_yaml_settings(
+    content: &str,
+    source: &str,
+    section: &str,
+) -> Result<HashMap<String, String>, EnvInventoryError> {
+    let parse_error = || EnvInventoryError::ParseFileError(source.to_string());
+    let value: Value = serde_yaml::from_str(content).map_err(|_| parse_error())?;
+    if !value.is_mapping() {
+        return Err(parse_error());
+    }
+
+    check_found_schema_version(
+        expected_schema_version(),
+        value.get("schema_version").and_then(Value::as_i64),
+    )?;
+
+    let section = if section.is_empty() {
+        Some(&value)
+    } else {
+        let nested = || {
+            section
+                .split('.')
+                .try_fold(&value, |value, key| value.get(key))
+        };
+        value.get(section).or_else(nested)
+    };
+    let mut settings = HashMap::new();
+    match section {
+        Some(section @ Value::Mapping(_)) => {
+            flatten_into("", section, &mut settings).ok_or_else(parse_error)?;
+        }
+        Some(_) => return Err(parse_error()),
+        None => {}
+    }
+    Ok(settings)
+}
+
+/// Flattens `value` into `settings`, returning `None` if it holds a value
+/// that can't be a setting.
+fn flatten_into(prefix: &str, value: &Value, settings: &mut HashMap<String, String>) -> Option<()> {
+    match value {
+        Value::Mapping(mapping) => {
+            for (key, value) in mapping {
+                let key = scalar_string(key)?;
+                let name = if prefix.is_empty() {
+                    key
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                flatten_into(&name, value, settings)?;
+            }
+        }
+        Value::Null => {}
+        Value::Tagged(tagged) => flatten_into(prefix, &tagged.value, settings)?,
+        value => {
+            settings.insert(prefix.to_string(), scalar_string(value)?);
+        }
+    }
+    Some(())
+}
+
+fn scalar_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    crate::register!(
+        YAML_DATABASE_URL = "postgres://localhost/default",
+        YAML_PORT = "80"
+    );
+
+    #[test]
+    fn test_load_yaml_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.yaml");
+        fs::write(
+            &path,
+            concat!(
+                "env:\n",
+                "  DATABASE_URL: postgres://db/app\n",
+                "  PORT: 8080\n",
+                "  DEBUG: false\n",
+                "  UNSET: ~\n",
+                "  pool:\n",
+                "    min: 1\n",
+                "other:\n",
+                "  IGNORED: yes\n",
+            ),
+        )
+        .unwrap();
+
+        let settings = load_yaml_settings(&path, "env").unwrap();
+        assert_eq!(settings["DATABASE_URL"], "postgres://db/app");
+        assert_eq!(settings["PORT"], "8080");
+        assert_eq!(settings["DEBUG"], "false");
+        assert_eq!(settings["pool.min"], "1");
+        assert!(!settings.contains_key("UNSET"));
+        assert!(!settings.contains_key("IGNORED"));
+
+        assert!(load_yaml_settings(&path, "missing").unwrap().is_empty());
+        assert_eq!(
+            load_yaml_settings(&path, "").unwrap()["other.IGNORED"],
+            "yes"
+        );
+
+        for invalid in [
+            "env: [a, b]\n",
+            "env:\n  TAGS: [a, b]\n",
+            "env: {unclosed\n",
+        ] {
+            fs::write(&path, invalid).unwrap();
+            assert!(matches!(
+                load_yaml_settings(&path, "env"),
+                Err(EnvInventoryError::ParseFileError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_load_yaml_file() {
+        let _guard = crate::env_lock();
+        crate::satisfy_required_vars();
+        let dir = tempfile::tempdir().unwrap();
+        let toml = dir.path().join("app.toml");
+        let yaml = dir.path().join("local.yml");
+        env::remove_var("YAML_DATABASE_URL");
+        env::remove_var("YAML_PORT");
+        fs::write(
+            &toml,
+            "[env]\nYAML_DATABASE_URL = \"postgres://toml/app\"\n",
+        )
+        .unwrap();
+        fs::write(&yaml, "env:\n  YAML_PORT: 9090\n").unwrap();
+
+        crate::load_and_validate_env_vars(&[&toml, &yaml], "env").unwrap();
+        assert_eq!(
+            env::var("YAML_DATABASE_URL").unwrap(),
+            "postgres://toml/app"
+        );
+        assert_eq!(env::var("YAML_PORT").unwrap(), "9090");
+        env::remove_var("YAML_DATABASE_URL");
+        env::remove_var("YAML_PORT");
+    }
+}